@@ -0,0 +1,60 @@
+// Fragment shader of `TiledAtlas`, the standard material with the block atlas repeated across merged faces
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+    pbr_types::STANDARD_MATERIAL_FLAGS_UNLIT_BIT,
+}
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(100) var<uniform> tile_size: vec2<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(101) var atlas_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(102) var atlas_sampler: sampler;
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    // Base colour times the vertex colour, the base material has no texture of its own
+    var pbr_input = pbr_input_from_standard_material(in, is_front);
+
+#ifdef VERTEX_UVS_B
+    // `uv` counts tiles across the face and `uv_b` is the corner of the tile in the atlas
+    // Gradients come from the unwrapped coordinates so the seams between repeats don't pick the smallest mip
+    let atlas_uv = in.uv_b + fract(in.uv) * tile_size;
+    pbr_input.material.base_color *= textureSampleGrad(
+        atlas_texture,
+        atlas_sampler,
+        atlas_uv,
+        dpdx(in.uv) * tile_size,
+        dpdy(in.uv) * tile_size,
+    );
+#endif
+
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+#ifdef PREPASS_PIPELINE
+    let out = deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    if (pbr_input.material.flags & STANDARD_MATERIAL_FLAGS_UNLIT_BIT) == 0u {
+        out.color = apply_pbr_lighting(pbr_input);
+    } else {
+        out.color = pbr_input.material.base_color;
+    }
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+
+    return out;
+}
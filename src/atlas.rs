@@ -1,12 +1,33 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use bevy::{
-    asset::{Assets, Handle},
-    image::{Image, TextureAtlasBuilder},
-    math::Rect,
+    asset::{Assets, Handle, RenderAssetUsages},
+    color::Color,
+    image::{Image, ImageFilterMode, ImageSampler, ImageSamplerDescriptor},
+    log::warn,
+    math::{Rect, UVec2, Vec2},
+    platform::collections::HashMap,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
 };
 
-use crate::Identifier;
+use crate::{
+    Identifier,
+    block::{BlockFace, BlockProperties, BlockRenderLayer},
+};
+
+/// Mip levels generated for the atlas, including the full resolution level
+const MIP_LEVELS: u32 = 4;
+/// Texels of each texture's edge repeated around it in the atlas<br>
+/// Wide enough that the smallest mip level doesn't blend neighbouring textures together
+const ATLAS_PADDING: u32 = 1 << (MIP_LEVELS - 1);
+/// Width and height every texture is stored at in the atlas, textures of other sizes are scaled to fit
+const TILE_SIZE: u32 = 16;
+/// A tile and its padding
+const SLOT_SIZE: u32 = TILE_SIZE + 2 * ATLAS_PADDING;
+/// The atlas is a fixed grid of slots so a texture's location never depends on which other textures are registered
+const ATLAS_SLOTS_PER_SIDE: u32 = 16;
+const ATLAS_SIZE: u32 = SLOT_SIZE * ATLAS_SLOTS_PER_SIDE;
+const ERROR_SLOT: u32 = 0;
 
 #[derive(Default, Clone)]
 pub struct AtlasManager {
@@ -14,6 +35,12 @@ pub struct AtlasManager {
     error_texture: Option<Handle<Image>>,
     error_atlas_location: Option<Rect>,
     atlas_texture: Option<Handle<Image>>,
+    /// Highest slot handed out so far, slots above it have never been used
+    last_slot: u32,
+    /// Slots of removed textures, reused before any new slot
+    free_slots: Vec<u32>,
+    /// Identifiers whose atlas locations changed since the atlas was last rebuilt
+    changed: BTreeSet<Identifier>,
 }
 
 impl AtlasManager {
@@ -21,63 +48,199 @@ impl AtlasManager {
         self.error_texture = Some(texture);
     }
 
+    /// Adding an identifier that is already registered replaces its texture but keeps its slot in the atlas
     pub fn add_data(&mut self, identifier: Identifier, texture: Handle<Image>) {
+        if let Some(texture_data) = self.data.get_mut(&identifier) {
+            texture_data.texture = texture;
+            texture_data.animation = None;
+            return;
+        }
+        let slot = self.allocate_slot(&identifier);
+        self.changed.insert(identifier.clone());
         self.data.insert(
             identifier,
             TextureData {
                 texture,
+                slot,
                 atlas_location: None,
+                render_layer: BlockRenderLayer::default(),
+                faces: Default::default(),
+                animation: None,
+                emission: None,
+                sound_group: None,
+                properties: BlockProperties::default(),
             },
         );
     }
 
+    /// Add a texture whose image is a vertical strip of `animation.frames` equally sized frames<br>
+    /// Only the first frame's location is handed to meshes, [`AtlasManager::update_animations`] copies later frames over it
+    pub fn add_animated_data(
+        &mut self,
+        identifier: Identifier,
+        texture: Handle<Image>,
+        animation: TextureAnimation,
+    ) {
+        self.add_data(identifier.clone(), texture);
+        if let Some(texture_data) = self.data.get_mut(&identifier) {
+            texture_data.animation = Some(animation);
+        }
+    }
+
+    /// Use a different texture for one face of an identifier added through [`AtlasManager::add_data`]<br>
+    /// Faces without their own texture use the texture passed to `add_data`
+    pub fn add_face_data(
+        &mut self,
+        identifier: &Identifier,
+        face: BlockFace,
+        texture: Handle<Image>,
+    ) {
+        if !self.data.contains_key(identifier) {
+            return;
+        }
+        let slot = match &self.data[identifier].faces[face as usize] {
+            Some(face_data) => face_data.slot,
+            None => {
+                self.changed.insert(identifier.clone());
+                self.allocate_slot(identifier)
+            }
+        };
+        if let Some(texture_data) = self.data.get_mut(identifier) {
+            texture_data.faces[face as usize] = Some(FaceTextureData {
+                texture,
+                slot,
+                atlas_location: None,
+            });
+        }
+    }
+
+    /// Frees the identifier's slots for textures added later
     pub fn remove_data(&mut self, identifier: &Identifier) {
-        self.data.remove(identifier);
+        let Some(texture_data) = self.data.remove(identifier) else {
+            return;
+        };
+        self.free_slots.extend(texture_data.slot);
+        self.free_slots.extend(
+            texture_data
+                .faces
+                .iter()
+                .flatten()
+                .filter_map(|face| face.slot),
+        );
+        self.changed.insert(identifier.clone());
     }
 
-    /// WARNING: This may invalidate existing chunks
-    pub fn rebuild_atlas(&mut self, textures: &mut Assets<Image>) {
-        let mut texture_atlas_builder = TextureAtlasBuilder::default();
+    /// `None` once every slot in the atlas is in use, the texture is then drawn with the error texture
+    fn allocate_slot(&mut self, identifier: &Identifier) -> Option<u32> {
+        if let Some(slot) = self.free_slots.pop() {
+            return Some(slot);
+        }
+        if self.last_slot + 1 >= ATLAS_SLOTS_PER_SIDE * ATLAS_SLOTS_PER_SIDE {
+            warn!("Block atlas is full, {identifier} will use the error texture");
+            return None;
+        }
+        self.last_slot += 1;
+        Some(self.last_slot)
+    }
 
-        if let Some(error_texture) = &self.error_texture {
-            let id = error_texture.id();
-            texture_atlas_builder.add_texture(Some(id), textures.get(id).unwrap());
+    pub fn set_render_layer(&mut self, identifier: &Identifier, render_layer: BlockRenderLayer) {
+        if let Some(texture_data) = self.data.get_mut(identifier) {
+            texture_data.render_layer = render_layer;
         }
+    }
 
-        for texture_data in self.data.values() {
-            let id = texture_data.texture.id();
-            texture_atlas_builder.add_texture(Some(id), textures.get(id).unwrap());
+    /// Unregistered identifiers are opaque as they are drawn with the error texture
+    pub fn render_layer(&self, identifier: &Identifier) -> BlockRenderLayer {
+        self.data
+            .get(identifier)
+            .map_or(BlockRenderLayer::default(), |texture_data| {
+                texture_data.render_layer
+            })
+    }
+
+    /// Make the identifier's faces glow at `emission` brightness, from above 0 to 1, or `None` to light them normally
+    pub fn set_emission(&mut self, identifier: &Identifier, emission: Option<f32>) {
+        if let Some(texture_data) = self.data.get_mut(identifier) {
+            texture_data.emission = emission.map(|emission| emission.clamp(0., 1.));
+        }
+    }
+
+    pub fn emission(&self, identifier: &Identifier) -> Option<f32> {
+        self.data.get(identifier)?.emission
+    }
+
+    /// Play the sound `sound_group` names when the identifier is placed or removed, `None` for the default click<br>
+    /// The group is the identifier of a sound file in `assets/<namespace>/sounds/`
+    pub fn set_sound_group(&mut self, identifier: &Identifier, sound_group: Option<Identifier>) {
+        if let Some(texture_data) = self.data.get_mut(identifier) {
+            texture_data.sound_group = sound_group;
         }
+    }
+
+    pub fn sound_group(&self, identifier: &Identifier) -> Option<&Identifier> {
+        self.data.get(identifier)?.sound_group.as_ref()
+    }
 
-        let (texture_atlas_layout, _texture_atlas_sources, texture) =
-            texture_atlas_builder.build().unwrap();
+    pub fn set_properties(&mut self, identifier: &Identifier, properties: BlockProperties) {
+        if let Some(texture_data) = self.data.get_mut(identifier) {
+            texture_data.properties = properties;
+        }
+    }
 
-        if self.error_texture.is_some() {
-            self.error_atlas_location = Some(Rect {
-                min: texture_atlas_layout.textures[0].as_rect().min
-                    / texture_atlas_layout.size.as_vec2(),
-                max: texture_atlas_layout.textures[0].as_rect().max
-                    / texture_atlas_layout.size.as_vec2(),
+    /// Unregistered identifiers have the default properties
+    pub fn properties(&self, identifier: &Identifier) -> BlockProperties {
+        self.data
+            .get(identifier)
+            .map_or(BlockProperties::default(), |texture_data| {
+                texture_data.properties
             })
+    }
+
+    /// Write every texture into its slot of the atlas, creating the atlas on the first call<br>
+    /// Textures keep their slot between rebuilds, so only the returned identifiers have changed atlas locations
+    /// and only chunks containing them need their meshes rebuilt
+    pub fn rebuild_atlas(&mut self, textures: &mut Assets<Image>) -> Vec<Identifier> {
+        let mut tiles = Vec::new();
+        if let Some(error_texture) = self.error_texture.as_ref().and_then(|id| textures.get(id)) {
+            tiles.push((ERROR_SLOT, read_tile(error_texture, 0, 1)));
+        }
+        for texture_data in self.data.values() {
+            let frames = texture_data
+                .animation
+                .map_or(1, |animation| animation.frames);
+            if let (Some(slot), Some(texture)) =
+                (texture_data.slot, textures.get(&texture_data.texture))
+            {
+                tiles.push((slot, read_tile(texture, 0, frames)));
+            }
+            for face in texture_data.faces.iter().flatten() {
+                if let (Some(slot), Some(texture)) = (face.slot, textures.get(&face.texture)) {
+                    tiles.push((slot, read_tile(texture, 0, 1)));
+                }
+            }
         }
 
-        for (i, texture_data) in self.data.values_mut().enumerate() {
-            let i = if self.error_texture.is_some() {
-                i + 1
-            } else {
-                i
-            };
+        let atlas_texture = self
+            .atlas_texture
+            .get_or_insert_with(|| textures.add(empty_atlas()))
+            .clone();
+        let atlas = textures
+            .get_mut(&atlas_texture)
+            .expect("Atlas texture removed from assets");
+        for (slot, tile) in tiles {
+            write_tile(atlas, slot, &tile);
+        }
+        generate_mipmaps(atlas);
 
-            // Convert to 0.0 -> 1.0
-            texture_data.atlas_location = Some(Rect {
-                min: texture_atlas_layout.textures[i].as_rect().min
-                    / texture_atlas_layout.size.as_vec2(),
-                max: texture_atlas_layout.textures[i].as_rect().max
-                    / texture_atlas_layout.size.as_vec2(),
-            });
+        self.error_atlas_location = self.error_texture.as_ref().map(|_| slot_rect(ERROR_SLOT));
+        for texture_data in self.data.values_mut() {
+            texture_data.atlas_location = texture_data.slot.map(slot_rect);
+            for face in texture_data.faces.iter_mut().flatten() {
+                face.atlas_location = face.slot.map(slot_rect);
+            }
         }
 
-        self.atlas_texture = Some(textures.add(texture));
+        std::mem::take(&mut self.changed).into_iter().collect()
     }
 
     /// Get UV location of texture in atlas
@@ -93,13 +256,270 @@ impl AtlasManager {
         )
     }
 
+    /// Get UV location of the texture for one face, falling back to the identifier's texture and then the error texture
+    pub fn face_atlas_location_or_error(&self, identifier: &Identifier, face: BlockFace) -> Rect {
+        self.data
+            .get(identifier)
+            .and_then(|texture_data| texture_data.faces[face as usize].as_ref())
+            .and_then(|face| face.atlas_location)
+            .unwrap_or_else(|| self.atlas_location_or_error(identifier))
+    }
+
+    /// Identifiers of all registered textures, sorted
+    pub fn identifiers(&self) -> impl Iterator<Item = &Identifier> {
+        self.data.keys()
+    }
+
     pub fn atlas_texture(&self) -> Option<Handle<Image>> {
         self.atlas_texture.clone()
     }
+
+    /// UV size of every atlas location, the same for all tiles
+    pub fn tile_size() -> Vec2 {
+        slot_rect(ERROR_SLOT).size()
+    }
+
+    /// Copy the current frame of every animated texture into the atlas, `elapsed` being seconds since startup<br>
+    /// `current_frames` holds the frame last copied for each texture so the atlas is only modified when a frame changes
+    pub fn update_animations(
+        &self,
+        elapsed: f32,
+        current_frames: &mut HashMap<Identifier, u32>,
+        textures: &mut Assets<Image>,
+    ) {
+        let Some(atlas_texture) = &self.atlas_texture else {
+            return;
+        };
+        let mut tiles = Vec::new();
+        for (identifier, texture_data) in self.data.iter() {
+            let (Some(animation), Some(slot)) = (texture_data.animation, texture_data.slot) else {
+                continue;
+            };
+            let frame = (elapsed / animation.frame_duration) as u32 % animation.frames;
+            if current_frames.get(identifier) == Some(&frame) {
+                continue;
+            }
+            if let Some(strip) = textures.get(&texture_data.texture) {
+                tiles.push((slot, read_tile(strip, frame, animation.frames)));
+                current_frames.insert(identifier.clone(), frame);
+            }
+        }
+        if tiles.is_empty() {
+            return;
+        }
+
+        let Some(atlas) = textures.get_mut(atlas_texture) else {
+            return;
+        };
+        for (slot, tile) in tiles {
+            write_tile(atlas, slot, &tile);
+        }
+        generate_mipmaps(atlas);
+    }
+}
+
+/// Top left texel of the tile in `slot`, inside its padding
+fn slot_origin(slot: u32) -> UVec2 {
+    UVec2::new(slot % ATLAS_SLOTS_PER_SIDE, slot / ATLAS_SLOTS_PER_SIDE) * SLOT_SIZE + ATLAS_PADDING
+}
+
+/// UV location of the tile in `slot`, inset by half a texel to keep filtering from sampling the padding
+fn slot_rect(slot: u32) -> Rect {
+    let min = slot_origin(slot).as_vec2() + 0.5;
+    let max = min + (TILE_SIZE - 1) as f32;
+    Rect {
+        min: min / ATLAS_SIZE as f32,
+        max: max / ATLAS_SIZE as f32,
+    }
+}
+
+fn empty_atlas() -> Image {
+    let mut atlas = Image::new_fill(
+        Extent3d {
+            width: ATLAS_SIZE,
+            height: ATLAS_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    set_mipmaps(&mut atlas, true);
+    atlas
+}
+
+/// Sample the atlas' mipmaps for distant faces, or only its full resolution for a sharper but shimmering look<br>
+/// Only the sampler of the image changes, so meshes and tasks holding the [`AtlasManager`] are unaffected
+pub fn set_mipmaps(atlas: &mut Image, mipmaps: bool) {
+    atlas.sampler = atlas_sampler(mipmaps);
+}
+
+/// Texels of `frame` out of `frames` stacked top to bottom in `texture`, scaled to [`TILE_SIZE`] and in row order
+fn read_tile(texture: &Image, frame: u32, frames: u32) -> Vec<Color> {
+    let frame_height = texture.height() / frames;
+    (0..TILE_SIZE)
+        .flat_map(|y| (0..TILE_SIZE).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            texture
+                .get_color_at(
+                    x * texture.width() / TILE_SIZE,
+                    frame * frame_height + y * frame_height / TILE_SIZE,
+                )
+                .unwrap_or(Color::NONE)
+        })
+        .collect()
+}
+
+/// Write a tile from [`read_tile`] into `slot`, surrounded by [`ATLAS_PADDING`] texels repeating its edges<br>
+/// Mipmaps need regenerating afterwards
+fn write_tile(atlas: &mut Image, slot: u32, tile: &[Color]) {
+    let corner = slot_origin(slot) - ATLAS_PADDING;
+    for y in 0..SLOT_SIZE {
+        for x in 0..SLOT_SIZE {
+            let tile_x = x.saturating_sub(ATLAS_PADDING).min(TILE_SIZE - 1);
+            let tile_y = y.saturating_sub(ATLAS_PADDING).min(TILE_SIZE - 1);
+            let _ = atlas.set_color_at(
+                corner.x + x,
+                corner.y + y,
+                tile[(tile_y * TILE_SIZE + tile_x) as usize],
+            );
+        }
+    }
+}
+
+/// Replace any existing mip levels of an `Rgba8UnormSrgb` texture with [`MIP_LEVELS`] box filtered levels
+fn generate_mipmaps(texture: &mut Image) {
+    let (mut width, mut height) = (texture.width() as usize, texture.height() as usize);
+    let Some(data) = texture.data.as_mut() else {
+        return;
+    };
+    data.truncate(width * height * 4);
+
+    let mut level_start = 0;
+    for _ in 1..MIP_LEVELS {
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        let mut level = Vec::with_capacity(next_width * next_height * 4);
+        for y in 0..next_height {
+            for x in 0..next_width {
+                // Odd sizes clamp to the last row or column
+                let texels = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+                    let source_x = (x * 2 + dx).min(width - 1);
+                    let source_y = (y * 2 + dy).min(height - 1);
+                    level_start + (source_y * width + source_x) * 4
+                });
+                for channel in 0..4 {
+                    let sum: u32 = texels
+                        .iter()
+                        .map(|&texel| data[texel + channel] as u32)
+                        .sum();
+                    level.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+        level_start = data.len();
+        data.extend_from_slice(&level);
+        (width, height) = (next_width, next_height);
+    }
+    texture.texture_descriptor.mip_level_count = MIP_LEVELS;
+}
+
+fn atlas_sampler(mipmaps: bool) -> ImageSampler {
+    ImageSampler::Descriptor(ImageSamplerDescriptor {
+        mipmap_filter: ImageFilterMode::Linear,
+        lod_max_clamp: if mipmaps { MIP_LEVELS as f32 } else { 0. },
+        ..ImageSamplerDescriptor::nearest()
+    })
+}
+
+/// Frames of an animated texture are stacked top to bottom in its image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureAnimation {
+    pub frames: u32,
+    /// Seconds each frame is shown for
+    pub frame_duration: f32,
 }
 
 #[derive(Clone)]
 struct TextureData {
     texture: Handle<Image>,
+    /// `None` if the atlas was full when the texture was added
+    slot: Option<u32>,
+    atlas_location: Option<Rect>,
+    render_layer: BlockRenderLayer,
+    /// Textures overriding `texture` for individual faces, indexed by [`BlockFace`]
+    faces: [Option<FaceTextureData>; 6],
+    animation: Option<TextureAnimation>,
+    /// See [`AtlasManager::set_emission`]
+    emission: Option<f32>,
+    /// See [`AtlasManager::set_sound_group`]
+    sound_group: Option<Identifier>,
+    properties: BlockProperties,
+}
+
+#[derive(Clone)]
+struct FaceTextureData {
+    texture: Handle<Image>,
+    slot: Option<u32>,
     atlas_location: Option<Rect>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_NAMESPACE;
+
+    fn texture(textures: &mut Assets<Image>, color: [u8; 4]) -> Handle<Image> {
+        textures.add(Image::new_fill(
+            Extent3d {
+                width: TILE_SIZE,
+                height: TILE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &color,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        ))
+    }
+
+    fn identifier(path: &str) -> Identifier {
+        Identifier::new(DEFAULT_NAMESPACE, path)
+    }
+
+    #[test]
+    fn rebuild_reports_moved_textures_only() {
+        let mut textures = Assets::<Image>::default();
+        let mut atlas_manager = AtlasManager::default();
+        atlas_manager.set_error_texture(texture(&mut textures, [255, 0, 255, 255]));
+        for path in ["stone", "dirt"] {
+            let handle = texture(&mut textures, [128; 4]);
+            atlas_manager.add_data(identifier(path), handle);
+        }
+        assert_eq!(
+            atlas_manager.rebuild_atlas(&mut textures),
+            vec![identifier("dirt"), identifier("stone")]
+        );
+        let stone = atlas_manager.atlas_location(&identifier("stone"));
+
+        // Nothing moved since the last rebuild
+        assert!(atlas_manager.rebuild_atlas(&mut textures).is_empty());
+        // A new texture for an existing identifier is drawn where the old one was
+        let handle = texture(&mut textures, [64; 4]);
+        atlas_manager.add_data(identifier("stone"), handle);
+        assert!(atlas_manager.rebuild_atlas(&mut textures).is_empty());
+        assert_eq!(atlas_manager.atlas_location(&identifier("stone")), stone);
+
+        // A texture taking the slot of a removed one changes what is drawn there
+        let dirt = atlas_manager.atlas_location(&identifier("dirt"));
+        atlas_manager.remove_data(&identifier("dirt"));
+        let handle = texture(&mut textures, [32; 4]);
+        atlas_manager.add_data(identifier("sand"), handle);
+        assert_eq!(
+            atlas_manager.rebuild_atlas(&mut textures),
+            vec![identifier("dirt"), identifier("sand")]
+        );
+        assert_eq!(atlas_manager.atlas_location(&identifier("sand")), dirt);
+        assert_eq!(atlas_manager.atlas_location(&identifier("stone")), stone);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    hint::black_box,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::{Assets, Handle},
+    math::IVec3,
+    render::mesh::Mesh,
+};
+use noiz::{Noise, prelude::common_noise::Perlin, rng::NoiseRng};
+
+use crate::{
+    DEFAULT_NAMESPACE, Identifier,
+    atlas::AtlasManager,
+    chunk::{
+        self, Chunk,
+        generation::{CaveProperties, TerrainProfile},
+        light::LightVolume,
+        mesh::MeshStrategy,
+        storage::SaveFormat,
+    },
+};
+
+/// Times each case is meshed, encoded and decoded, the average is reported
+const ITERATIONS: u32 = 100;
+
+/// Time meshing a worst case, best case and typical chunk with every [`MeshStrategy`] and print the results,
+/// followed by the size and speed of saving them in every [`SaveFormat`]<br>
+/// Run with `cargo run --release --features mesh-benchmark`
+pub fn run() {
+    let atlas_manager = Arc::new(atlas_manager());
+    let stone = Identifier::new(DEFAULT_NAMESPACE, "stone");
+    let cases = [
+        (
+            "checkerboard",
+            Chunk::filled_checkerboard(IVec3::ZERO, &stone),
+        ),
+        ("solid", Chunk::filled(IVec3::ZERO, &stone)),
+        ("terrain", terrain_chunk()),
+    ];
+
+    for (name, chunk) in cases {
+        save_formats(name, &chunk);
+        let chunk = Arc::new(RwLock::new(chunk));
+        let neighbors = [const { None }; 26];
+        let Some(light) =
+            LightVolume::propagate(&Arc::downgrade(&chunk), &neighbors, &atlas_manager)
+        else {
+            continue;
+        };
+        for (strategy_name, strategy, ambient_occlusion) in [
+            ("per face", MeshStrategy::PerFace, false),
+            ("per face + ao", MeshStrategy::PerFace, true),
+            ("greedy", MeshStrategy::Greedy, false),
+        ] {
+            let mut vertices = 0;
+            let mut total = Duration::ZERO;
+            for _ in 0..ITERATIONS {
+                let start = Instant::now();
+                let meshes = strategy.build(
+                    Arc::downgrade(&chunk),
+                    neighbors.clone(),
+                    Arc::downgrade(&atlas_manager),
+                    &light,
+                    ambient_occlusion,
+                );
+                total += start.elapsed();
+                vertices = black_box(meshes).map_or(0, |meshes| {
+                    [meshes.opaque, meshes.transparent, meshes.emissive]
+                        .iter()
+                        .flatten()
+                        .map(Mesh::count_vertices)
+                        .sum::<usize>()
+                });
+            }
+            println!(
+                "{name:>12} {strategy_name:<14} {:>10.3?} per chunk, {vertices} vertices",
+                total / ITERATIONS
+            );
+        }
+    }
+}
+
+/// Print the stored size of `chunk` in every [`SaveFormat`], before and after compression, and how long encoding and decoding it takes
+fn save_formats(name: &str, chunk: &Chunk) {
+    for format in SaveFormat::ALL {
+        let (Ok(serialized), Ok(entry)) = (format.serialize(chunk), format.encode_entry(chunk))
+        else {
+            continue;
+        };
+        let mut encode = Duration::ZERO;
+        let mut decode = Duration::ZERO;
+        for _ in 0..ITERATIONS {
+            let start = Instant::now();
+            let _ = black_box(format.encode_entry(chunk));
+            encode += start.elapsed();
+            let start = Instant::now();
+            let _ = black_box(SaveFormat::decode_entry(&entry));
+            decode += start.elapsed();
+        }
+        println!(
+            "{name:>12} {:<14} {:>10.3?} encode, {:>10.3?} decode, {} bytes ({} uncompressed)",
+            format!("{format:?}"),
+            encode / ITERATIONS,
+            decode / ITERATIONS,
+            entry.len(),
+            serialized.len()
+        );
+    }
+}
+
+/// Stone, dirt and grass registered without textures, meshing only needs their atlas locations
+fn atlas_manager() -> AtlasManager {
+    let mut atlas_manager = AtlasManager::default();
+    atlas_manager.set_error_texture(Handle::default());
+    for path in ["stone", "dirt", "grass"] {
+        atlas_manager.add_data(Identifier::new(DEFAULT_NAMESPACE, path), Handle::default());
+    }
+    atlas_manager.rebuild_atlas(&mut Assets::default());
+    atlas_manager
+}
+
+/// Chunk at the surface of a seed 0 level
+fn terrain_chunk() -> Chunk {
+    let caves = CaveProperties::default();
+    let noise = Noise::<Perlin> {
+        seed: NoiseRng(0),
+        frequency: 1. / chunk::SIZE_F32,
+        ..Default::default()
+    };
+    let cave_noise = Noise::<Perlin> {
+        seed: NoiseRng(1),
+        frequency: caves.frequency,
+        ..Default::default()
+    };
+    Chunk::generate(
+        IVec3::ZERO,
+        &noise,
+        &cave_noise,
+        caves.threshold,
+        &TerrainProfile::default(),
+    )
+}
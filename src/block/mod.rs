@@ -1,24 +1,162 @@
-use std::sync::Arc;
+use std::{fs, io, path::Path, sync::Arc};
 
-use bevy::{asset::Handle, ecs::resource::Resource, image::Image, math::Vec3};
+use bevy::{
+    asset::Handle,
+    ecs::resource::Resource,
+    image::Image,
+    math::{IVec3, Vec3},
+};
 use bevy_asset_loader::asset_collection::AssetCollection;
 use serde::{Deserialize, Serialize};
 
-use crate::{Identifier, atlas::AtlasManager};
+use crate::{
+    DEFAULT_NAMESPACE, Identifier,
+    atlas::AtlasManager,
+    chunk::{Chunk, ChunkGrid, read_chunk},
+};
 
+/// Block textures themselves are found by [`find_block_textures`]
 #[derive(AssetCollection, Resource)]
 pub struct BlockAssets {
     #[asset(path = "Error.png")]
     pub error: Handle<Image>,
-    #[asset(path = "Stone.png")]
-    pub stone: Handle<Image>,
-    #[asset(path = "Dirt.png")]
-    pub dirt: Handle<Image>,
+}
+
+/// Folder inside each namespace's asset folder holding its block textures
+pub const BLOCK_TEXTURE_FOLDER: &str = "blocks";
+
+/// A block texture file found by [`find_block_textures`]
+pub struct BlockTextureFile {
+    pub identifier: Identifier,
+    /// Faces the file overrides the block's texture for, empty for the block's own texture
+    pub faces: &'static [BlockFace],
+    /// Relative to the assets folder, for loading through the asset server
+    pub asset_path: String,
+}
+
+/// Scan `assets/<namespace>/blocks/*.png`, each file's stem becoming the path of an identifier in `<namespace>`<br>
+/// A stem ending in `_top`, `_bottom` or `_side` overrides those faces of the block before the suffix, if that block has a file of its own
+pub fn find_block_textures(assets_directory: &Path) -> io::Result<Vec<BlockTextureFile>> {
+    let mut stems = Vec::new();
+    for namespace_entry in fs::read_dir(assets_directory)? {
+        let namespace_entry = namespace_entry?;
+        let Some(namespace) = namespace_entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        let blocks_directory = namespace_entry.path().join(BLOCK_TEXTURE_FOLDER);
+        if !blocks_directory.is_dir() {
+            continue;
+        }
+        for block_entry in fs::read_dir(blocks_directory)? {
+            let path = block_entry?.path();
+            if path.extension().is_none_or(|extension| extension != "png") {
+                continue;
+            }
+            if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                stems.push((namespace.clone(), stem.to_owned()));
+            }
+        }
+    }
+    // Directory order isn't guaranteed, keep registration the same between runs
+    stems.sort();
+
+    let files = stems
+        .iter()
+        .map(|(namespace, stem)| {
+            let asset_path = format!("{namespace}/{BLOCK_TEXTURE_FOLDER}/{stem}.png");
+            let face_override = [
+                ("_top", &[BlockFace::Top][..]),
+                ("_bottom", &[BlockFace::Bottom][..]),
+                ("_side", &BlockFace::SIDES[..]),
+            ]
+            .into_iter()
+            .find_map(|(suffix, faces)| {
+                let block = stem.strip_suffix(suffix)?;
+                stems
+                    .contains(&(namespace.clone(), block.to_owned()))
+                    .then_some((block, faces))
+            });
+            let (path, faces) = face_override.unwrap_or((stem.as_str(), &[]));
+            BlockTextureFile {
+                identifier: Identifier::new(namespace, path),
+                faces,
+                asset_path,
+            }
+        })
+        .collect();
+    Ok(files)
 }
 
 #[derive(Default, Resource)]
 pub struct BlockAtlasManager(pub Arc<AtlasManager>);
 
+/// Side of a block, in the same order as [`NEIGHBOR_OFFSETS`](crate::chunk::NEIGHBOR_OFFSETS)<br>
+/// North is towards -Z and east towards +X
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFace {
+    East,
+    West,
+    Top,
+    Bottom,
+    South,
+    North,
+}
+
+impl BlockFace {
+    pub const ALL: [Self; 6] = [
+        Self::East,
+        Self::West,
+        Self::Top,
+        Self::Bottom,
+        Self::South,
+        Self::North,
+    ];
+    /// Every face other than the top and bottom
+    pub const SIDES: [Self; 4] = [Self::East, Self::West, Self::South, Self::North];
+}
+
+/// Which chunk mesh a block's faces are drawn in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlockRenderLayer {
+    #[default]
+    Opaque,
+    /// Alpha blended, for blocks such as glass or water
+    Transparent,
+}
+
+/// Path of the air block in [`DEFAULT_NAMESPACE`]<br>
+/// Empty space is stored as `None`, which is the canonical form. Air blocks are turned into `None` by
+/// [`Block::non_air`] wherever blocks enter a chunk, so everything reading chunks only has to check for `None`
+pub const AIR: &str = "air";
+
+/// How a block behaves, registered per identifier with [`AtlasManager::set_properties`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockProperties {
+    /// Collides with the player
+    pub solid: bool,
+    /// Hides the faces of blocks next to it and stops light
+    pub opaque: bool,
+    /// Placing a block into it replaces it instead of being blocked by it
+    pub replaceable: bool,
+    /// Drawn as a flat surface, its top face only being drawn below an empty block
+    pub fluid: bool,
+    /// How hard the block is to break, 0 breaking instantly
+    pub hardness: f32,
+}
+
+/// Unregistered blocks are solid and opaque, like the error texture they are drawn with
+impl Default for BlockProperties {
+    fn default() -> Self {
+        Self {
+            solid: true,
+            opaque: true,
+            replaceable: false,
+            fluid: false,
+            hardness: 1.,
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Block {
     pub identifier: Identifier,
@@ -28,14 +166,45 @@ impl Block {
     pub fn new(identifier: Identifier) -> Self {
         Self { identifier }
     }
+
+    pub fn is_air(&self) -> bool {
+        self.identifier.0 == DEFAULT_NAMESPACE && self.identifier.1 == AIR
+    }
+
+    /// Turn an air block into `None`, see [`AIR`]
+    pub fn non_air(block: Option<Block>) -> Option<Block> {
+        block.filter(|block| !block.is_air())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    /// World position of the block that was hit
+    pub block_coordinates: IVec3,
+    /// Position of the chunk containing the block that was hit
+    pub chunk_coordinates: IVec3,
+    /// Normal of the face the ray entered the block through, zero if the ray started inside the block
+    pub normal: IVec3,
+    /// Distance along the ray to where it entered the block
+    pub distance: f32,
 }
 
+/// Voxel visited by a [`BlockRay`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RayStep {
+    pub position: IVec3,
+    /// Normal of the face the ray entered this voxel through, zero for the voxel the ray started in
+    pub normal: IVec3,
+}
+
+#[derive(Clone)]
 pub struct BlockRay {
     pub position: Vec3,
     step: Vec3,
     delta: Vec3,
     bound: Vec3,
     pub normal: Vec3,
+    distance: f32,
 }
 
 impl BlockRay {
@@ -71,7 +240,50 @@ impl BlockRay {
             delta,
             bound,
             normal: Vec3::ZERO,
+            distance: 0.,
+        }
+    }
+
+    /// Distance travelled along the ray to reach the current position
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// Step along the ray until it enters a solid block, passing through non-solid blocks and fluids<br>
+    /// Returns `None` if the ray travels further than `max_distance` or reaches a chunk which isn't loaded<br>
+    /// Only a single read lock is taken per chunk the ray passes through
+    pub fn cast(
+        mut self,
+        chunk_grid: &ChunkGrid,
+        atlas_manager: &AtlasManager,
+        max_distance: f32,
+    ) -> Option<RaycastHit> {
+        let is_target = |block: &Block| {
+            let properties = atlas_manager.properties(&block.identifier);
+            properties.solid && !properties.fluid
+        };
+        while self.distance <= max_distance {
+            let chunk_position = ChunkGrid::to_chunk_coordinates(self.position);
+            let chunk = read_chunk(chunk_grid.0.get(&chunk_position)?);
+            while self.distance <= max_distance
+                && ChunkGrid::to_chunk_coordinates(self.position) == chunk_position
+            {
+                let block_coordinates = self.position.floor().as_ivec3();
+                if chunk.contents[Chunk::to_index(Chunk::to_block_coordinates(block_coordinates))]
+                    .as_ref()
+                    .is_some_and(is_target)
+                {
+                    return Some(RaycastHit {
+                        block_coordinates,
+                        chunk_coordinates: chunk_position,
+                        normal: self.normal.as_ivec3(),
+                        distance: self.distance,
+                    });
+                }
+                self.step();
+            }
         }
+        None
     }
 
     fn max(x: f32, fx: f32, s: f32, d: f32) -> f32 {
@@ -82,18 +294,116 @@ impl BlockRay {
     pub fn step(&mut self) {
         if self.bound.x < self.bound.y && self.bound.x < self.bound.z {
             self.position.x += self.step.x;
+            self.distance = self.bound.x;
             self.bound.x += self.delta.x;
             self.normal = Vec3::X * -self.step;
             return;
         }
         if self.bound.y < self.bound.z {
             self.position.y += self.step.y;
+            self.distance = self.bound.y;
             self.bound.y += self.delta.y;
             self.normal = Vec3::Y * -self.step;
             return;
         }
         self.position.z += self.step.z;
+        self.distance = self.bound.z;
         self.bound.z += self.delta.z;
         self.normal = Vec3::Z * -self.step;
     }
 }
+
+/// Never ends, yields the current voxel and then steps so the first item is the voxel containing the origin
+impl Iterator for BlockRay {
+    type Item = RayStep;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let step = RayStep {
+            position: self.position.as_ivec3(),
+            normal: self.normal.as_ivec3(),
+        };
+        self.step();
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::RwLock;
+
+    use super::*;
+    use crate::chunk::mesh::tests::{atlas_manager, identifier};
+
+    fn step(position: [i32; 3], normal: [i32; 3]) -> RayStep {
+        RayStep {
+            position: IVec3::from_array(position),
+            normal: IVec3::from_array(normal),
+        }
+    }
+
+    #[test]
+    fn ray_follows_the_dda_path() {
+        // Crosses x = 1, 2, 3 at t = 0.5, 1.5, 2.5 and y = 1, 2 at t = 1, 3, in units of the x distance
+        let ray = BlockRay::from_origin_in_direction(Vec3::splat(0.5), Vec3::new(1., 0.5, 0.));
+        assert_eq!(
+            ray.take(6).collect::<Vec<RayStep>>(),
+            vec![
+                step([0, 0, 0], [0, 0, 0]),
+                step([1, 0, 0], [-1, 0, 0]),
+                step([1, 1, 0], [0, -1, 0]),
+                step([2, 1, 0], [-1, 0, 0]),
+                step([3, 1, 0], [-1, 0, 0]),
+                step([3, 2, 0], [0, -1, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn ray_faces_back_along_negative_directions() {
+        let ray = BlockRay::from_origin_in_direction(Vec3::new(0.5, 0.5, 0.25), Vec3::NEG_Z);
+        assert_eq!(
+            ray.take(3).collect::<Vec<RayStep>>(),
+            vec![
+                step([0, 0, 0], [0, 0, 0]),
+                step([0, 0, -1], [0, 0, 1]),
+                step([0, 0, -2], [0, 0, 1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn cast_passes_through_fluids_and_non_solid_blocks() {
+        let atlas_manager = atlas_manager();
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        let mut next = Chunk::new(IVec3::X);
+        for (x, path) in [(3, "water"), (5, "glass"), (20, "water")] {
+            chunk.contents[Chunk::to_index(Chunk::to_block_coordinates(IVec3::new(x, 1, 1)))] =
+                Some(Block::new(identifier(path)));
+        }
+        next.contents[Chunk::to_index(Chunk::to_block_coordinates(IVec3::new(40, 1, 1)))] =
+            Some(Block::new(identifier("stone")));
+        let mut chunk_grid = ChunkGrid::default();
+        for chunk in [chunk, next] {
+            chunk_grid
+                .0
+                .insert(chunk.position, Arc::new(RwLock::new(chunk)));
+        }
+
+        let ray = BlockRay::from_origin_in_direction(Vec3::new(0.5, 1.5, 1.5), Vec3::X);
+        // Glass is solid, unlike water
+        let hit = ray.clone().cast(&chunk_grid, &atlas_manager, 64.).unwrap();
+        assert_eq!(hit.block_coordinates, IVec3::new(5, 1, 1));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert_eq!(hit.chunk_coordinates, IVec3::ZERO);
+
+        let ray = BlockRay::from_origin_in_direction(Vec3::new(6.5, 1.5, 1.5), Vec3::X);
+        let hit = ray.clone().cast(&chunk_grid, &atlas_manager, 64.).unwrap();
+        assert_eq!(hit.block_coordinates, IVec3::new(40, 1, 1));
+        assert_eq!(hit.chunk_coordinates, IVec3::X);
+        assert!(ray.clone().cast(&chunk_grid, &atlas_manager, 20.).is_none());
+
+        // Runs into the unloaded chunk behind the water
+        let ray = BlockRay::from_origin_in_direction(Vec3::new(0.5, 1.5, 1.5), Vec3::NEG_X);
+        assert!(ray.cast(&chunk_grid, &atlas_manager, 64.).is_none());
+    }
+}
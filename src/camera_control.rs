@@ -1,25 +1,148 @@
-use std::f32::consts::FRAC_PI_2;
+use std::{f32::consts::FRAC_PI_2, fmt};
 
 use bevy::{
-    input::{ButtonInput, keyboard::KeyCode, mouse::AccumulatedMouseMotion},
-    math::{EulerRot, Quat, Vec2},
+    input::mouse::AccumulatedMouseMotion,
+    math::{EulerRot, IVec3, Quat, Vec2, Vec3},
     prelude::*,
     time::Time,
     transform::components::Transform,
 };
 
+use crate::{
+    GameState,
+    atlas::AtlasManager,
+    block::BlockAtlasManager,
+    chunk::ChunkGrid,
+    input::{Action, ActionInput},
+    level::{Level, Levels},
+};
+
 #[derive(Component)]
-#[require(Camera3d)]
+#[require(Camera3d, MovementMode)]
 pub struct MovableCamera {
     pub speed: f32,
     pub sensitivity: f32,
+    /// Factor `speed` is multiplied by while Left Control is held
+    pub sprint_multiplier: f32,
+    /// Change in velocity per second while a movement key is held, 0 reaching full speed instantly
+    pub acceleration: f32,
+    /// Exponential rate the velocity decays at once no movement key is held, 10 losing about 63% every tenth of a second<br>
+    /// 0 stops instantly
+    pub damping: f32,
+    /// World space velocity the camera moves at, or walks at horizontally in [`MovementMode::Walk`]
+    pub velocity: Vec3,
+}
+
+impl Default for MovableCamera {
+    fn default() -> Self {
+        Self {
+            speed: 15.,
+            sensitivity: 0.002,
+            sprint_multiplier: 2.,
+            acceleration: 60.,
+            damping: 10.,
+            velocity: Vec3::ZERO,
+        }
+    }
+}
+
+impl MovableCamera {
+    /// Ease `velocity` towards `target`, or towards stopping when `target` is zero<br>
+    /// Never overshoots, so the velocity is never longer than the longest of itself and `target`
+    fn approach(&mut self, target: Vec3, delta: f32) {
+        self.velocity = if target != Vec3::ZERO {
+            if self.acceleration <= 0. {
+                target
+            } else {
+                self.velocity
+                    .move_towards(target, self.acceleration * delta)
+            }
+        } else if self.damping <= 0. {
+            Vec3::ZERO
+        } else {
+            self.velocity * (-self.damping * delta).exp()
+        };
+        // Damping only approaches zero, stop before the camera drifts imperceptibly forever
+        if self.velocity.length_squared() < MIN_SPEED * MIN_SPEED {
+            self.velocity = Vec3::ZERO;
+        }
+    }
+}
+
+/// Gravity and collision against blocks for a [`MovableCamera`] in [`MovementMode::Walk`]<br>
+/// The camera then walks by setting `intent` instead of moving its translation directly
+#[derive(Component)]
+pub struct PlayerPhysics {
+    /// Downwards acceleration in blocks per second squared
+    pub gravity: f32,
+    pub velocity: Vec3,
+    /// Half the size of the collision box on each axis
+    pub half_extents: Vec3,
+    /// Height of the camera above the bottom of the collision box
+    pub eye_height: f32,
+    /// Upwards velocity set when jumping
+    pub jump_speed: f32,
+    /// Horizontal velocity the player is trying to move at
+    pub intent: Vec3,
+    /// Jump the next time the player is on the ground
+    pub jump: bool,
+    pub on_ground: bool,
 }
 
+impl Default for PlayerPhysics {
+    fn default() -> Self {
+        Self {
+            gravity: 28.,
+            velocity: Vec3::ZERO,
+            half_extents: Vec3::new(0.3, 0.9, 0.3),
+            eye_height: 1.62,
+            jump_speed: 8.5,
+            intent: Vec3::ZERO,
+            jump: false,
+            on_ground: false,
+        }
+    }
+}
+
+/// How a [`MovableCamera`] moves, toggled with G
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MovementMode {
+    /// Free movement through blocks, Space and Shift move up and down
+    Fly,
+    /// Gravity and collision from [`PlayerPhysics`], Space jumps
+    #[default]
+    Walk,
+}
+
+impl fmt::Display for MovementMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovementMode::Fly => write!(f, "Fly"),
+            MovementMode::Walk => write!(f, "Walk"),
+        }
+    }
+}
+
+/// Mouse movement in pixels per second that fully tilting the look stick turns the camera like
+const GAMEPAD_LOOK_SPEED: f32 = 1500.;
+/// Slowest speed the camera keeps moving at after damping, in blocks per second
+const MIN_SPEED: f32 = 0.01;
+/// Fastest the player can fall, also keeps each frame's movement small enough to collide with every block passed
+const TERMINAL_VELOCITY: f32 = 60.;
+/// Largest distance moved along an axis before checking for collisions again, less than a block so none are skipped
+const MAX_STEP: f32 = 0.45;
+/// Gap kept between the collision box and blocks so a box resting exactly on a block boundary doesn't overlap it
+const SKIN: f32 = 0.001;
+
 pub struct CameraMovementPlugin;
 
 impl Plugin for CameraMovementPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, control_camera);
+        app.add_systems(
+            Update,
+            (control_camera, apply_player_physics.after(control_camera))
+                .run_if(in_state(GameState::InGame)),
+        );
     }
 }
 
@@ -39,40 +162,195 @@ fn axis(a: bool, b: bool) -> f32 {
 fn control_camera(
     time: Res<Time>,
     mouse_motion: Res<AccumulatedMouseMotion>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    camera_query: Single<(&mut Transform, &MovableCamera)>,
+    input: ActionInput,
+    camera_query: Single<(
+        &mut Transform,
+        &mut MovableCamera,
+        &mut MovementMode,
+        Option<&mut PlayerPhysics>,
+    )>,
 ) {
-    let (mut transform, movable_camera) = camera_query.into_inner();
-
-    let forward = transform.forward().normalize();
-    let left = transform.left().normalize();
-    let up = transform.up().normalize();
-    transform.translation += (forward
-        * axis(
-            keyboard_input.pressed(KeyCode::KeyW),
-            keyboard_input.pressed(KeyCode::KeyS),
-        )
-        + left
-            * axis(
-                keyboard_input.pressed(KeyCode::KeyA),
-                keyboard_input.pressed(KeyCode::KeyD),
-            )
-        + up * axis(
-            keyboard_input.pressed(KeyCode::Space),
-            keyboard_input.pressed(KeyCode::ShiftLeft),
-        ))
-        * movable_camera.speed
-        * time.delta_secs();
-
-    if mouse_motion.delta == Vec2::ZERO {
+    let (mut transform, mut movable_camera, mut mode, physics) = camera_query.into_inner();
+
+    if input.just_pressed(Action::ToggleMovementMode) {
+        *mode = match *mode {
+            MovementMode::Fly => MovementMode::Walk,
+            MovementMode::Walk => MovementMode::Fly,
+        };
+    }
+
+    let speed = if input.pressed(Action::Sprint) {
+        movable_camera.speed * movable_camera.sprint_multiplier
+    } else {
+        movable_camera.speed
+    };
+    let stick = input.left_stick();
+    let forward_axis = axis(
+        input.pressed(Action::MoveForward),
+        input.pressed(Action::MoveBackward),
+    ) + stick.y;
+    let left_axis = axis(
+        input.pressed(Action::MoveLeft),
+        input.pressed(Action::MoveRight),
+    ) - stick.x;
+    match (*mode, physics) {
+        (MovementMode::Walk, Some(mut physics)) => {
+            // Walking stays level whichever way the camera is pitched
+            let forward = transform.forward().with_y(0.).normalize_or_zero();
+            let left = transform.left().with_y(0.).normalize_or_zero();
+            // Clamped so moving diagonally isn't faster, while a partly tilted stick still moves slower
+            let target = (forward * forward_axis + left * left_axis).clamp_length_max(1.) * speed;
+            movable_camera.approach(target, time.delta_secs());
+            physics.intent = movable_camera.velocity.with_y(0.);
+            physics.jump = input.pressed(Action::MoveUp);
+        }
+        (_, physics) => {
+            let forward = transform.forward().normalize();
+            let left = transform.left().normalize();
+            let up = transform.up().normalize();
+            let direction = forward * forward_axis
+                + left * left_axis
+                + up * axis(
+                    input.pressed(Action::MoveUp),
+                    input.pressed(Action::MoveDown),
+                );
+            let target = direction.clamp_length_max(1.) * speed;
+            movable_camera.approach(target, time.delta_secs());
+            let velocity = movable_camera.velocity;
+            transform.translation += velocity * time.delta_secs();
+            // Carried over when switching to walking so the camera doesn't stop dead mid-air
+            if let Some(mut physics) = physics {
+                physics.velocity = velocity;
+                physics.on_ground = false;
+            }
+        }
+    }
+
+    // The look stick is turned into the mouse movement that would turn the camera as far, +Y being down like the mouse
+    let look = mouse_motion.delta
+        + input.right_stick() * Vec2::new(1., -1.) * GAMEPAD_LOOK_SPEED * time.delta_secs();
+    if look == Vec2::ZERO {
         return;
     }
     let (mut yaw, mut pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
-    yaw += -mouse_motion.delta.x * movable_camera.sensitivity;
+    yaw += -look.x * movable_camera.sensitivity;
 
     const PITCH_MAX: f32 = FRAC_PI_2 - 0.01;
-    pitch =
-        (pitch - mouse_motion.delta.y * movable_camera.sensitivity).clamp(-PITCH_MAX, PITCH_MAX);
+    pitch = (pitch - look.y * movable_camera.sensitivity).clamp(-PITCH_MAX, PITCH_MAX);
 
     transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll);
 }
+
+/// Whether the block at world coordinates `world` collides with the player, `None` if its chunk isn't loaded
+fn is_solid(level: &Level, atlas_manager: &AtlasManager, world: IVec3) -> Option<bool> {
+    let chunk_position = ChunkGrid::to_chunk_coordinates(world.as_vec3());
+    if !level.get_chunk_grid().0.contains_key(&chunk_position) {
+        return None;
+    }
+    Some(
+        level
+            .get_block(world)
+            .is_some_and(|block| atlas_manager.properties(&block.identifier).solid),
+    )
+}
+
+/// Whether a box from `min` to `max` overlaps a solid block, `None` if it overlaps a chunk that isn't loaded
+fn box_collides(level: &Level, atlas_manager: &AtlasManager, min: Vec3, max: Vec3) -> Option<bool> {
+    // Shrunk by the skin so faces exactly on a block boundary don't count as overlapping
+    let min = (min + SKIN).floor().as_ivec3();
+    let max = (max - SKIN).floor().as_ivec3();
+    let mut collides = false;
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                collides |= is_solid(level, atlas_manager, IVec3::new(x, y, z))?;
+            }
+        }
+    }
+    Some(collides)
+}
+
+/// Moves the camera by its velocity one axis at a time, stopping against solid blocks<br>
+/// Nothing moves while the player's surroundings are in chunks that aren't loaded so they can't fall into ungenerated terrain
+fn apply_player_physics(
+    time: Res<Time>,
+    levels: Option<Res<Levels>>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    camera_query: Single<(&mut Transform, &mut PlayerPhysics, &MovementMode)>,
+) {
+    let Some(levels) = levels else {
+        return;
+    };
+    let level = levels.active();
+    let (mut transform, mut physics, mode) = camera_query.into_inner();
+    if *mode != MovementMode::Walk {
+        return;
+    }
+    let delta = time.delta_secs();
+
+    let feet_offset = Vec3::Y * physics.eye_height;
+    let box_min =
+        |eye: Vec3, physics: &PlayerPhysics| eye - feet_offset - physics.half_extents.with_y(0.);
+    let box_max = |eye: Vec3, physics: &PlayerPhysics| {
+        eye - feet_offset + physics.half_extents.with_y(physics.half_extents.y * 2.)
+    };
+
+    let mut eye = transform.translation;
+    let atlas_manager = &block_atlas_manager.0;
+    match box_collides(
+        &level,
+        atlas_manager,
+        box_min(eye, &physics),
+        box_max(eye, &physics),
+    ) {
+        None => {
+            physics.velocity = Vec3::ZERO;
+            return;
+        }
+        // Climb out of blocks placed inside the player or terrain the player was spawned in
+        Some(true) => {
+            transform.translation.y += 1.;
+            physics.velocity = Vec3::ZERO;
+            return;
+        }
+        Some(false) => {}
+    }
+
+    if physics.jump && physics.on_ground {
+        physics.velocity.y = physics.jump_speed;
+    }
+    physics.velocity.y = (physics.velocity.y - physics.gravity * delta).max(-TERMINAL_VELOCITY);
+    physics.velocity.x = physics.intent.x;
+    physics.velocity.z = physics.intent.z;
+    physics.on_ground = false;
+
+    // Vertical first so walking off an edge and landing resolve before sliding along walls
+    for axis in [1, 0, 2] {
+        let mut remaining = physics.velocity[axis] * delta;
+        while remaining != 0. {
+            let step = remaining.clamp(-MAX_STEP, MAX_STEP);
+            remaining -= step;
+            let mut next = eye;
+            next[axis] += step;
+            let (min, max) = (box_min(next, &physics), box_max(next, &physics));
+            match box_collides(&level, atlas_manager, min, max) {
+                Some(false) => eye = next,
+                // Treat unloaded chunks like walls
+                _ => {
+                    // Rest against the face of the block that was hit
+                    if step > 0. {
+                        eye[axis] += (max[axis] - SKIN).floor() - SKIN - (max[axis] - step);
+                    } else {
+                        eye[axis] += (min[axis] + SKIN).floor() + 1. + SKIN - (min[axis] - step);
+                        if axis == 1 {
+                            physics.on_ground = true;
+                        }
+                    }
+                    physics.velocity[axis] = 0.;
+                    break;
+                }
+            }
+        }
+    }
+    transform.translation = eye;
+}
@@ -0,0 +1,570 @@
+use bevy::math::{I16Vec3, IVec2, IVec3, Vec3Swizzles};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    DEFAULT_NAMESPACE, Identifier,
+    block::Block,
+    chunk::{Chunk, NEIGHBOR_OFFSETS, NEIGHBORHOOD_OFFSETS, SIZE_I32},
+};
+
+/// Number of showcase blocks placed along the x axis before wrapping onto the next row
+pub const SHOWCASE_ROW_LENGTH: i32 = 8;
+/// Distance between showcase blocks so every face of each block is visible
+pub const SHOWCASE_SPACING: i32 = 2;
+
+/// Blocks making up generated terrain from the surface downwards
+#[derive(Clone)]
+pub struct TerrainProfile {
+    /// Top block of each column
+    pub surface: Identifier,
+    pub surface_depth: i32,
+    /// Blocks between the surface and the base
+    pub subsurface: Identifier,
+    pub subsurface_depth: i32,
+    /// Fills everything below the subsurface
+    pub base: Identifier,
+    /// Fills the empty space above the surface up to `sea_level`, caves beneath the surface stay dry
+    pub sea: Identifier,
+    /// Highest world y filled with `sea`
+    pub sea_level: i32,
+}
+
+impl Default for TerrainProfile {
+    fn default() -> Self {
+        Self {
+            surface: Identifier::new(DEFAULT_NAMESPACE, "grass"),
+            surface_depth: 1,
+            subsurface: Identifier::new(DEFAULT_NAMESPACE, "dirt"),
+            subsurface_depth: 3,
+            base: Identifier::new(DEFAULT_NAMESPACE, "stone"),
+            sea: Identifier::new(DEFAULT_NAMESPACE, "water"),
+            sea_level: 0,
+        }
+    }
+}
+
+impl TerrainProfile {
+    /// Identifier of the block `depth` blocks below the surface, where the surface block itself is at depth 0
+    pub fn block_at_depth(&self, depth: i32) -> &Identifier {
+        if depth < self.surface_depth {
+            return &self.surface;
+        }
+        if depth < self.surface_depth + self.subsurface_depth {
+            return &self.subsurface;
+        }
+        &self.base
+    }
+}
+
+/// Controls the 3D noise used to carve caves out of generated terrain
+#[derive(Clone, Copy)]
+pub struct CaveProperties {
+    pub frequency: f32,
+    /// Blocks below the surface are carved out where the noise sample exceeds this
+    pub threshold: f32,
+}
+
+impl Default for CaveProperties {
+    fn default() -> Self {
+        Self {
+            frequency: 1. / 16.,
+            threshold: 0.25,
+        }
+    }
+}
+
+/// Ore scattered through the base block of generated terrain in small veins
+#[derive(Clone)]
+pub struct OreProperties {
+    pub ore: Identifier,
+    /// Veins attempted per chunk, attempts landing outside the base block or above `max_height` place nothing
+    pub veins_per_chunk: u32,
+    /// Steps of the random walk each vein takes, the most blocks a vein can have
+    pub vein_size: u32,
+    /// Highest world y a vein can start at
+    pub max_height: i32,
+}
+
+/// Ores of a level generated with [`GeneratorKind::Normal`]
+pub fn default_ores() -> Vec<OreProperties> {
+    vec![OreProperties {
+        ore: Identifier::new(DEFAULT_NAMESPACE, "glowstone"),
+        veins_per_chunk: 4,
+        vein_size: 6,
+        max_height: -16,
+    }]
+}
+
+/// Layers of a [`GeneratorKind::Flat`] level created from the main menu
+pub fn default_flat_layers() -> Vec<(Identifier, u32)> {
+    vec![
+        (Identifier::new(DEFAULT_NAMESPACE, "stone"), 3),
+        (Identifier::new(DEFAULT_NAMESPACE, "dirt"), 2),
+        (Identifier::new(DEFAULT_NAMESPACE, "grass"), 1),
+    ]
+}
+
+/// Trees planted on the surface block of generated terrain
+#[derive(Clone)]
+pub struct TreeProperties {
+    pub log: Identifier,
+    pub leaves: Identifier,
+    /// Trees attempted per chunk, attempts not landing on the surface block place nothing
+    pub trees_per_chunk: u32,
+    /// Shortest trunk in blocks, trunks are up to 2 blocks taller
+    pub trunk_height: i32,
+    /// Blocks the leaves reach out from the trunk<br>
+    /// Trees must be smaller than a chunk, as only the chunks next to a chunk are checked for trees reaching into it
+    pub leaves_radius: i32,
+}
+
+impl Default for TreeProperties {
+    fn default() -> Self {
+        Self {
+            log: Identifier::new(DEFAULT_NAMESPACE, "log"),
+            leaves: Identifier::new(DEFAULT_NAMESPACE, "leaves"),
+            trees_per_chunk: 3,
+            trunk_height: 4,
+            leaves_radius: 2,
+        }
+    }
+}
+
+impl TreeProperties {
+    /// World position of the lowest log and the trunk height of each tree planted by the chunk at `position`,
+    /// see [`Chunk::generate_trees`]
+    fn sites(
+        &self,
+        seed: u32,
+        position: IVec3,
+        sea_level: i32,
+        height: &impl Fn(IVec2) -> i32,
+    ) -> Vec<(IVec3, i32)> {
+        let mut rng = ChunkRng::new(seed, position);
+        (0..self.trees_per_chunk)
+            .filter_map(|_| {
+                // Every attempt draws the same numbers so the sequence doesn't depend on the terrain
+                let column = position.xz() * SIZE_I32
+                    + IVec2::new(
+                        rng.below(SIZE_I32 as u32) as i32,
+                        rng.below(SIZE_I32 as u32) as i32,
+                    );
+                let trunk_height = self.trunk_height + rng.below(3) as i32;
+                let surface = height(column);
+                (surface.div_euclid(SIZE_I32) == position.y && surface >= sea_level)
+                    .then(|| (IVec3::new(column.x, surface + 1, column.y), trunk_height))
+            })
+            .collect()
+    }
+
+    /// Leaves of a tree whose highest log is at `top`
+    fn leaves_around(&self, top: IVec3) -> impl Iterator<Item = IVec3> {
+        let leaves_radius = self.leaves_radius;
+        (-1..=1).flat_map(move |layer| {
+            // The top layer is narrower to round off the crown
+            let radius = if layer == 1 {
+                leaves_radius - 1
+            } else {
+                leaves_radius
+            };
+            (-radius..=radius).flat_map(move |dx| {
+                (-radius..=radius)
+                    .filter(move |dz| radius == 0 || dx.abs() != radius || dz.abs() != radius)
+                    .map(move |dz| top + IVec3::new(dx, layer, dz))
+            })
+        })
+    }
+}
+
+/// Deterministic random numbers for generating a chunk, the same level seed and chunk position always giving the same sequence<br>
+/// SplitMix64, unlike the std hasher its output is guaranteed to be the same on every platform and version
+pub struct ChunkRng(u64);
+
+impl ChunkRng {
+    pub fn new(seed: u32, position: IVec3) -> Self {
+        Self(
+            seed as u64
+                ^ (position.x as u64).wrapping_mul(0x9e3779b97f4a7c15)
+                ^ (position.y as u64).wrapping_mul(0xc2b2ae3d27d4eb4f)
+                ^ (position.z as u64).wrapping_mul(0x165667b19e3779f9),
+        )
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Number in `0..end`, `end` must not be 0
+    pub fn below(&mut self, end: u32) -> u32 {
+        (self.next_u64() % end as u64) as u32
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub enum GeneratorKind {
+    #[default]
+    Normal,
+    /// Superflat world made of `(block, thickness)` layers stacked upwards from y=0<br>
+    /// Everything below y=0 is filled with the first layer and everything above the last layer is left empty
+    Flat { layers: Vec<(Identifier, u32)> },
+    /// Flat stone floor with one of each given block placed in a grid on top of it<br>
+    /// Used for visually checking meshing and atlas changes
+    Showcase { blocks: Vec<Identifier> },
+}
+
+/// World position of the `index`th block of the showcase world
+pub fn showcase_position(index: usize) -> IVec3 {
+    let index = index as i32;
+    IVec3::new(
+        index % SHOWCASE_ROW_LENGTH * SHOWCASE_SPACING,
+        1,
+        index / SHOWCASE_ROW_LENGTH * SHOWCASE_SPACING,
+    )
+}
+
+impl Chunk {
+    /// See [`GeneratorKind::Flat`]
+    pub fn generate_flat(position: IVec3, layers: &[(Identifier, u32)]) -> Self {
+        let mut chunk = Self::new(position);
+        let Some((bottom, _)) = layers.first() else {
+            return chunk;
+        };
+
+        chunk.fill_layer(i32::MIN, 0, &Block::new(bottom.clone()));
+        let mut layer_start = 0;
+        for (identifier, thickness) in layers {
+            let layer_end = layer_start + *thickness as i32;
+            chunk.fill_layer(layer_start, layer_end, &Block::new(identifier.clone()));
+            layer_start = layer_end;
+        }
+
+        chunk
+    }
+
+    /// Fill every block with a world y in `start..end` that lies inside this chunk
+    fn fill_layer(&mut self, start: i32, end: i32, block: &Block) {
+        let origin_y = self.position.y * SIZE_I32;
+        let start = start.saturating_sub(origin_y).max(0);
+        let end = end.saturating_sub(origin_y).min(SIZE_I32);
+        if start >= end {
+            return;
+        }
+        self.set_area(
+            I16Vec3::new(0, start as i16, 0),
+            I16Vec3::new(SIZE_I32 as i16 - 1, end as i16 - 1, SIZE_I32 as i16 - 1),
+            block,
+        );
+    }
+
+    pub fn generate_showcase(position: IVec3, blocks: &[Identifier]) -> Self {
+        // Floor fills everything at or below y=0
+        let mut chunk = Self::generate_flat(
+            position,
+            &[(Identifier::new(DEFAULT_NAMESPACE, "stone"), 1)],
+        );
+        let origin = position * SIZE_I32;
+
+        for (index, identifier) in blocks.iter().enumerate() {
+            let local = showcase_position(index) - origin;
+            if local.min_element() < 0 || local.max_element() >= SIZE_I32 {
+                continue;
+            }
+            chunk.contents[Chunk::to_index(local.as_i16vec3())] =
+                Some(Block::new(identifier.clone()));
+        }
+
+        chunk
+    }
+
+    /// Every other block filled with `identifier` in a 3D checkerboard, the most faces a chunk can have
+    pub fn filled_checkerboard(position: IVec3, identifier: &Identifier) -> Self {
+        let mut chunk = Self::new(position);
+        for (index, block) in chunk.contents.iter_mut().enumerate() {
+            let local = Chunk::to_block_coordinates_from_index(index).unwrap();
+            if (local.x + local.y + local.z) % 2 == 0 {
+                *block = Some(Block::new(identifier.clone()));
+            }
+        }
+        chunk
+    }
+
+    /// Replace blocks of `host` with veins of each of `ores`, seeded from `seed` and the chunk position<br>
+    /// Veins stay inside this chunk, a step that would leave it is skipped
+    pub fn generate_ores(&mut self, seed: u32, ores: &[OreProperties], host: &Identifier) {
+        let mut rng = ChunkRng::new(seed, self.position);
+        let origin_y = self.position.y * SIZE_I32;
+        for ore in ores {
+            for _ in 0..ore.veins_per_chunk {
+                let mut local = I16Vec3::new(
+                    rng.below(SIZE_I32 as u32) as i16,
+                    rng.below(SIZE_I32 as u32) as i16,
+                    rng.below(SIZE_I32 as u32) as i16,
+                );
+                // Every attempt draws the same numbers so the sequence doesn't depend on what was placed
+                let starts = origin_y + local.y as i32 <= ore.max_height;
+                for _ in 0..ore.vein_size {
+                    let index = Self::to_index(local);
+                    if starts
+                        && self.contents[index]
+                            .as_ref()
+                            .is_some_and(|block| block.identifier == *host)
+                    {
+                        self.contents[index] = Some(Block::new(ore.ore.clone()));
+                    }
+                    let step = NEIGHBOR_OFFSETS[rng.below(NEIGHBOR_OFFSETS.len() as u32) as usize];
+                    let next = local.as_ivec3() + step;
+                    if next.min_element() >= 0 && next.max_element() < SIZE_I32 {
+                        local = next.as_i16vec3();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Plant the trees of this chunk and of the chunks around it that reach into it, seeded from `seed` and each chunk's position<br>
+    /// A tree stands on the surface of a column whose terrain `height` lies inside the chunk planting it and not below `sea_level`,
+    /// so the trees of neighbours are known without generating them and a tree crossing the border is the same in both chunks<br>
+    /// Trees only grow into empty blocks, every log being placed before any leaves and trees of lower chunk positions first
+    pub fn generate_trees(
+        &mut self,
+        seed: u32,
+        trees: &TreeProperties,
+        sea_level: i32,
+        height: impl Fn(IVec2) -> i32,
+    ) {
+        let mut sources = NEIGHBORHOOD_OFFSETS
+            .map(|offset| self.position + offset)
+            .to_vec();
+        sources.push(self.position);
+        // Same order whichever chunk is planting, so overlapping trees are cut the same way on both sides of a border
+        sources.sort_by_key(|source| (source.x, source.y, source.z));
+        let sites = sources
+            .into_iter()
+            .flat_map(|source| trees.sites(seed, source, sea_level, &height))
+            .collect::<Vec<_>>();
+
+        let logs = sites.iter().flat_map(|&(base, trunk_height)| {
+            (0..trunk_height).map(move |log| (base + IVec3::Y * log, &trees.log))
+        });
+        let leaves = sites.iter().flat_map(|&(base, trunk_height)| {
+            trees
+                .leaves_around(base + IVec3::Y * (trunk_height - 1))
+                .map(move |leaves| (leaves, &trees.leaves))
+        });
+        let origin = self.position * SIZE_I32;
+        for (world, identifier) in logs.chain(leaves) {
+            let Some(index) = Self::try_to_index((world - origin).as_i16vec3()) else {
+                continue;
+            };
+            if self.contents[index].is_none() {
+                self.contents[index] = Some(Block::new(identifier.clone()));
+            }
+        }
+    }
+
+    /// Every block filled with `identifier`, leaving only the faces on the chunk's border
+    pub fn filled(position: IVec3, identifier: &Identifier) -> Self {
+        let mut chunk = Self::new(position);
+        chunk.fill_layer(i32::MIN, i32::MAX, &Block::new(identifier.clone()));
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifier(path: &str) -> Identifier {
+        Identifier::new(DEFAULT_NAMESPACE, path)
+    }
+
+    /// Stone in the bottom half, dirt above it and empty in the top quarter
+    fn layered_chunk() -> Chunk {
+        let mut chunk = Chunk::new(IVec3::new(0, -2, 0));
+        let last = SIZE_I32 as i16 - 1;
+        chunk.set_area(
+            I16Vec3::ZERO,
+            I16Vec3::new(last, 15, last),
+            &Block::new(identifier("stone")),
+        );
+        chunk.set_area(
+            I16Vec3::new(0, 16, 0),
+            I16Vec3::new(last, 23, last),
+            &Block::new(identifier("dirt")),
+        );
+        chunk
+    }
+
+    fn ores() -> Vec<OreProperties> {
+        vec![OreProperties {
+            ore: identifier("glowstone"),
+            veins_per_chunk: 64,
+            vein_size: 8,
+            max_height: 0,
+        }]
+    }
+
+    fn identifiers(chunk: &Chunk) -> Vec<Option<Identifier>> {
+        chunk
+            .contents
+            .iter()
+            .map(|block| block.as_ref().map(|block| block.identifier.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn ores_are_deterministic() {
+        let mut first = layered_chunk();
+        let mut second = layered_chunk();
+        first.generate_ores(7, &ores(), &identifier("stone"));
+        second.generate_ores(7, &ores(), &identifier("stone"));
+        assert_eq!(identifiers(&first), identifiers(&second));
+
+        let mut other_seed = layered_chunk();
+        other_seed.generate_ores(8, &ores(), &identifier("stone"));
+        assert_ne!(identifiers(&first), identifiers(&other_seed));
+    }
+
+    #[test]
+    fn ores_only_replace_stone() {
+        let before = identifiers(&layered_chunk());
+        let mut chunk = layered_chunk();
+        chunk.generate_ores(7, &ores(), &identifier("stone"));
+        let after = identifiers(&chunk);
+
+        let mut placed = 0;
+        for (before, after) in before.iter().zip(&after) {
+            if before != after {
+                assert_eq!(before.as_ref(), Some(&identifier("stone")));
+                assert_eq!(after.as_ref(), Some(&identifier("glowstone")));
+                placed += 1;
+            }
+        }
+        assert!(placed > 0);
+    }
+
+    #[test]
+    fn trees_cross_chunk_borders() {
+        let trees = TreeProperties {
+            trees_per_chunk: 64,
+            ..Default::default()
+        };
+        let height = |_: IVec2| 5;
+        let chunks = [IVec3::ZERO, IVec3::X].map(|position| {
+            let mut chunk = Chunk::new(position);
+            chunk.generate_trees(3, &trees, 0, height);
+            chunk
+        });
+        let block_at = |world: IVec3| {
+            chunks.iter().find_map(|chunk| {
+                let index = Chunk::try_to_index((world - chunk.position * SIZE_I32).as_i16vec3())?;
+                Some(&chunk.contents[index])
+            })
+        };
+
+        let mut crossing = 0;
+        for source in [IVec3::ZERO, IVec3::X] {
+            for (base, trunk_height) in trees.sites(3, source, 0, &height) {
+                let blocks = (0..trunk_height)
+                    .map(|log| base + IVec3::Y * log)
+                    .chain(trees.leaves_around(base + IVec3::Y * (trunk_height - 1)))
+                    .collect::<Vec<_>>();
+                if blocks
+                    .iter()
+                    .any(|world| world.x.div_euclid(SIZE_I32) != source.x)
+                {
+                    crossing += 1;
+                }
+                // Blocks outside of both chunks are left to the chunks they fall in
+                for world in blocks {
+                    if let Some(block) = block_at(world) {
+                        assert!(block.is_some(), "{world} of the tree at {base}");
+                    }
+                }
+            }
+        }
+        assert!(crossing > 0);
+    }
+
+    #[test]
+    fn trees_are_deterministic() {
+        let trees = TreeProperties::default();
+        let generate = |seed| {
+            let mut chunk = Chunk::new(IVec3::new(-1, 0, 2));
+            chunk.generate_trees(seed, &trees, 0, |column: IVec2| column.x.rem_euclid(7) + 10);
+            identifiers(&chunk)
+        };
+        assert_eq!(generate(1), generate(1));
+        assert_ne!(generate(1), generate(2));
+    }
+
+    #[test]
+    fn trees_stand_above_the_sea() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.generate_trees(1, &TreeProperties::default(), 6, |_: IVec2| 5);
+        assert!(chunk.iter_blocks().next().is_none());
+    }
+
+    #[test]
+    fn showcase_places_every_block_once() {
+        // More than a row, so the grid wraps
+        let blocks = (0..20)
+            .map(|index| identifier(&format!("block_{index}")))
+            .collect::<Vec<_>>();
+        let chunk = Chunk::generate_showcase(IVec3::ZERO, &blocks);
+        for (index, block) in blocks.iter().enumerate() {
+            let position = showcase_position(index);
+            assert_eq!(
+                chunk.contents[Chunk::to_index(position.as_i16vec3())]
+                    .as_ref()
+                    .map(|placed| &placed.identifier),
+                Some(block),
+                "block {index} at {position}"
+            );
+        }
+        let placed = chunk
+            .iter_blocks()
+            .filter(|(_, block)| block.identifier != identifier("stone"))
+            .count();
+        assert_eq!(placed, blocks.len());
+
+        // Chunks away from the grid only hold the floor
+        let below = Chunk::generate_showcase(IVec3::NEG_Y, &blocks);
+        assert!(below.contents.iter().all(|block| {
+            block
+                .as_ref()
+                .is_some_and(|block| block.identifier == identifier("stone"))
+        }));
+    }
+
+    #[test]
+    fn flat_layers_stack_upwards() {
+        let layers = default_flat_layers();
+        let below = Chunk::generate_flat(IVec3::NEG_Y, &layers);
+        assert!(below.contents.iter().all(|block| {
+            block
+                .as_ref()
+                .is_some_and(|block| block.identifier == identifier("stone"))
+        }));
+        let above = Chunk::generate_flat(IVec3::Y, &layers);
+        assert!(above.iter_blocks().next().is_none());
+
+        let chunk = Chunk::generate_flat(IVec3::ZERO, &layers);
+        let column = (0..SIZE_I32)
+            .map(|y| {
+                chunk.contents[Chunk::to_index(I16Vec3::new(5, y as i16, 9))]
+                    .as_ref()
+                    .map(|block| block.identifier.clone())
+            })
+            .collect::<Vec<_>>();
+        let mut expected = vec![Some(identifier("stone")); 3];
+        expected.extend(vec![Some(identifier("dirt")); 2]);
+        expected.push(Some(identifier("grass")));
+        expected.resize(SIZE_I32 as usize, None);
+        assert_eq!(column, expected);
+    }
+}
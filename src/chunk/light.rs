@@ -0,0 +1,271 @@
+use std::{
+    collections::VecDeque,
+    sync::{RwLock, Weak},
+};
+
+use bevy::math::{I16Vec3, IVec3};
+
+use crate::{
+    atlas::AtlasManager,
+    block::Block,
+    chunk::{
+        CONTENTS_SIZE, Chunk, ChunkNeighbors, NEIGHBOR_OFFSETS, NEIGHBORHOOD_OFFSETS, SIZE_I32,
+        SIZE_USIZE, Y_POS, read_chunk,
+    },
+};
+
+/// Light level of a block with an emission of 1, each block travelled lowers it by 1
+pub const MAX_LIGHT: u8 = 15;
+/// Furthest light can travel into or out of a chunk
+const MARGIN: i32 = MAX_LIGHT as i32;
+const EXTENT: i32 = SIZE_I32 + 2 * MARGIN;
+
+/// How a block position takes part in propagation, copied out of the chunks so no locks are held while flooding
+#[derive(Clone, Copy, PartialEq)]
+enum LightCell {
+    /// Inside a neighbour that isn't loaded
+    Unknown,
+    Open,
+    /// Opaque blocks without an emission stop light
+    Blocking,
+    Emitter(u8),
+}
+
+/// Block and sky light of a chunk and of the [`MAX_LIGHT`] deep layer of each neighbour bordering it,
+/// including those sharing only an edge or corner<br>
+/// Emitters in the chunk and that layer are flooded through both, emitters further away are ignored<br>
+/// Sky light is only computed for the chunk itself, neighbours keep what they stored when they were last lit
+pub struct LightVolume {
+    levels: Vec<u8>,
+    sky: Vec<u8>,
+    /// Sky light that entered the top of each of the chunk's columns, see [`Chunk::sky_entry`]
+    sky_entry: Vec<u8>,
+}
+
+impl LightVolume {
+    /// Will return `None` if `chunk` was invalidated
+    pub fn propagate(
+        chunk: &Weak<RwLock<Chunk>>,
+        neighbors: &ChunkNeighbors,
+        atlas_manager: &AtlasManager,
+    ) -> Option<Self> {
+        let mut cells = vec![LightCell::Unknown; (EXTENT * EXTENT * EXTENT) as usize];
+        // Unknown neighbours are treated as open to the sky so faces bordering them aren't darkened
+        let mut sky = vec![MAX_LIGHT; cells.len()];
+        // Each chunk is locked on its own so a waiting writer can't deadlock against this task
+        let mut sky_entry = None;
+        for (direction, (neighbor, offset)) in
+            neighbors.iter().zip(NEIGHBORHOOD_OFFSETS).enumerate()
+        {
+            let Some(neighbor) = neighbor.as_ref().and_then(Weak::upgrade) else {
+                continue;
+            };
+            let neighbor = read_chunk(&neighbor);
+            copy_cells(
+                &mut cells,
+                &mut sky,
+                &neighbor,
+                offset * SIZE_I32,
+                atlas_manager,
+            );
+            // Sky light leaving the bottom of the chunk above enters the top of this one
+            if direction == Y_POS && !neighbor.sky_light.is_empty() {
+                sky_entry = Some(
+                    (0..SIZE_USIZE * SIZE_USIZE)
+                        .map(|column| neighbor.sky_light[column_index(column, 0)])
+                        .collect::<Vec<u8>>(),
+                );
+            }
+        }
+        let chunk = chunk.upgrade()?;
+        let chunk = read_chunk(&chunk);
+        copy_cells(&mut cells, &mut sky, &chunk, IVec3::ZERO, atlas_manager);
+        // Without the chunk above fall back to the entry worked out when the chunk was last lit or generated
+        let sky_entry = sky_entry
+            .or_else(|| (!chunk.sky_entry.is_empty()).then(|| chunk.sky_entry.clone()))
+            .unwrap_or_else(|| vec![MAX_LIGHT; SIZE_USIZE * SIZE_USIZE]);
+        let chunk_sky = propagate_sky_light(&chunk, &sky_entry, |block| {
+            if atlas_manager.properties(&block.identifier).opaque {
+                MAX_LIGHT
+            } else {
+                1
+            }
+        });
+        drop(chunk);
+        for (index, level) in chunk_sky.into_iter().enumerate() {
+            let local = Chunk::to_block_coordinates_from_index(index)
+                .unwrap()
+                .as_ivec3();
+            if let Some(volume_index) = to_volume_index(local) {
+                sky[volume_index] = level;
+            }
+        }
+
+        let mut levels = vec![0; cells.len()];
+        let mut queue = VecDeque::new();
+        for (index, cell) in cells.iter().enumerate() {
+            if let LightCell::Emitter(level) = *cell {
+                levels[index] = level;
+                queue.push_back(index);
+            }
+        }
+        while let Some(index) = queue.pop_front() {
+            let level = levels[index];
+            if level <= 1 {
+                continue;
+            }
+            let position = from_volume_index(index);
+            for offset in NEIGHBOR_OFFSETS {
+                let Some(next) = to_volume_index(position + offset) else {
+                    continue;
+                };
+                if cells[next] == LightCell::Open && levels[next] < level - 1 {
+                    levels[next] = level - 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+        Some(Self {
+            levels,
+            sky,
+            sky_entry,
+        })
+    }
+
+    /// Block light at `position` relative to the chunk origin, 0 outside of the volume
+    pub fn block_light(&self, position: IVec3) -> u8 {
+        to_volume_index(position).map_or(0, |index| self.levels[index])
+    }
+
+    /// Sky light at `position` relative to the chunk origin, full outside of the volume
+    pub fn sky_light(&self, position: IVec3) -> u8 {
+        to_volume_index(position).map_or(MAX_LIGHT, |index| self.sky[index])
+    }
+
+    /// Stores the chunk's part of the volume in `chunk`, returning the indices into [`NEIGHBOR_OFFSETS`] of
+    /// the faces within reach of light that changed since it was last stored<br>
+    /// The chunks across those faces are lit by this chunk and need remeshing
+    pub fn store(&self, chunk: &mut Chunk) -> Vec<usize> {
+        let mut changed = [false; 6];
+        let mut block_light = Vec::with_capacity(CONTENTS_SIZE);
+        let mut sky_light = Vec::with_capacity(CONTENTS_SIZE);
+        for index in 0..CONTENTS_SIZE {
+            let position = Chunk::to_block_coordinates_from_index(index)
+                .unwrap()
+                .as_ivec3();
+            let (block, sky) = (self.block_light(position), self.sky_light(position));
+            block_light.push(block);
+            sky_light.push(sky);
+
+            // Empty light counts as unlit, and as a changed sky so the chunk below picks up the entry
+            let block_changed = chunk.block_light.get(index).copied().unwrap_or(0) != block;
+            let sky_changed = chunk.sky_light.get(index) != Some(&sky);
+            if !block_changed && !sky_changed {
+                continue;
+            }
+            // Directions are ordered positive then negative for each axis
+            for direction in 0..6 {
+                let axis = direction / 2;
+                let distance = if direction % 2 == 0 {
+                    SIZE_I32 - 1 - position[axis]
+                } else {
+                    position[axis]
+                };
+                // Neighbours only read sky light in the layer touching their face
+                changed[direction] |=
+                    (block_changed && distance < MARGIN) || (sky_changed && distance == 0);
+            }
+        }
+        chunk.block_light = block_light;
+        chunk.sky_light = sky_light;
+        chunk.sky_entry = self.sky_entry.clone();
+        (0..6).filter(|direction| changed[*direction]).collect()
+    }
+}
+
+/// Index into [`Chunk::contents`] of the block at height `y` in `column`, columns being indexed by `x + z * SIZE`
+fn column_index(column: usize, y: usize) -> usize {
+    let (x, z) = (column % SIZE_USIZE, column / SIZE_USIZE);
+    Chunk::to_index(I16Vec3::new(x as i16, y as i16, z as i16))
+}
+
+/// Sky light of every block in `chunk`, indexed like [`Chunk::contents`], from `entry` entering the top of each column<br>
+/// Light travels straight down, empty blocks keep it and each block lowers it by `attenuation` of that block
+pub fn propagate_sky_light(
+    chunk: &Chunk,
+    entry: &[u8],
+    attenuation: impl Fn(&Block) -> u8,
+) -> Vec<u8> {
+    let mut sky = vec![0; CONTENTS_SIZE];
+    for (column, &entry) in entry.iter().enumerate() {
+        let mut level = entry;
+        for y in (0..SIZE_USIZE).rev() {
+            let index = column_index(column, y);
+            if let Some(block) = &chunk.contents[index] {
+                level = level.saturating_sub(attenuation(block));
+            }
+            sky[index] = level;
+        }
+    }
+    sky
+}
+
+fn to_volume_index(position: IVec3) -> Option<usize> {
+    let position = position + MARGIN;
+    if position.cmplt(IVec3::ZERO).any() || position.cmpge(IVec3::splat(EXTENT)).any() {
+        return None;
+    }
+    Some((position.x + position.y * EXTENT + position.z * EXTENT * EXTENT) as usize)
+}
+
+fn from_volume_index(index: usize) -> IVec3 {
+    let index = index as i32;
+    IVec3::new(
+        index % EXTENT,
+        index / EXTENT % EXTENT,
+        index / (EXTENT * EXTENT),
+    ) - MARGIN
+}
+
+/// Copy the blocks and stored sky light of `chunk` that fall inside the volume,
+/// `origin` being the chunk's position relative to the lit chunk in blocks
+fn copy_cells(
+    cells: &mut [LightCell],
+    sky: &mut [u8],
+    chunk: &Chunk,
+    origin: IVec3,
+    atlas_manager: &AtlasManager,
+) {
+    // Only the part of the chunk inside the volume, most of a neighbour is further than the margin
+    let min = (-MARGIN - origin).max(IVec3::ZERO);
+    let max = (SIZE_I32 + MARGIN - origin).min(IVec3::splat(SIZE_I32));
+    for x in min.x..max.x {
+        for y in min.y..max.y {
+            for z in min.z..max.z {
+                let local = IVec3::new(x, y, z);
+                let Some(volume_index) = to_volume_index(origin + local) else {
+                    continue;
+                };
+                let index = Chunk::to_index(local.as_i16vec3());
+                cells[volume_index] = light_cell(chunk.contents[index].as_ref(), atlas_manager);
+                if let Some(&level) = chunk.sky_light.get(index) {
+                    sky[volume_index] = level;
+                }
+            }
+        }
+    }
+}
+
+fn light_cell(block: Option<&Block>, atlas_manager: &AtlasManager) -> LightCell {
+    let Some(block) = block else {
+        return LightCell::Open;
+    };
+    if let Some(emission) = atlas_manager.emission(&block.identifier) {
+        return LightCell::Emitter((emission * MAX_LIGHT as f32).round() as u8);
+    }
+    if atlas_manager.properties(&block.identifier).opaque {
+        LightCell::Blocking
+    } else {
+        LightCell::Open
+    }
+}
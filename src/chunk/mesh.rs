@@ -1,290 +1,1491 @@
-use std::sync::{RwLock, Weak};
+use std::{
+    io::{self, Write},
+    sync::{RwLock, Weak},
+};
 
 use bevy::{
     asset::RenderAssetUsages,
-    render::mesh::{Indices, Mesh, PrimitiveTopology},
+    math::{IVec3, Rect, Vec2, Vec3},
+    platform::collections::HashMap,
+    render::mesh::{Indices, Mesh, PrimitiveTopology, VertexAttributeValues},
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    Identifier,
     atlas::AtlasManager,
-    chunk::{self, Chunk, SIZE_USIZE, Z_INDEX_USIZE},
+    block::{Block, BlockFace, BlockRenderLayer},
+    chunk::{
+        Chunk, ChunkNeighbors, NEIGHBOR_OFFSETS, NEIGHBORHOOD_OFFSETS, SIZE_I32, SIZE_USIZE,
+        Z_INDEX_USIZE,
+        light::{LightVolume, MAX_LIGHT},
+        read_chunk,
+    },
 };
 
-/// Will return `None` if either [`Weak`] was invalidated while generating and `Some(None)` if the mesh would have been empty
+/// How chunk meshes are built
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MeshStrategy {
+    /// One quad per visible block face, see [`build_mesh`]
+    #[default]
+    PerFace,
+    /// Merge coplanar faces of the same block into larger quads, see [`build_greedy_mesh`]
+    Greedy,
+}
+
+impl MeshStrategy {
+    /// Greedy meshes currently ignore `ambient_occlusion` as merged quads would need matching occlusion on every corner
+    pub fn build(
+        self,
+        chunk: Weak<RwLock<Chunk>>,
+        neighbors: ChunkNeighbors,
+        atlas_manager: Weak<AtlasManager>,
+        light: &LightVolume,
+        ambient_occlusion: bool,
+    ) -> Option<ChunkMeshes> {
+        match self {
+            Self::PerFace => build_mesh(chunk, neighbors, atlas_manager, light, ambient_occlusion),
+            Self::Greedy => build_greedy_mesh(chunk, neighbors, atlas_manager, light),
+        }
+    }
+}
+
+/// Blocks merged into one along each axis by [`build_lod_mesh`]
+pub const LOD_SCALE: usize = 2;
+/// Cells along each axis of a chunk meshed by [`build_lod_mesh`]
+const LOD_SIZE: usize = SIZE_USIZE / LOD_SCALE;
+
+/// Level of detail a chunk is meshed at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeshDetail {
+    /// Every block, with the level's [`MeshStrategy`]
+    #[default]
+    Full,
+    /// Every [`LOD_SCALE`]³ blocks merged into one, see [`build_lod_mesh`]
+    Coarse,
+}
+
+/// Meshes of a chunk split by [`BlockRenderLayer`], `None` where a layer has no faces
+#[derive(Default)]
+pub struct ChunkMeshes {
+    pub opaque: Option<Mesh>,
+    pub transparent: Option<Mesh>,
+    /// Faces of blocks with an emission set in the [`AtlasManager`], whatever their render layer<br>
+    /// Meant to be drawn unlit, with the emission as the vertex colour
+    pub emissive: Option<Mesh>,
+}
+
+impl ChunkMeshes {
+    pub fn is_empty(&self) -> bool {
+        self.opaque.is_none() && self.transparent.is_none() && self.emissive.is_none()
+    }
+
+    /// Write every layer as an object of a Wavefront OBJ file, moving vertices by `origin`<br>
+    /// Positions, texture coordinates and normals are each written once and indexed separately by the faces,
+    /// so the corners of a cube are shared by its faces<br>
+    /// OBJ can't repeat part of a texture, so quads merged by [`build_greedy_mesh`] stretch a single tile across them
+    pub fn write_obj(&self, origin: Vec3, writer: &mut impl Write) -> io::Result<()> {
+        let tile_size = AtlasManager::tile_size();
+        let mut positions = ObjElements::new("v");
+        let mut uvs = ObjElements::new("vt");
+        let mut normals = ObjElements::new("vn");
+        for (name, mesh) in [
+            ("opaque", &self.opaque),
+            ("transparent", &self.transparent),
+            ("emissive", &self.emissive),
+        ] {
+            let Some(mesh) = mesh else {
+                continue;
+            };
+            writeln!(writer, "o {name}")?;
+            let mut corners = Vec::with_capacity(mesh.count_vertices());
+            if let Some(VertexAttributeValues::Float32x3(mesh_positions)) =
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+            {
+                for position in mesh_positions {
+                    let position = origin + Vec3::from_array(*position);
+                    corners.push(positions.index(writer, position.to_array())?.to_string());
+                }
+            }
+            if let (
+                Some(VertexAttributeValues::Float32x2(mesh_uvs)),
+                Some(VertexAttributeValues::Float32x2(tiles)),
+            ) = (
+                mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+                mesh.attribute(Mesh::ATTRIBUTE_UV_1),
+            ) {
+                for ((uv, tile), corner) in mesh_uvs.iter().zip(tiles).zip(&mut corners) {
+                    let uv =
+                        Vec2::from_array(*tile) + Vec2::from_array(*uv).min(Vec2::ONE) * tile_size;
+                    // OBJ texture coordinates start at the bottom of the image
+                    let index = uvs.index(writer, [uv.x, 1. - uv.y])?;
+                    corner.push_str(&format!("/{index}"));
+                }
+            }
+            if let Some(VertexAttributeValues::Float32x3(mesh_normals)) =
+                mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+            {
+                for (normal, corner) in mesh_normals.iter().zip(&mut corners) {
+                    let index = normals.index(writer, *normal)?;
+                    // Without texture coordinates the normal still comes third
+                    if !corner.contains('/') {
+                        corner.push('/');
+                    }
+                    corner.push_str(&format!("/{index}"));
+                }
+            }
+            if let Some(indices) = mesh.indices() {
+                let indices = indices.iter().collect::<Vec<usize>>();
+                for triangle in indices.chunks_exact(3) {
+                    let [a, b, c] = [0, 1, 2].map(|corner| &corners[triangle[corner]]);
+                    writeln!(writer, "f {a} {b} {c}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Distinct values of one kind of OBJ element, each written once under `prefix` the first time it is indexed
+struct ObjElements<const N: usize> {
+    prefix: &'static str,
+    indices: HashMap<[u32; N], usize>,
+}
+
+impl<const N: usize> ObjElements<N> {
+    fn new(prefix: &'static str) -> Self {
+        Self {
+            prefix,
+            indices: HashMap::new(),
+        }
+    }
+
+    /// OBJ indices count from 1 across the whole file rather than per object
+    fn index(&mut self, writer: &mut impl Write, value: [f32; N]) -> io::Result<usize> {
+        // Adding zero turns -0 into 0, which would otherwise be written twice
+        let key = value.map(|component| (component + 0.).to_bits());
+        if let Some(index) = self.indices.get(&key) {
+            return Ok(*index);
+        }
+        write!(writer, "{}", self.prefix)?;
+        for component in value {
+            write!(writer, " {component}")?;
+        }
+        writeln!(writer)?;
+        let index = self.indices.len() + 1;
+        self.indices.insert(key, index);
+        Ok(index)
+    }
+}
+
+#[derive(Default)]
+struct MeshBuffers {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    /// Position within the tile, counted in tiles so a quad covering several blocks repeats it, see [`TiledAtlas`](crate::level::TiledAtlas)
+    uv_0: Vec<[f32; 2]>,
+    /// Corner of the tile in the atlas
+    uv_1: Vec<[f32; 2]>,
+    /// Left empty by meshers that don't write vertex colours
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+}
+
+/// One `T` per chunk mesh, see [`ChunkMeshes`]
+#[derive(Default)]
+struct Layered<T> {
+    opaque: T,
+    transparent: T,
+    emissive: T,
+}
+
+type LayeredBuffers = Layered<MeshBuffers>;
+
+impl<T> Layered<T> {
+    fn get_mut(&mut self, layer: BlockRenderLayer) -> &mut T {
+        match layer {
+            BlockRenderLayer::Opaque => &mut self.opaque,
+            BlockRenderLayer::Transparent => &mut self.transparent,
+        }
+    }
+
+    /// Layer the faces of blocks with `identifier` are drawn in
+    fn for_block(&mut self, atlas_manager: &AtlasManager, identifier: &Identifier) -> &mut T {
+        if atlas_manager.emission(identifier).is_some() {
+            return &mut self.emissive;
+        }
+        self.get_mut(atlas_manager.render_layer(identifier))
+    }
+
+    fn map<U>(self, mut f: impl FnMut(T) -> U) -> Layered<U> {
+        Layered {
+            opaque: f(self.opaque),
+            transparent: f(self.transparent),
+            emissive: f(self.emissive),
+        }
+    }
+}
+
+impl LayeredBuffers {
+    fn into_meshes(self) -> ChunkMeshes {
+        ChunkMeshes {
+            opaque: self.opaque.into_mesh(),
+            transparent: self.transparent.into_mesh(),
+            emissive: self.emissive.into_mesh(),
+        }
+    }
+}
+
+impl MeshBuffers {
+    /// Empty buffers with room for `faces` quads, vertex colours included
+    fn with_faces(faces: usize) -> Self {
+        Self {
+            positions: Vec::with_capacity(faces * 4),
+            normals: Vec::with_capacity(faces * 4),
+            uv_0: Vec::with_capacity(faces * 4),
+            uv_1: Vec::with_capacity(faces * 4),
+            colors: Vec::with_capacity(faces * 4),
+            indices: Vec::with_capacity(faces * 6),
+        }
+    }
+
+    /// Push a `width` by `height` quad facing along `axis` (0 = x, 1 = y, 2 = z)<br>
+    /// `start` is the block coordinate of the quad's minimum corner on the two remaining axes<br>
+    /// UVs count one tile per block covered so merged quads repeat their texture
+    #[allow(clippy::too_many_arguments)]
+    fn push_quad(
+        &mut self,
+        axis: usize,
+        positive: bool,
+        slice: usize,
+        start: [usize; 2],
+        width: usize,
+        height: usize,
+        atlas_rect: Rect,
+    ) {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        let mut origin = [0.; 3];
+        origin[axis] = (slice + positive as usize) as f32;
+        origin[u] = start[0] as f32;
+        origin[v] = start[1] as f32;
+        let mut u_extent = [0.; 3];
+        u_extent[u] = width as f32;
+        let mut v_extent = [0.; 3];
+        v_extent[v] = height as f32;
+        let corner = |u_scale: f32, v_scale: f32| {
+            [
+                origin[0] + u_extent[0] * u_scale + v_extent[0] * v_scale,
+                origin[1] + u_extent[1] * u_scale + v_extent[1] * v_scale,
+                origin[2] + u_extent[2] * u_scale + v_extent[2] * v_scale,
+            ]
+        };
+
+        let mut normal = [0.; 3];
+        normal[axis] = if positive { 1. } else { -1. };
+
+        let indices_offset = self.positions.len() as u32;
+        self.positions.extend_from_slice(&[
+            corner(0., 0.),
+            corner(1., 0.),
+            corner(1., 1.),
+            corner(0., 1.),
+        ]);
+        self.normals.extend_from_slice(&[normal; 4]);
+
+        let (width, height) = (width as f32, height as f32);
+        // Side faces keep the texture upright, with its bottom edge at the lowest y
+        let uv = |u_scale: f32, v_scale: f32| match axis {
+            0 => [v_scale * height, (1. - u_scale) * width],
+            1 => [u_scale * width, v_scale * height],
+            _ => [u_scale * width, (1. - v_scale) * height],
+        };
+        self.uv_0
+            .extend_from_slice(&[uv(0., 0.), uv(1., 0.), uv(1., 1.), uv(0., 1.)]);
+        self.uv_1.extend_from_slice(&[atlas_rect.min.to_array(); 4]);
+
+        // u x v points along the positive axis, so counter clockwise winding for positive faces is 0 -> 1 -> 2
+        let winding: [u32; 6] = if positive {
+            [0, 1, 2, 0, 2, 3]
+        } else {
+            [0, 2, 1, 0, 3, 2]
+        };
+        self.indices
+            .extend(winding.map(|index| indices_offset + index));
+    }
+
+    /// Push the face of a block looking along [`NEIGHBOR_OFFSETS`]`[direction]`, with the `corners` from [`face_corners`]
+    /// and the `ao` and `colors` of each corner<br>
+    /// Side faces keep the texture upright, with its bottom edge at the bottom of the block
+    fn push_face(
+        &mut self,
+        direction: usize,
+        corners: [[f32; 3]; 4],
+        atlas_rect: Rect,
+        ao: [u8; 4],
+        colors: [[f32; 4]; 4],
+    ) {
+        let axis = direction / 2;
+        let positive = direction % 2 == 0;
+        let indices_offset = self.positions.len() as u32;
+        self.positions.extend_from_slice(&corners);
+        let mut normal = [0.; 3];
+        normal[axis] = if positive { 1. } else { -1. };
+        self.normals.extend_from_slice(&[normal; 4]);
+        self.colors.extend(colors);
+        // The corners of positive faces are listed clockwise when looking at them
+        self.indices
+            .extend_from_slice(&quad_indices(indices_offset, positive, ao));
+        self.uv_0.extend(FACE_CORNERS.map(|[a, b]| {
+            let (a, b) = (a as f32, b as f32);
+            match axis {
+                0 => [a, 1. - b],
+                1 => [a, b],
+                _ => [b, 1. - a],
+            }
+        }));
+        self.uv_1.extend_from_slice(&[atlas_rect.min.to_array(); 4]);
+    }
+
+    fn into_mesh(self) -> Option<Mesh> {
+        if self.indices.is_empty() {
+            return None;
+        }
+
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uv_0)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_1, self.uv_1)
+        .with_inserted_indices(Indices::U32(self.indices));
+        if !self.colors.is_empty() {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        }
+        Some(mesh)
+    }
+}
+
+/// Blocks along each axis of an [`Occupancy`], the chunk and one layer of its neighbours on either side
+const PADDED_SIZE: usize = SIZE_USIZE + 2;
+
+/// One bit per block of a chunk and of the layer touching it in each neighbour<br>
+/// Taken once before meshing so culling and ambient occlusion don't lock neighbours or match on `Option<Block>` for every face<br>
+/// Layers of unloaded neighbours are empty, so faces against unloaded chunks are still drawn.
+/// The edge and corner neighbours are only read when they are needed for ambient occlusion, and are otherwise empty too
+struct Occupancy {
+    solid: Vec<u64>,
+    /// Blocks with [`BlockProperties::opaque`](crate::block::BlockProperties::opaque) set
+    opaque: Vec<u64>,
+    /// Non-opaque blocks in the neighbour layers, the only neighbouring blocks culling needs the identifier of
+    border_identifiers: HashMap<IVec3, Identifier>,
+}
+
+impl Occupancy {
+    /// Each neighbour's read lock is released before the next one is taken<br>
+    /// Only the face neighbours are read unless `edges_and_corners` is set, culling never looks further
+    fn new(
+        neighbors: &ChunkNeighbors,
+        atlas_manager: &AtlasManager,
+        edges_and_corners: bool,
+    ) -> Self {
+        let words = PADDED_SIZE.pow(3).div_ceil(u64::BITS as usize);
+        let mut occupancy = Self {
+            solid: vec![0; words],
+            opaque: vec![0; words],
+            border_identifiers: HashMap::new(),
+        };
+        let read = if edges_and_corners {
+            NEIGHBORHOOD_OFFSETS.len()
+        } else {
+            NEIGHBOR_OFFSETS.len()
+        };
+        for (neighbor, offset) in neighbors.iter().zip(NEIGHBORHOOD_OFFSETS).take(read) {
+            let Some(neighbor) = neighbor.as_ref().and_then(Weak::upgrade) else {
+                continue;
+            };
+            let neighbor = read_chunk(&neighbor);
+            // The single layer touching the chunk on each axis the neighbour is offset along
+            let range = |axis: usize| match offset[axis] {
+                1 => SIZE_I32..SIZE_I32 + 1,
+                -1 => -1..0,
+                _ => 0..SIZE_I32,
+            };
+            for x in range(0) {
+                for y in range(1) {
+                    for z in range(2) {
+                        let position = IVec3::new(x, y, z);
+                        let wrapped = position.rem_euclid(IVec3::splat(SIZE_I32));
+                        let Some(block) = &neighbor.contents[Chunk::to_index(wrapped.as_i16vec3())]
+                        else {
+                            continue;
+                        };
+                        if !occupancy.insert(atlas_manager, block, position) {
+                            occupancy
+                                .border_identifiers
+                                .insert(position, block.identifier.clone());
+                        }
+                    }
+                }
+            }
+        }
+        occupancy
+    }
+
+    fn add_chunk(&mut self, atlas_manager: &AtlasManager, chunk: &Chunk) {
+        for (position, block) in chunk.iter_blocks() {
+            self.insert(atlas_manager, block, position.as_ivec3());
+        }
+    }
+
+    /// Returns whether `block` is opaque
+    fn insert(&mut self, atlas_manager: &AtlasManager, block: &Block, position: IVec3) -> bool {
+        let index = Self::index(position).expect("Position outside of the occupancy");
+        let (word, bit) = (
+            index / u64::BITS as usize,
+            1 << (index % u64::BITS as usize),
+        );
+        self.solid[word] |= bit;
+        let opaque = atlas_manager.properties(&block.identifier).opaque;
+        if opaque {
+            self.opaque[word] |= bit;
+        }
+        opaque
+    }
+
+    fn index(position: IVec3) -> Option<usize> {
+        let padded = position + 1;
+        if padded.cmplt(IVec3::ZERO).any() || padded.cmpge(IVec3::splat(PADDED_SIZE as i32)).any() {
+            return None;
+        }
+        let padded = padded.as_uvec3();
+        Some(
+            padded.x as usize
+                + padded.y as usize * PADDED_SIZE
+                + padded.z as usize * PADDED_SIZE * PADDED_SIZE,
+        )
+    }
+
+    fn get(bits: &[u64], position: IVec3) -> bool {
+        Self::index(position).is_some_and(|index| {
+            bits[index / u64::BITS as usize] & 1 << (index % u64::BITS as usize) != 0
+        })
+    }
+
+    /// Whether there is a block at `position` relative to the chunk origin
+    fn is_solid(&self, position: IVec3) -> bool {
+        Self::get(&self.solid, position)
+    }
+
+    /// Whether the face of `block` looking into the block at `neighbor` is hidden by it, `chunk` being the chunk this occupancy was taken of<br>
+    /// [Opaque](crate::block::BlockProperties::opaque) neighbours hide every face, other neighbours only hide faces of the same block
+    /// so the inside of glass isn't drawn while blocks behind it still are
+    fn is_face_hidden(&self, chunk: &Chunk, block: &Block, neighbor: IVec3) -> bool {
+        if Self::get(&self.opaque, neighbor) {
+            return true;
+        }
+        if !self.is_solid(neighbor) {
+            return false;
+        }
+        let identifier = match Chunk::try_to_index(neighbor.as_i16vec3()) {
+            Some(index) => chunk.contents[index]
+                .as_ref()
+                .map(|block| &block.identifier),
+            None => self.border_identifiers.get(&neighbor),
+        };
+        identifier == Some(&block.identifier)
+    }
+
+    /// Whether the top face of a [fluid](crate::block::BlockProperties::fluid) block is hidden by the block at `above`<br>
+    /// Any block hides it, so only the surface of a body of water is drawn and not the water under glass or leaves
+    fn is_fluid_top_hidden(&self, above: IVec3) -> bool {
+        self.is_solid(above)
+    }
+}
+
+/// Ambient occlusion level from 0 (darkest) to 3 (unoccluded) of the face `vertex` on the side of `block` facing `normal`<br>
+/// Samples the two blocks beside the vertex and the block diagonal to it in the layer the face looks into
+fn vertex_ao(is_solid: impl Fn(IVec3) -> bool, block: IVec3, normal: IVec3, vertex: Vec3) -> u8 {
+    let normal_axis = if normal.x != 0 {
+        0
+    } else if normal.y != 0 {
+        1
+    } else {
+        2
+    };
+    // Direction from the centre of the face towards the vertex along each axis tangent to the face
+    let towards = (vertex - (block.as_vec3() + 0.5)).signum().as_ivec3();
+    let mut side_1_offset = IVec3::ZERO;
+    side_1_offset[(normal_axis + 1) % 3] = towards[(normal_axis + 1) % 3];
+    let mut side_2_offset = IVec3::ZERO;
+    side_2_offset[(normal_axis + 2) % 3] = towards[(normal_axis + 2) % 3];
+
+    let outside = block + normal;
+    let side_1 = is_solid(outside + side_1_offset);
+    let side_2 = is_solid(outside + side_2_offset);
+    if side_1 && side_2 {
+        return 0;
+    }
+    let corner = is_solid(outside + side_1_offset + side_2_offset);
+    3 - side_1 as u8 - side_2 as u8 - corner as u8
+}
+
+/// Vertex colour brightness for each ambient occlusion level
+const AO_BRIGHTNESS: [f32; 4] = [0.45, 0.65, 0.82, 1.];
+
+/// Brightness of a face with no sky light, such as in a cave
+const SKY_LIGHT_FLOOR: f32 = 0.15;
+/// How much brighter a face lit by [`MAX_LIGHT`] block light is drawn, on top of its sky light
+const BLOCK_LIGHT_BOOST: f32 = 1.5;
+
+/// Vertex colour of a corner with ambient occlusion level `ao` on a face looking into a block with `block_light` and `sky_light`
+fn lit_color(ao: u8, block_light: u8, sky_light: u8) -> [f32; 4] {
+    let sky = SKY_LIGHT_FLOOR + (1. - SKY_LIGHT_FLOOR) * sky_light as f32 / MAX_LIGHT as f32;
+    let block = BLOCK_LIGHT_BOOST * block_light as f32 / MAX_LIGHT as f32;
+    let brightness = AO_BRIGHTNESS[ao as usize] * (sky + block);
+    [brightness, brightness, brightness, 1.]
+}
+
+/// Vertex colour of an emissive face, which replaces ambient occlusion as a glowing face isn't shadowed by its surroundings
+fn emission_color(emission: f32) -> [f32; 4] {
+    [emission, emission, emission, 1.]
+}
+
+/// Indices into [`NEIGHBOR_OFFSETS`] in the order [`build_mesh`] pushes the faces of a block: top, bottom, east, west, south, north
+const FACE_ORDER: [usize; 6] = [2, 3, 0, 1, 4, 5];
+
+/// Offsets of each corner of a face along the two axes tangent to it, see [`face_corners`]
+const FACE_CORNERS: [[usize; 2]; 4] = [[0, 0], [1, 0], [1, 1], [0, 1]];
+
+/// Corners of the face of the block at `block` looking along [`NEIGHBOR_OFFSETS`]`[direction]`, in the order of [`FACE_CORNERS`]<br>
+/// The first offset of each corner runs along the axis two after the normal's and the second along the axis after it,
+/// so the faces on both sides of an axis list their corners in the same order, clockwise when looking at the positive one
+fn face_corners(direction: usize, block: IVec3) -> [[f32; 3]; 4] {
+    let axis = direction / 2;
+    let positive = direction % 2 == 0;
+    let (a_axis, b_axis) = ((axis + 2) % 3, (axis + 1) % 3);
+    let block = block.as_vec3().to_array();
+    let offset = |coordinate: f32, high: bool| if high { coordinate + 1. } else { coordinate };
+    FACE_CORNERS.map(|[a, b]| {
+        let mut corner = block;
+        corner[axis] = offset(block[axis], positive);
+        corner[a_axis] = offset(block[a_axis], a == 1);
+        corner[b_axis] = offset(block[b_axis], b == 1);
+        corner
+    })
+}
+
+/// Indices of the two triangles making up a quad whose four corners were pushed starting at `offset`<br>
+/// `clockwise` is whether the corners are listed clockwise when looking at the front of the face<br>
+/// The quad is split along the diagonal between its two darker corners so ambient occlusion interpolates symmetrically
+fn quad_indices(offset: u32, clockwise: bool, ao: [u8; 4]) -> [u32; 6] {
+    let [a, b, c, d] = if ao[0] + ao[2] > ao[1] + ao[3] {
+        [1, 2, 3, 0]
+    } else {
+        [0, 1, 2, 3]
+    };
+    let triangles = if clockwise {
+        [a, c, b, a, d, c]
+    } else {
+        [a, b, c, a, c, d]
+    };
+    triangles.map(|index| offset + index)
+}
+
+/// Will return `None` if either [`Weak`] was invalidated while generating<br>
+/// Faces on the chunk border are culled against `neighbors`, see [`ChunkGrid::neighbors`](crate::chunk::ChunkGrid::neighbors)<br>
+/// Each face is lit by the block and sky light in front of it from `light` through [`Mesh::ATTRIBUTE_COLOR`],
+/// and when `ambient_occlusion` is set each vertex is also darkened by the blocks around it<br>
+/// Emissive faces hold their emission in the colour instead<br>
+/// Faces are split into one mesh per [`BlockRenderLayer`], culled according to [`Occupancy::is_face_hidden`]
 pub fn build_mesh(
     chunk: Weak<RwLock<Chunk>>,
+    neighbors: ChunkNeighbors,
+    atlas_manager: Weak<AtlasManager>,
+    light: &LightVolume,
+    ambient_occlusion: bool,
+) -> Option<ChunkMeshes> {
+    let chunk = chunk.upgrade()?;
+    let atlas = atlas_manager.upgrade()?;
+    // Neighbours are read before the chunk's lock is taken so no two chunks are ever locked at once
+    let mut occupancy = Occupancy::new(&neighbors, &atlas, ambient_occlusion);
+    let chunk = read_chunk(&chunk);
+    occupancy.add_chunk(&atlas, &chunk);
+
+    // Which faces of each block are visible, in the order of `FACE_ORDER`, found first and counted so every buffer is allocated once
+    let visible_blocks = chunk
+        .iter_blocks()
+        .map(|(block_position, block)| {
+            let block_position = block_position.as_ivec3();
+            let fluid = atlas.properties(&block.identifier).fluid;
+            let visible = FACE_ORDER.map(|direction| {
+                let normal = NEIGHBOR_OFFSETS[direction];
+                let facing = block_position + normal;
+                !(occupancy.is_face_hidden(&chunk, block, facing)
+                    || (fluid && normal == IVec3::Y && occupancy.is_fluid_top_hidden(facing)))
+            });
+            (block_position, block, visible)
+        })
+        .filter(|(_, _, visible)| visible.contains(&true))
+        .collect::<Vec<(IVec3, &Block, [bool; 6])>>();
+    let mut face_counts = Layered::<usize>::default();
+    for (_, block, visible) in &visible_blocks {
+        *face_counts.for_block(&atlas, &block.identifier) +=
+            visible.iter().filter(|visible| **visible).count();
+    }
+    let mut layers = face_counts.map(MeshBuffers::with_faces);
+
+    for (block_position, block, visible) in visible_blocks {
+        let face_rect =
+            |face: BlockFace| atlas.face_atlas_location_or_error(&block.identifier, face);
+        let buffers = layers.for_block(&atlas, &block.identifier);
+        let emission = atlas.emission(&block.identifier);
+
+        let face_colors = |normal: IVec3, ao: [u8; 4]| match emission {
+            Some(emission) => [emission_color(emission); 4],
+            None => {
+                let facing = block_position + normal;
+                let (block_light, sky_light) = (light.block_light(facing), light.sky_light(facing));
+                ao.map(|ao| lit_color(ao, block_light, sky_light))
+            }
+        };
+        // Ambient occlusion of each of the face's `corners`
+        let occlusion = |normal: IVec3, corners: &[[f32; 3]; 4]| -> [u8; 4] {
+            if !ambient_occlusion || emission.is_some() {
+                return [3; 4];
+            }
+            std::array::from_fn(|corner| {
+                vertex_ao(
+                    |position| occupancy.is_solid(position),
+                    block_position,
+                    normal,
+                    Vec3::from_array(corners[corner]),
+                )
+            })
+        };
+
+        for (direction, visible) in FACE_ORDER.into_iter().zip(visible) {
+            if !visible {
+                continue;
+            }
+            let normal = NEIGHBOR_OFFSETS[direction];
+            let corners = face_corners(direction, block_position);
+            let ao = occlusion(normal, &corners);
+            buffers.push_face(
+                direction,
+                corners,
+                face_rect(BlockFace::ALL[direction]),
+                ao,
+                face_colors(normal, ao),
+            );
+        }
+    }
+
+    Some(layers.into_meshes())
+}
+
+/// Alternative to [`build_mesh`] which merges adjacent coplanar faces of the same block and light into a single quad<br>
+/// Merged quads repeat the block's texture once per block, see [`MeshBuffers::push_quad`]<br>
+/// Return value has the same meaning as [`build_mesh`]
+pub fn build_greedy_mesh(
+    chunk: Weak<RwLock<Chunk>>,
+    neighbors: ChunkNeighbors,
+    atlas_manager: Weak<AtlasManager>,
+    light: &LightVolume,
+) -> Option<ChunkMeshes> {
+    let chunk = chunk.upgrade()?;
+    let atlas_manager = atlas_manager.upgrade()?;
+    let mut occupancy = Occupancy::new(&neighbors, &atlas_manager, false);
+    let chunk = read_chunk(&chunk);
+    occupancy.add_chunk(&atlas_manager, &chunk);
+    let to_index =
+        |position: [usize; 3]| position[0] + position[1] * SIZE_USIZE + position[2] * Z_INDEX_USIZE;
+
+    let mut layers = LayeredBuffers::default();
+    // Directions follow the `NEIGHBOR_OFFSETS` order: +X, -X, +Y, -Y, +Z, -Z
+    for (direction, offset) in NEIGHBOR_OFFSETS.into_iter().enumerate() {
+        let axis = direction / 2;
+        let positive = direction % 2 == 0;
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        for slice in 0..SIZE_USIZE {
+            // Identifier and block and sky light of the visible face at each (u, v) position in this slice
+            // Only faces with equal light are merged so each quad has a single colour
+            let mut mask: [[Option<(&Identifier, (u8, u8))>; SIZE_USIZE]; SIZE_USIZE] =
+                [[None; SIZE_USIZE]; SIZE_USIZE];
+            for mask_u in 0..SIZE_USIZE {
+                for mask_v in 0..SIZE_USIZE {
+                    let mut position = [0; 3];
+                    position[axis] = slice;
+                    position[u] = mask_u;
+                    position[v] = mask_v;
+                    let Some(ref block) = chunk.contents[to_index(position)] else {
+                        continue;
+                    };
+                    let facing =
+                        IVec3::new(position[0] as i32, position[1] as i32, position[2] as i32)
+                            + offset;
+                    let hidden = occupancy.is_face_hidden(&chunk, block, facing)
+                        || (offset == IVec3::Y
+                            && atlas_manager.properties(&block.identifier).fluid
+                            && occupancy.is_fluid_top_hidden(facing));
+                    if !hidden {
+                        let face_light = (light.block_light(facing), light.sky_light(facing));
+                        mask[mask_u][mask_v] = Some((&block.identifier, face_light));
+                    }
+                }
+            }
+
+            for start_v in 0..SIZE_USIZE {
+                for start_u in 0..SIZE_USIZE {
+                    let Some(face) = mask[start_u][start_v] else {
+                        continue;
+                    };
+                    let (identifier, face_light) = face;
+
+                    let (mut width, mut height) = (1, 1);
+                    while start_u + width < SIZE_USIZE
+                        && mask[start_u + width][start_v] == Some(face)
+                    {
+                        width += 1;
+                    }
+                    while start_v + height < SIZE_USIZE
+                        && (start_u..start_u + width)
+                            .all(|mask_u| mask[mask_u][start_v + height] == Some(face))
+                    {
+                        height += 1;
+                    }
+                    for row in mask.iter_mut().skip(start_u).take(width) {
+                        for masked in row.iter_mut().skip(start_v).take(height) {
+                            *masked = None;
+                        }
+                    }
+
+                    let buffers = layers.for_block(&atlas_manager, identifier);
+                    buffers.push_quad(
+                        axis,
+                        positive,
+                        slice,
+                        [start_u, start_v],
+                        width,
+                        height,
+                        atlas_manager
+                            .face_atlas_location_or_error(identifier, BlockFace::ALL[direction]),
+                    );
+                    let color = atlas_manager
+                        .emission(identifier)
+                        .map_or_else(|| lit_color(3, face_light.0, face_light.1), emission_color);
+                    buffers.colors.extend([color; 4]);
+                }
+            }
+        }
+    }
+
+    Some(layers.into_meshes())
+}
+
+/// Coarse alternative to [`build_mesh`] for distant chunks, merging every [`LOD_SCALE`]³ blocks into a single cell
+/// holding the most common block among them<br>
+/// A cell is empty when fewer than half of its blocks are filled, ties between blocks go to the first one found<br>
+/// Faces are lit by the light in front of the corner of their cell and have no ambient occlusion<br>
+/// Faces on the chunk border are only culled when every block of `neighbors` in front of them is opaque.
+/// Neighbours meshed at another detail don't line up with the cells, and the border faces close the step between
+/// the two surfaces instead of leaving a gap. A full detail neighbour still culls its border faces against the real
+/// blocks, which can leave a block sized hole where a cell rounded those blocks away
+pub fn build_lod_mesh(
+    chunk: Weak<RwLock<Chunk>>,
+    neighbors: ChunkNeighbors,
     atlas_manager: Weak<AtlasManager>,
-) -> Option<Option<Mesh>> {
-    let mut positions = Vec::new();
-    let mut normals = Vec::new();
-    let mut indices = Vec::new();
-    let mut uv_0 = Vec::new();
-    let mut indices_offset = 0;
-
-    for index in 0..chunk::CONTENTS_SIZE {
-        let atlas_rect = {
-            let rw_lock = chunk.upgrade()?;
-            let Some(ref block) = rw_lock.read().expect("Chunk rw poisoned").contents[index] else {
+    light: &LightVolume,
+) -> Option<ChunkMeshes> {
+    let chunk = chunk.upgrade()?;
+    let atlas_manager = atlas_manager.upgrade()?;
+    // Only the neighbour layers are filled in, cells are culled against each other
+    let occupancy = Occupancy::new(&neighbors, &atlas_manager, false);
+    let chunk = read_chunk(&chunk);
+    let cell_index = |cell: IVec3| {
+        if cell.cmplt(IVec3::ZERO).any() || cell.cmpge(IVec3::splat(LOD_SIZE as i32)).any() {
+            return None;
+        }
+        let cell = cell.as_uvec3();
+        Some(cell.x as usize + cell.y as usize * LOD_SIZE + cell.z as usize * LOD_SIZE * LOD_SIZE)
+    };
+
+    let mut cells: Vec<Option<&Block>> = vec![None; LOD_SIZE.pow(3)];
+    let mut counts: Vec<(&Block, usize)> = Vec::new();
+    for (index, cell) in cells.iter_mut().enumerate() {
+        let origin = IVec3::new(
+            (index % LOD_SIZE) as i32,
+            (index / LOD_SIZE % LOD_SIZE) as i32,
+            (index / (LOD_SIZE * LOD_SIZE)) as i32,
+        ) * LOD_SCALE as i32;
+        counts.clear();
+        let mut filled = 0;
+        for offset in 0..LOD_SCALE.pow(3) {
+            let position = origin
+                + IVec3::new(
+                    (offset % LOD_SCALE) as i32,
+                    (offset / LOD_SCALE % LOD_SCALE) as i32,
+                    (offset / (LOD_SCALE * LOD_SCALE)) as i32,
+                );
+            let Some(block) = &chunk.contents[Chunk::to_index(position.as_i16vec3())] else {
                 continue;
             };
-            atlas_manager
-                .upgrade()?
-                .atlas_location_or_error(&block.identifier)
+            filled += 1;
+            match counts
+                .iter_mut()
+                .find(|(counted, _)| counted.identifier == block.identifier)
+            {
+                Some((_, count)) => *count += 1,
+                None => counts.push((block, 1)),
+            }
+        }
+        if filled * 2 < LOD_SCALE.pow(3) {
+            continue;
+        }
+        *cell = counts
+            .iter()
+            .fold(
+                None,
+                |best: Option<(&Block, usize)>, &(block, count)| match best {
+                    Some((_, best_count)) if best_count >= count => best,
+                    _ => Some((block, count)),
+                },
+            )
+            .map(|(block, _)| block);
+    }
+
+    let mut layers = LayeredBuffers::default();
+    for (index, block) in cells.iter().enumerate() {
+        let Some(block) = block else {
+            continue;
         };
+        let cell = IVec3::new(
+            (index % LOD_SIZE) as i32,
+            (index / LOD_SIZE % LOD_SIZE) as i32,
+            (index / (LOD_SIZE * LOD_SIZE)) as i32,
+        );
+        let fluid = atlas_manager.properties(&block.identifier).fluid;
+        // Directions follow the `NEIGHBOR_OFFSETS` order: +X, -X, +Y, -Y, +Z, -Z
+        for (direction, offset) in NEIGHBOR_OFFSETS.into_iter().enumerate() {
+            let axis = direction / 2;
+            let positive = direction % 2 == 0;
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let origin = cell * LOD_SCALE as i32;
+            // First block in front of the face
+            let facing = if positive {
+                origin + offset * LOD_SCALE as i32
+            } else {
+                origin + offset
+            };
 
-        let (x, y, z) = {
-            let block_position = Chunk::to_block_coordinates_from_index(index)
-                .unwrap()
-                .as_vec3();
-            (block_position.x, block_position.y, block_position.z)
+            let hidden = match cell_index(cell + offset) {
+                // Same rules as `Occupancy::is_face_hidden` and `Occupancy::is_fluid_top_hidden`, applied to cells
+                Some(index) => cells[index].is_some_and(|neighbor| {
+                    atlas_manager.properties(&neighbor.identifier).opaque
+                        || neighbor.identifier == block.identifier
+                        || (fluid && offset == IVec3::Y)
+                }),
+                None => (0..LOD_SCALE * LOD_SCALE).all(|offset| {
+                    let mut position = facing;
+                    position[u] += (offset % LOD_SCALE) as i32;
+                    position[v] += (offset / LOD_SCALE) as i32;
+                    Occupancy::get(&occupancy.opaque, position)
+                }),
+            };
+            if hidden {
+                continue;
+            }
+
+            let buffers = layers.for_block(&atlas_manager, &block.identifier);
+            let first_vertex = buffers.positions.len();
+            buffers.push_quad(
+                axis,
+                positive,
+                cell[axis] as usize,
+                [cell[u] as usize, cell[v] as usize],
+                1,
+                1,
+                atlas_manager
+                    .face_atlas_location_or_error(&block.identifier, BlockFace::ALL[direction]),
+            );
+            // Quads were pushed in cells, scaled to blocks here
+            for position in &mut buffers.positions[first_vertex..] {
+                *position = position.map(|coordinate| coordinate * LOD_SCALE as f32);
+            }
+            let color = atlas_manager.emission(&block.identifier).map_or_else(
+                || lit_color(3, light.block_light(facing), light.sky_light(facing)),
+                emission_color,
+            );
+            buffers.colors.extend([color; 4]);
+        }
+    }
+
+    Some(layers.into_meshes())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use bevy::asset::{Assets, Handle};
+
+    use super::*;
+    use crate::{
+        DEFAULT_NAMESPACE,
+        block::BlockProperties,
+        chunk::{
+            CONTENTS_SIZE,
+            generation::{ChunkRng, TerrainProfile},
+            tests::Constant,
+        },
+    };
+
+    pub(crate) fn identifier(path: &str) -> Identifier {
+        Identifier::new(DEFAULT_NAMESPACE, path)
+    }
+
+    /// Opaque stone, dirt and grass with its own top texture, transparent glass and water and emissive glowstone<br>
+    /// Registered without textures, meshing only needs their atlas locations
+    pub(crate) fn atlas_manager() -> AtlasManager {
+        let mut atlas_manager = AtlasManager::default();
+        atlas_manager.set_error_texture(Handle::default());
+        for path in ["stone", "dirt", "grass", "glass", "water", "glowstone"] {
+            atlas_manager.add_data(identifier(path), Handle::default());
+        }
+        atlas_manager.add_face_data(&identifier("grass"), BlockFace::Top, Handle::default());
+        for (path, fluid) in [("glass", false), ("water", true)] {
+            atlas_manager.set_render_layer(&identifier(path), BlockRenderLayer::Transparent);
+            atlas_manager.set_properties(
+                &identifier(path),
+                BlockProperties {
+                    solid: !fluid,
+                    opaque: false,
+                    fluid,
+                    ..Default::default()
+                },
+            );
+        }
+        atlas_manager.set_emission(&identifier("glowstone"), Some(0.8));
+        atlas_manager.rebuild_atlas(&mut Assets::default());
+        atlas_manager
+    }
+
+    /// Roughly a third of the blocks filled with a mix of every block of [`atlas_manager`]
+    pub(crate) fn random_chunk(position: IVec3, seed: u32) -> Chunk {
+        let paths = ["stone", "dirt", "grass", "glass", "water", "glowstone"];
+        let mut rng = ChunkRng::new(seed, position);
+        let mut chunk = Chunk::new(position);
+        for index in 0..CONTENTS_SIZE {
+            let block = rng.below(16) as usize;
+            if block < paths.len() {
+                chunk.contents[index] = Some(Block::new(identifier(paths[block])));
+            }
+        }
+        chunk
+    }
+
+    fn block_at<'a>(
+        chunk: &'a Chunk,
+        neighbors: &[&'a Chunk],
+        position: IVec3,
+    ) -> Option<&'a Block> {
+        let offset = position.div_euclid(IVec3::splat(SIZE_I32));
+        let owner = if offset == IVec3::ZERO {
+            chunk
+        } else {
+            neighbors
+                .iter()
+                .copied()
+                .find(|neighbor| neighbor.position == chunk.position + offset)?
         };
+        owner.contents[Chunk::to_index((position - offset * SIZE_I32).as_i16vec3())].as_ref()
+    }
 
-        // May be worth storing chunk.upgrade() as a local variable instead of calling Weak::upgrade for each face
-        // SIZE_USIZE moves the index by 1 on the y axis
-        // Z_INDEX_USIZE moves the index by 1 on the z axis
-        // TOP FACE
-        if index / SIZE_USIZE % SIZE_USIZE != SIZE_USIZE - 1
-            && chunk
-                .upgrade()?
-                .read()
-                .expect("Chunk rw poisoned")
-                .contents
-                .get(index + SIZE_USIZE)
-                .is_none_or(|block| block.is_none())
-        {
-            positions.extend_from_slice(&[
-                [x, y + 1., z],
-                [x + 1., y + 1., z],
-                [x + 1., y + 1., z + 1.],
-                [x, y + 1., z + 1.],
-            ]);
-            normals.extend_from_slice(&[
-                [0.0, 1.0, 0.0],
-                [0.0, 1.0, 0.0],
-                [0.0, 1.0, 0.0],
-                [0.0, 1.0, 0.0],
-            ]);
-            indices.extend_from_slice(&[
-                indices_offset,
-                indices_offset + 3,
-                indices_offset + 1,
-                indices_offset + 1,
-                indices_offset + 3,
-                indices_offset + 2,
-            ]);
-            uv_0.extend_from_slice(&[
-                [atlas_rect.min.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.max.y],
-                [atlas_rect.min.x, atlas_rect.max.y],
-            ]);
-
-            indices_offset += 4;
-        }
-        // BOTTOM FACE
-        if index / SIZE_USIZE % SIZE_USIZE != 0
-            && chunk
-                .upgrade()?
-                .read()
-                .expect("Chunk rw poisoned")
-                .contents
-                .get(index - SIZE_USIZE)
-                .is_none_or(|block| block.is_none())
-        {
-            positions.extend_from_slice(&[
-                [x, y, z],
-                [x + 1., y, z],
-                [x + 1., y, z + 1.],
-                [x, y, z + 1.],
-            ]);
-            normals.extend_from_slice(&[
-                [0.0, -1.0, 0.0],
-                [0.0, -1.0, 0.0],
-                [0.0, -1.0, 0.0],
-                [0.0, -1.0, 0.0],
-            ]);
-            indices.extend_from_slice(&[
-                indices_offset,
-                indices_offset + 1,
-                indices_offset + 3,
-                indices_offset + 1,
-                indices_offset + 2,
-                indices_offset + 3,
-            ]);
-            uv_0.extend_from_slice(&[
-                [atlas_rect.min.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.max.y],
-                [atlas_rect.min.x, atlas_rect.max.y],
-            ]);
-            indices_offset += 4;
-        }
-        // RIGHT FACE
-        if index % SIZE_USIZE != SIZE_USIZE - 1
-            && chunk
-                .upgrade()?
-                .read()
-                .expect("Chunk rw poisoned")
-                .contents
-                .get(index + 1)
-                .is_none_or(|block| block.is_none())
-        {
-            positions.extend_from_slice(&[
-                [x + 1., y, z],
-                [x + 1., y, z + 1.],
-                [x + 1., y + 1., z + 1.],
-                [x + 1., y + 1., z],
-            ]);
-            normals.extend_from_slice(&[
-                [1.0, 0.0, 0.0],
-                [1.0, 0.0, 0.0],
-                [1.0, 0.0, 0.0],
-                [1.0, 0.0, 0.0],
-            ]);
-            indices.extend_from_slice(&[
-                indices_offset,
-                indices_offset + 3,
-                indices_offset + 1,
-                indices_offset + 1,
-                indices_offset + 3,
-                indices_offset + 2,
-            ]);
-            uv_0.extend_from_slice(&[
-                [atlas_rect.min.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.max.y],
-                [atlas_rect.min.x, atlas_rect.max.y],
-            ]);
-            indices_offset += 4;
-        }
-        // LEFT FACE
-        if index % SIZE_USIZE != 0
-            && chunk
-                .upgrade()?
-                .read()
-                .expect("Chunk rw poisoned")
-                .contents
-                .get(index - 1)
-                .is_none_or(|block| block.is_none())
-        {
-            positions.extend_from_slice(&[
-                [x, y, z],
-                [x, y, z + 1.],
-                [x, y + 1., z + 1.],
-                [x, y + 1., z],
-            ]);
-            normals.extend_from_slice(&[
-                [-1.0, 0.0, 0.0],
-                [-1.0, 0.0, 0.0],
-                [-1.0, 0.0, 0.0],
-                [-1.0, 0.0, 0.0],
-            ]);
-            indices.extend_from_slice(&[
-                indices_offset,
-                indices_offset + 1,
-                indices_offset + 3,
-                indices_offset + 1,
-                indices_offset + 2,
-                indices_offset + 3,
-            ]);
-            uv_0.extend_from_slice(&[
-                [atlas_rect.min.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.max.y],
-                [atlas_rect.min.x, atlas_rect.max.y],
-            ]);
-            indices_offset += 4;
-        }
-        // BACK FACE
-        if index / Z_INDEX_USIZE != SIZE_USIZE - 1
-            && chunk
-                .upgrade()?
-                .read()
-                .expect("Chunk rw poisoned")
-                .contents
-                .get(index + Z_INDEX_USIZE)
-                .is_none_or(|block| block.is_none())
-        {
-            positions.extend_from_slice(&[
-                [x, y, z + 1.],
-                [x, y + 1., z + 1.],
-                [x + 1., y + 1., z + 1.],
-                [x + 1., y, z + 1.],
-            ]);
-            normals.extend_from_slice(&[
-                [0.0, 0.0, 1.0],
-                [0.0, 0.0, 1.0],
-                [0.0, 0.0, 1.0],
-                [0.0, 0.0, 1.0],
-            ]);
-            indices.extend_from_slice(&[
-                indices_offset,
-                indices_offset + 3,
-                indices_offset + 1,
-                indices_offset + 1,
-                indices_offset + 3,
-                indices_offset + 2,
-            ]);
-            uv_0.extend_from_slice(&[
-                [atlas_rect.min.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.max.y],
-                [atlas_rect.min.x, atlas_rect.max.y],
-            ]);
-            indices_offset += 4;
-        }
-        // FRONT FACE
-        if index / Z_INDEX_USIZE != 0
-            && chunk
-                .upgrade()?
-                .read()
-                .expect("Chunk rw poisoned")
-                .contents
-                .get(index - Z_INDEX_USIZE)
-                .is_none_or(|block| block.is_none())
-        {
-            positions.extend_from_slice(&[
-                [x, y, z],
-                [x, y + 1., z],
-                [x + 1., y + 1., z],
-                [x + 1., y, z],
-            ]);
-            normals.extend_from_slice(&[
-                [0.0, 0.0, -1.0],
-                [0.0, 0.0, -1.0],
-                [0.0, 0.0, -1.0],
-                [0.0, 0.0, -1.0],
-            ]);
-            indices.extend_from_slice(&[
-                indices_offset,
-                indices_offset + 1,
-                indices_offset + 3,
-                indices_offset + 1,
-                indices_offset + 2,
-                indices_offset + 3,
-            ]);
-            uv_0.extend_from_slice(&[
-                [atlas_rect.min.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.min.y],
-                [atlas_rect.max.x, atlas_rect.max.y],
-                [atlas_rect.min.x, atlas_rect.max.y],
-            ]);
-            indices_offset += 4;
-        }
-    }
-
-    if indices.is_empty() {
-        return Some(None);
-    }
-
-    Some(Some(
-        Mesh::new(
-            PrimitiveTopology::TriangleList,
-            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    /// Normal, texture, whether the corners are listed clockwise, corners and UV corners of each face of the block at `x`, `y`, `z`
+    /// as the mesher wrote them out by hand before [`MeshBuffers::push_face`], in the order it pushed them<br>
+    /// UV corners are whether they sit on the far edge of the tile along x and y
+    #[allow(clippy::type_complexity)]
+    fn hand_written_faces(
+        x: f32,
+        y: f32,
+        z: f32,
+    ) -> [(IVec3, BlockFace, bool, [[f32; 3]; 4], [[bool; 2]; 4]); 6] {
+        let flat = [[false, false], [true, false], [true, true], [false, true]];
+        let east_west = [[false, true], [true, true], [true, false], [false, false]];
+        let north_south = [[false, true], [false, false], [true, false], [true, true]];
+        [
+            (
+                IVec3::Y,
+                BlockFace::Top,
+                true,
+                [
+                    [x, y + 1., z],
+                    [x + 1., y + 1., z],
+                    [x + 1., y + 1., z + 1.],
+                    [x, y + 1., z + 1.],
+                ],
+                flat,
+            ),
+            (
+                IVec3::NEG_Y,
+                BlockFace::Bottom,
+                false,
+                [
+                    [x, y, z],
+                    [x + 1., y, z],
+                    [x + 1., y, z + 1.],
+                    [x, y, z + 1.],
+                ],
+                flat,
+            ),
+            (
+                IVec3::X,
+                BlockFace::East,
+                true,
+                [
+                    [x + 1., y, z],
+                    [x + 1., y, z + 1.],
+                    [x + 1., y + 1., z + 1.],
+                    [x + 1., y + 1., z],
+                ],
+                east_west,
+            ),
+            (
+                IVec3::NEG_X,
+                BlockFace::West,
+                false,
+                [
+                    [x, y, z],
+                    [x, y, z + 1.],
+                    [x, y + 1., z + 1.],
+                    [x, y + 1., z],
+                ],
+                east_west,
+            ),
+            (
+                IVec3::Z,
+                BlockFace::South,
+                true,
+                [
+                    [x, y, z + 1.],
+                    [x, y + 1., z + 1.],
+                    [x + 1., y + 1., z + 1.],
+                    [x + 1., y, z + 1.],
+                ],
+                north_south,
+            ),
+            (
+                IVec3::NEG_Z,
+                BlockFace::North,
+                false,
+                [
+                    [x, y, z],
+                    [x, y + 1., z],
+                    [x + 1., y + 1., z],
+                    [x + 1., y, z],
+                ],
+                north_south,
+            ),
+        ]
+    }
+
+    /// The per face mesher as it was before the occupancy bitmask, [`MeshBuffers::push_face`] and counting faces up front,
+    /// culling against the blocks themselves and growing its buffers face by face
+    fn reference_mesh(
+        chunk: &Chunk,
+        neighbors: &[&Chunk],
+        atlas: &AtlasManager,
+        light: &LightVolume,
+        ambient_occlusion: bool,
+    ) -> ChunkMeshes {
+        let mut layers = LayeredBuffers::default();
+        for (position, block) in chunk.iter_blocks() {
+            let position = position.as_ivec3();
+            let properties = atlas.properties(&block.identifier);
+            let emission = atlas.emission(&block.identifier);
+            let buffers = layers.for_block(atlas, &block.identifier);
+            let [x, y, z] = position.as_vec3().to_array();
+            for (normal, face, clockwise, corners, uv_corners) in hand_written_faces(x, y, z) {
+                let facing = position + normal;
+                if let Some(neighbor) = block_at(chunk, neighbors, facing) {
+                    if atlas.properties(&neighbor.identifier).opaque
+                        || neighbor.identifier == block.identifier
+                        || (properties.fluid && normal == IVec3::Y)
+                    {
+                        continue;
+                    }
+                }
+
+                let ao = if ambient_occlusion && emission.is_none() {
+                    corners.map(|corner| {
+                        vertex_ao(
+                            |position| block_at(chunk, neighbors, position).is_some(),
+                            position,
+                            normal,
+                            Vec3::from_array(corner),
+                        )
+                    })
+                } else {
+                    [3; 4]
+                };
+                let offset = buffers.positions.len() as u32;
+                buffers.positions.extend_from_slice(&corners);
+                buffers
+                    .normals
+                    .extend_from_slice(&[normal.as_vec3().to_array(); 4]);
+                buffers.colors.extend(match emission {
+                    Some(emission) => [emission_color(emission); 4],
+                    None => ao.map(|ao| {
+                        lit_color(ao, light.block_light(facing), light.sky_light(facing))
+                    }),
+                });
+                buffers
+                    .indices
+                    .extend_from_slice(&quad_indices(offset, clockwise, ao));
+                let rect = atlas.face_atlas_location_or_error(&block.identifier, face);
+                buffers.uv_0.extend(
+                    uv_corners
+                        .map(|[far_x, far_y]| [far_x, far_y].map(|far| f32::from(u8::from(far)))),
+                );
+                buffers.uv_1.extend_from_slice(&[rect.min.to_array(); 4]);
+            }
+        }
+        layers.into_meshes()
+    }
+
+    fn copy_contents(chunk: &Chunk) -> Chunk {
+        let mut copy = Chunk::new(chunk.position);
+        copy.contents = chunk.contents.clone();
+        copy
+    }
+
+    /// `chunk` meshed by [`build_mesh`] and by [`reference_mesh`], with `neighbors` around it
+    fn mesh_both(
+        chunk: Chunk,
+        neighbors: Vec<Chunk>,
+        ambient_occlusion: bool,
+    ) -> (ChunkMeshes, ChunkMeshes) {
+        let atlas = Arc::new(atlas_manager());
+        let position = chunk.position;
+        let chunk = Arc::new(RwLock::new(chunk));
+        let neighbors = neighbors
+            .into_iter()
+            .map(|neighbor| Arc::new(RwLock::new(neighbor)))
+            .collect::<Vec<_>>();
+        let chunk_neighbors: ChunkNeighbors = NEIGHBORHOOD_OFFSETS.map(|offset| {
+            neighbors
+                .iter()
+                .find(|neighbor| read_chunk(neighbor).position == position + offset)
+                .map(Arc::downgrade)
+        });
+        let light =
+            LightVolume::propagate(&Arc::downgrade(&chunk), &chunk_neighbors, &atlas).unwrap();
+
+        let meshes = build_mesh(
+            Arc::downgrade(&chunk),
+            chunk_neighbors,
+            Arc::downgrade(&atlas),
+            &light,
+            ambient_occlusion,
         )
-        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
-        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
-        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uv_0)
-        .with_inserted_indices(Indices::U32(indices)),
-    ))
+        .unwrap();
+        let neighbors = neighbors
+            .iter()
+            .map(|neighbor| read_chunk(neighbor))
+            .collect::<Vec<_>>();
+        let expected = reference_mesh(
+            &read_chunk(&chunk),
+            &neighbors
+                .iter()
+                .map(|neighbor| &**neighbor)
+                .collect::<Vec<&Chunk>>(),
+            &atlas,
+            &light,
+            ambient_occlusion,
+        );
+        (meshes, expected)
+    }
+
+    fn layers(meshes: &ChunkMeshes) -> [(&str, Option<&Mesh>); 3] {
+        [
+            ("opaque", meshes.opaque.as_ref()),
+            ("transparent", meshes.transparent.as_ref()),
+            ("emissive", meshes.emissive.as_ref()),
+        ]
+    }
+
+    /// Every attribute and index of every layer is the same, down to the bytes<br>
+    /// `case` names what was meshed in the assertion messages
+    fn assert_same_meshes(case: &str, meshes: &ChunkMeshes, expected: &ChunkMeshes) {
+        for ((name, mesh), (_, expected)) in layers(meshes).into_iter().zip(layers(expected)) {
+            let (Some(mesh), Some(expected)) = (mesh, expected) else {
+                assert_eq!(mesh.is_some(), expected.is_some(), "{case}: {name} layer");
+                continue;
+            };
+            for attribute in [
+                Mesh::ATTRIBUTE_POSITION,
+                Mesh::ATTRIBUTE_NORMAL,
+                Mesh::ATTRIBUTE_UV_0,
+                Mesh::ATTRIBUTE_UV_1,
+                Mesh::ATTRIBUTE_COLOR,
+            ] {
+                assert_eq!(
+                    mesh.attribute(attribute)
+                        .map(VertexAttributeValues::get_bytes),
+                    expected
+                        .attribute(attribute)
+                        .map(VertexAttributeValues::get_bytes),
+                    "{case}: {name} layer {}",
+                    attribute.name
+                );
+            }
+            assert_eq!(
+                mesh.indices()
+                    .map(|indices| indices.iter().collect::<Vec<usize>>()),
+                expected
+                    .indices()
+                    .map(|indices| indices.iter().collect::<Vec<usize>>()),
+                "{case}: {name} layer indices"
+            );
+        }
+    }
+
+    #[test]
+    fn push_face_matches_hand_written_faces() {
+        for ambient_occlusion in [false, true] {
+            let (meshes, expected) =
+                mesh_both(random_chunk(IVec3::ZERO, 1), Vec::new(), ambient_occlusion);
+            assert!(
+                meshes.opaque.is_some()
+                    && meshes.transparent.is_some()
+                    && meshes.emissive.is_some()
+            );
+            assert_same_meshes(
+                &format!("ambient occlusion {ambient_occlusion}"),
+                &meshes,
+                &expected,
+            );
+        }
+    }
+
+    #[test]
+    fn occupancy_culling_matches_block_lookups() {
+        let stone = identifier("stone");
+        let glass = identifier("glass");
+        let surrounded = || {
+            NEIGHBORHOOD_OFFSETS
+                .iter()
+                .enumerate()
+                .map(|(seed, offset)| random_chunk(*offset, seed as u32))
+                .collect::<Vec<_>>()
+        };
+        let cases = [
+            (
+                "checkerboard",
+                Chunk::filled_checkerboard(IVec3::ZERO, &stone),
+                Vec::new(),
+            ),
+            ("solid", Chunk::filled(IVec3::ZERO, &stone), Vec::new()),
+            (
+                "solid between stone and glass",
+                Chunk::filled(IVec3::ZERO, &stone),
+                vec![
+                    Chunk::filled(IVec3::X, &stone),
+                    Chunk::filled(IVec3::NEG_X, &glass),
+                    Chunk::filled_checkerboard(IVec3::Y, &glass),
+                ],
+            ),
+            ("random", random_chunk(IVec3::ZERO, 2), Vec::new()),
+            (
+                "random surrounded",
+                random_chunk(IVec3::ZERO, 3),
+                surrounded(),
+            ),
+            (
+                "glass surrounded",
+                Chunk::filled(IVec3::ZERO, &glass),
+                surrounded(),
+            ),
+        ];
+        for (name, chunk, neighbors) in cases {
+            for ambient_occlusion in [false, true] {
+                let (meshes, expected) = mesh_both(
+                    copy_contents(&chunk),
+                    neighbors.iter().map(copy_contents).collect(),
+                    ambient_occlusion,
+                );
+                assert_same_meshes(
+                    &format!("{name}, ambient occlusion {ambient_occlusion}"),
+                    &meshes,
+                    &expected,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn buffers_are_allocated_once() {
+        let neighbors = vec![random_chunk(IVec3::X, 5), random_chunk(IVec3::NEG_Y, 6)];
+        for ambient_occlusion in [false, true] {
+            let (meshes, expected) = mesh_both(
+                random_chunk(IVec3::ZERO, 4),
+                neighbors.iter().map(copy_contents).collect(),
+                ambient_occlusion,
+            );
+            // Same output as growing the buffers face by face
+            assert_same_meshes(
+                &format!("ambient occlusion {ambient_occlusion}"),
+                &meshes,
+                &expected,
+            );
+
+            for (name, mesh) in layers(&meshes) {
+                let mesh = mesh.unwrap();
+                let capacities = [
+                    Mesh::ATTRIBUTE_POSITION,
+                    Mesh::ATTRIBUTE_NORMAL,
+                    Mesh::ATTRIBUTE_UV_0,
+                    Mesh::ATTRIBUTE_UV_1,
+                    Mesh::ATTRIBUTE_COLOR,
+                ]
+                .map(|attribute| match mesh.attribute(attribute) {
+                    Some(VertexAttributeValues::Float32x2(values)) => {
+                        (values.len(), values.capacity())
+                    }
+                    Some(VertexAttributeValues::Float32x3(values)) => {
+                        (values.len(), values.capacity())
+                    }
+                    Some(VertexAttributeValues::Float32x4(values)) => {
+                        (values.len(), values.capacity())
+                    }
+                    _ => panic!("{name} layer is missing {}", attribute.name),
+                });
+                for (len, capacity) in capacities {
+                    assert_eq!(len, capacity, "{name} layer");
+                }
+                let Some(Indices::U32(indices)) = mesh.indices() else {
+                    panic!("{name} layer has no u32 indices");
+                };
+                assert_eq!(indices.len(), indices.capacity(), "{name} layer indices");
+            }
+        }
+    }
+
+    #[test]
+    fn ocean_has_a_single_surface() {
+        let profile = TerrainProfile {
+            sea_level: 20,
+            ..Default::default()
+        };
+        // Sampled height of (-1.2 * 10.) as i32 + 2 = -10, below the chunk
+        let generate =
+            |position| Chunk::generate(position, &Constant(-1.2), &Constant(-1.), 0.25, &profile);
+        let (meshes, expected) = mesh_both(
+            generate(IVec3::ZERO),
+            NEIGHBOR_OFFSETS.into_iter().map(generate).collect(),
+            false,
+        );
+        assert_same_meshes("ocean", &meshes, &expected);
+
+        assert!(meshes.opaque.is_none() && meshes.emissive.is_none());
+        let water = meshes.transparent.unwrap();
+        assert_eq!(water.count_vertices(), 4 * SIZE_USIZE * SIZE_USIZE);
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            water.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("Water has no normals");
+        };
+        assert!(normals.iter().all(|normal| *normal == [0., 1., 0.]));
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            water.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("Water has no positions");
+        };
+        assert!(positions.iter().all(|position| position[1] == 21.));
+    }
+
+    #[test]
+    fn greedy_merges_a_solid_chunk() {
+        let atlas = Arc::new(atlas_manager());
+        let chunk = Arc::new(RwLock::new(Chunk::filled(
+            IVec3::ZERO,
+            &identifier("stone"),
+        )));
+        let neighbors: ChunkNeighbors = NEIGHBORHOOD_OFFSETS.map(|_| None);
+        let light = LightVolume::propagate(&Arc::downgrade(&chunk), &neighbors, &atlas).unwrap();
+        let vertices = |strategy: MeshStrategy| {
+            strategy
+                .build(
+                    Arc::downgrade(&chunk),
+                    neighbors.clone(),
+                    Arc::downgrade(&atlas),
+                    &light,
+                    false,
+                )
+                .unwrap()
+                .opaque
+                .unwrap()
+                .count_vertices()
+        };
+        // Every side is lit the same, so each one merges into a single quad
+        assert_eq!(
+            vertices(MeshStrategy::PerFace),
+            6 * SIZE_USIZE * SIZE_USIZE * 4
+        );
+        assert_eq!(vertices(MeshStrategy::Greedy), 6 * 4);
+    }
+
+    #[test]
+    fn grass_top_uses_its_face_texture() {
+        let atlas = Arc::new(atlas_manager());
+        let grass = identifier("grass");
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        chunk.contents[Chunk::to_index(Chunk::to_block_coordinates(IVec3::splat(4)))] =
+            Some(Block::new(grass.clone()));
+        let chunk = Arc::new(RwLock::new(chunk));
+        let neighbors: ChunkNeighbors = NEIGHBORHOOD_OFFSETS.map(|_| None);
+        let light = LightVolume::propagate(&Arc::downgrade(&chunk), &neighbors, &atlas).unwrap();
+        let top = atlas
+            .face_atlas_location_or_error(&grass, BlockFace::Top)
+            .min
+            .to_array();
+        let side = atlas
+            .face_atlas_location_or_error(&grass, BlockFace::East)
+            .min
+            .to_array();
+        assert_ne!(top, side);
+
+        for strategy in [MeshStrategy::PerFace, MeshStrategy::Greedy] {
+            let mesh = strategy
+                .build(
+                    Arc::downgrade(&chunk),
+                    neighbors.clone(),
+                    Arc::downgrade(&atlas),
+                    &light,
+                    false,
+                )
+                .unwrap()
+                .opaque
+                .unwrap();
+            let (
+                Some(VertexAttributeValues::Float32x3(normals)),
+                Some(VertexAttributeValues::Float32x2(tiles)),
+            ) = (
+                mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+                mesh.attribute(Mesh::ATTRIBUTE_UV_1),
+            )
+            else {
+                panic!("{strategy:?} mesh has no normals or tiles");
+            };
+            assert_eq!(normals.len(), 6 * 4, "{strategy:?}");
+            for (normal, tile) in normals.iter().zip(tiles) {
+                // The bottom falls back to the block's own texture like the sides
+                let expected = if normal[1] > 0. { top } else { side };
+                assert_eq!(*tile, expected, "{strategy:?} {normal:?}");
+            }
+        }
+    }
 }
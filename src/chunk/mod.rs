@@ -1,7 +1,11 @@
-use std::sync::{Arc, RwLock};
+use std::sync::{
+    Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak,
+    atomic::{AtomicBool, Ordering},
+};
 
 use bevy::{
-    math::{I16Vec3, IVec3, Vec2, Vec3},
+    log::warn,
+    math::{I16Vec3, IVec2, IVec3, Vec2, Vec3},
     platform::collections::HashMap,
     prelude::{Deref, DerefMut},
 };
@@ -12,9 +16,12 @@ use serde::{
 };
 use serde_with::serde_as;
 
-use crate::{DEFAULT_NAMESPACE, Identifier, block::Block};
+use crate::{atlas::AtlasManager, block::Block, chunk::generation::TerrainProfile};
 
+pub mod generation;
+pub mod light;
 pub mod mesh;
+pub mod storage;
 
 pub const SIZE_I16: i16 = 32;
 pub const Z_INDEX_I16: i16 = SIZE_I16 * SIZE_I16;
@@ -25,8 +32,81 @@ pub const SIZE_F32: f32 = 32.;
 
 pub const SIZE_USIZE: usize = 32;
 pub const Z_INDEX_USIZE: usize = SIZE_USIZE * SIZE_USIZE;
+
+/// Set once a poisoned chunk lock has been reported, later ones are recovered without logging
+static CHUNK_POISON_REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Lock a chunk for reading, recovering the lock if a thread panicked while holding it<br>
+/// The chunk may then be partly modified, which is better than every later access to it panicking too
+pub fn read_chunk(chunk: &RwLock<Chunk>) -> RwLockReadGuard<'_, Chunk> {
+    chunk.read().unwrap_or_else(|error| {
+        report_poisoned_chunk();
+        error.into_inner()
+    })
+}
+
+/// Lock a chunk for writing, recovering the lock like [`read_chunk`]
+pub fn write_chunk(chunk: &RwLock<Chunk>) -> RwLockWriteGuard<'_, Chunk> {
+    chunk.write().unwrap_or_else(|error| {
+        report_poisoned_chunk();
+        error.into_inner()
+    })
+}
+
+/// Development builds still panic, so the panic that poisoned the lock isn't missed
+fn report_poisoned_chunk() {
+    if !CHUNK_POISON_REPORTED.swap(true, Ordering::Relaxed) {
+        warn!(
+            "A chunk lock was poisoned by a panic while it was held, the chunk may be partly modified"
+        );
+    }
+    if cfg!(debug_assertions) {
+        panic!("Chunk rw poisoned");
+    }
+}
+
 pub const CONTENTS_SIZE: usize = SIZE_USIZE * SIZE_USIZE * SIZE_USIZE;
 
+/// Offsets of the six chunks sharing a face with a chunk, indexed by the constants below
+pub const NEIGHBOR_OFFSETS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+/// Index of the chunk above in [`NEIGHBOR_OFFSETS`]
+pub const Y_POS: usize = 2;
+
+/// Offsets of all 26 chunks around a chunk, starting with [`NEIGHBOR_OFFSETS`] in the same order
+/// followed by the 12 chunks sharing an edge and the 8 sharing a corner
+pub const NEIGHBORHOOD_OFFSETS: [IVec3; 26] = neighborhood_offsets();
+
+const fn neighborhood_offsets() -> [IVec3; 26] {
+    let mut offsets = [IVec3::ZERO; 26];
+    let mut count = 0;
+    while count < NEIGHBOR_OFFSETS.len() {
+        offsets[count] = NEIGHBOR_OFFSETS[count];
+        count += 1;
+    }
+    let mut cell = 0;
+    while cell < 27 {
+        let offset = IVec3::new(cell % 3 - 1, cell / 3 % 3 - 1, cell / 9 - 1);
+        let axes = (offset.x != 0) as i32 + (offset.y != 0) as i32 + (offset.z != 0) as i32;
+        if axes >= 2 {
+            offsets[count] = offset;
+            count += 1;
+        }
+        cell += 1;
+    }
+    offsets
+}
+
+/// Handles to the chunks around a chunk in [`NEIGHBORHOOD_OFFSETS`] order, `None` where not loaded<br>
+/// The first six are the face neighbours, indexed like [`NEIGHBOR_OFFSETS`]
+pub type ChunkNeighbors = [Option<Weak<RwLock<Chunk>>>; 26];
+
 #[derive(Default)]
 pub struct ChunkGrid(pub HashMap<IVec3, Arc<RwLock<Chunk>>>);
 
@@ -43,15 +123,110 @@ impl ChunkGrid {
         )
     }
 
+    pub fn neighbors(&self, position: IVec3) -> ChunkNeighbors {
+        NEIGHBORHOOD_OFFSETS.map(|offset| self.0.get(&(position + offset)).map(Arc::downgrade))
+    }
+
+    /// Returns a copy of the block at world coordinates `world`, `None` if there is no block there or its chunk is not loaded<br>
+    /// Takes a read lock on the chunk for every call, blocking while a task holds its write lock.
+    /// For many blocks lock each chunk once and read it with [`Chunk::get`] instead, like [`ChunkGrid::column_to_surface`] does
+    pub fn get_block(&self, world: IVec3) -> Option<Block> {
+        read_chunk(self.0.get(&Self::to_chunk_coordinates(world.as_vec3()))?)
+            .get(Chunk::to_block_coordinates(world))
+            .cloned()
+    }
+
     /// This will block the current thread due to a call to RwLock::write()<br>
-    /// Using this function is not recommended unless you are <b>ONLY</b> setting one block
-    pub fn set_block(&self, block_coordinates: IVec3, block: Option<Block>) -> Option<()> {
-        self.0
-            .get(&Self::to_chunk_coordinates(block_coordinates.as_vec3()))?
-            .write()
-            .expect("Chunk rw poisoned")
-            .contents[Chunk::to_index(Chunk::to_block_coordinates(block_coordinates))] = block;
-        Some(())
+    /// Using this function is not recommended unless you are <b>ONLY</b> setting one block<br>
+    /// Meshes are not updated, see [`Level::set_block`](crate::level::Level::set_block) for that<br>
+    /// Returns the block that was replaced, `None` if the chunk is not loaded
+    pub fn set_block(
+        &self,
+        block_coordinates: IVec3,
+        block: Option<Block>,
+    ) -> Option<Option<Block>> {
+        let mut chunk = write_chunk(
+            self.0
+                .get(&Self::to_chunk_coordinates(block_coordinates.as_vec3()))?,
+        );
+        chunk.dirty = true;
+        Some(std::mem::replace(
+            &mut chunk.contents[Chunk::to_index(Chunk::to_block_coordinates(block_coordinates))],
+            Block::non_air(block),
+        ))
+    }
+
+    /// Positions from `start` downwards up to (but not including) the first solid block, at most `max_depth` long<br>
+    /// Non-solid blocks such as water are part of the column, solidity is looked up in `atlas_manager`<br>
+    /// Stops at the first unloaded chunk so nothing is placed into ungenerated space<br>
+    /// Only a single read lock is taken per chunk the column passes through
+    pub fn column_to_surface(
+        &self,
+        start: IVec3,
+        max_depth: u32,
+        atlas_manager: &AtlasManager,
+    ) -> Vec<IVec3> {
+        let max_depth = max_depth as usize;
+        let mut column = Vec::new();
+        let mut position = start;
+        while column.len() < max_depth {
+            let chunk_position = Self::to_chunk_coordinates(position.as_vec3());
+            let Some(chunk) = self.0.get(&chunk_position) else {
+                break;
+            };
+            let chunk = read_chunk(chunk);
+            while column.len() < max_depth
+                && Self::to_chunk_coordinates(position.as_vec3()) == chunk_position
+            {
+                if chunk.contents[Chunk::to_index(Chunk::to_block_coordinates(position))]
+                    .as_ref()
+                    .is_some_and(|block| atlas_manager.properties(&block.identifier).solid)
+                {
+                    return column;
+                }
+                column.push(position);
+                position.y -= 1;
+            }
+        }
+        column
+    }
+
+    /// Set multiple blocks while only taking a single write lock per affected chunk<br>
+    /// Returns the position of every modified chunk once so they can be remeshed together<br>
+    /// The index into `edits` of every edit that was made is pushed onto `replaced` along with the block it replaced<br>
+    /// Edits that fall inside chunks which are not loaded are skipped
+    pub fn set_blocks(
+        &self,
+        edits: &[(IVec3, Option<Block>)],
+        replaced: &mut Vec<(usize, Option<Block>)>,
+    ) -> Vec<IVec3> {
+        let mut chunk_edits = HashMap::<IVec3, Vec<usize>>::default();
+        for (edit, (block_coordinates, _)) in edits.iter().enumerate() {
+            chunk_edits
+                .entry(Self::to_chunk_coordinates(block_coordinates.as_vec3()))
+                .or_default()
+                .push(edit);
+        }
+
+        chunk_edits
+            .into_iter()
+            .filter_map(|(chunk_position, chunk_edits)| {
+                let mut chunk = write_chunk(self.0.get(&chunk_position)?);
+                chunk.dirty = true;
+                for edit in chunk_edits {
+                    let (block_coordinates, block) = &edits[edit];
+                    let index = Chunk::to_index(Chunk::to_block_coordinates(*block_coordinates));
+                    replaced.push((
+                        edit,
+                        std::mem::replace(
+                            &mut chunk.contents[index],
+                            Block::non_air(block.clone()),
+                        ),
+                    ));
+                }
+                Some(chunk_position)
+            })
+            .collect()
     }
 }
 
@@ -60,6 +235,26 @@ pub struct Chunk {
     #[serde(skip)]
     pub position: IVec3,
     pub contents: SerializableChunkContents,
+    /// Set by block edits after generation or loading<br>
+    /// Clean chunks are not saved when unloaded since they can be regenerated from the seed
+    #[serde(skip)]
+    pub dirty: bool,
+    /// [`content_hash`](Chunk::content_hash) of the contents as they are saved on disk, `None` if the chunk was never saved<br>
+    /// A dirty chunk whose contents were changed back is then not written again
+    #[serde(skip)]
+    pub saved_hash: Option<u64>,
+    /// Block light of each block from 0 to [`MAX_LIGHT`](light::MAX_LIGHT), indexed like `contents`<br>
+    /// Empty until the chunk is first meshed, as that is where light is propagated
+    #[serde(skip)]
+    pub block_light: Vec<u8>,
+    /// Sky light of each block, indexed like `contents`, see [`propagate_sky_light`](light::propagate_sky_light)<br>
+    /// Set by generation and relit when meshed, empty for loaded chunks until they are meshed
+    #[serde(skip)]
+    pub sky_light: Vec<u8>,
+    /// Sky light entering the top of each column, indexed by `x + z * SIZE`<br>
+    /// Kept so a chunk can be relit while the chunk above it isn't loaded
+    #[serde(skip)]
+    pub sky_entry: Vec<u8>,
 }
 
 impl Chunk {
@@ -67,38 +262,77 @@ impl Chunk {
         Self {
             position,
             contents: SerializableChunkContents::default(),
+            dirty: false,
+            saved_hash: None,
+            block_light: Vec::new(),
+            sky_light: Vec::new(),
+            sky_entry: Vec::new(),
         }
     }
 
     // In future may be moved somewhere else and may potentially be split into multiple functions
-    pub fn generate(position: IVec3, noise: &impl SampleableFor<Vec2, f32>) -> Self {
+    /// Cave carving happens before layers are assigned so cave walls below the subsurface are always the base block
+    pub fn generate(
+        position: IVec3,
+        noise: &impl SampleableFor<Vec2, f32>,
+        cave_noise: &impl SampleableFor<Vec3, f32>,
+        cave_threshold: f32,
+        profile: &TerrainProfile,
+    ) -> Self {
         let mut chunk = Self::new(position);
+        chunk.sky_entry = vec![0; SIZE_USIZE * SIZE_USIZE];
 
         for x in 0..SIZE_I32 {
             let raw_x = position.x * SIZE_I32 + x;
             for z in 0..SIZE_I32 {
                 let raw_z = position.z * SIZE_I32 + z;
-                let sample: f32 = noise.sample(Vec2::new(raw_x as f32, raw_z as f32));
-                let height = (sample * 10.) as i32 + 2;
-                if height < position.y * SIZE_I32 {
+                let height = Self::terrain_height(noise, IVec2::new(raw_x, raw_z));
+                // Caves never reach the surface, so a column is only lit if the surface is within or below the chunk
+                if height < (position.y + 1) * SIZE_I32 {
+                    chunk.sky_entry[(x + z * SIZE_I32) as usize] = light::MAX_LIGHT;
+                }
+                // Chunks above the surface and the sea stay empty
+                if height.max(profile.sea_level) < position.y * SIZE_I32 {
                     continue;
                 }
 
-                chunk.set_area(
-                    I16Vec3::new(x as i16, 0, z as i16),
-                    I16Vec3::new(
-                        x as i16,
-                        (height + position.y.abs() * SIZE_I32).min(SIZE_I32 - 1) as i16,
-                        z as i16,
-                    ),
-                    &Block::new(Identifier::new(DEFAULT_NAMESPACE, "stone")),
-                );
+                for y in 0..SIZE_I32 {
+                    let raw_y = position.y * SIZE_I32 + y;
+                    if raw_y > height {
+                        if raw_y > profile.sea_level {
+                            break;
+                        }
+                        chunk.contents
+                            [Self::to_index(I16Vec3::new(x as i16, y as i16, z as i16))] =
+                            Some(Block::new(profile.sea.clone()));
+                        continue;
+                    }
+                    // Only carve beneath the surface so caves don't remove terrain above the heightmap
+                    if raw_y < height {
+                        let cave_sample: f32 =
+                            cave_noise.sample(Vec3::new(raw_x as f32, raw_y as f32, raw_z as f32));
+                        if cave_sample > cave_threshold {
+                            continue;
+                        }
+                    }
+                    chunk.contents[Self::to_index(I16Vec3::new(x as i16, y as i16, z as i16))] =
+                        Some(Block::new(profile.block_at_depth(height - raw_y).clone()));
+                }
             }
         }
+        // Generated terrain is entirely opaque, water is treated as opaque too until the chunk is relit when meshed
+        chunk.sky_light =
+            light::propagate_sky_light(&chunk, &chunk.sky_entry, |_| light::MAX_LIGHT);
 
         chunk
     }
 
+    /// World y of the surface block of the world column `column` in terrain generated from `noise`
+    pub fn terrain_height(noise: &impl SampleableFor<Vec2, f32>, column: IVec2) -> i32 {
+        let sample: f32 = noise.sample(column.as_vec2());
+        (sample * 10.) as i32 + 2
+    }
+
     pub fn to_block_coord(raw_coordinate: i32) -> i16 {
         let block_coord = raw_coordinate % SIZE_I32;
         if block_coord >= 0 {
@@ -124,10 +358,49 @@ impl Chunk {
         ))
     }
 
+    /// `position` must be inside of the chunk, see [`Chunk::try_to_index`] for positions that may not be
     pub fn to_index(position: I16Vec3) -> usize {
+        debug_assert!(
+            Self::contains_local(position),
+            "Local position {position} is outside of the chunk"
+        );
         (position.x + position.y * SIZE_I16 + position.z * Z_INDEX_I16) as usize
     }
 
+    /// Returns `None` if `position` is outside of the chunk rather than an index of another block or past the end of the contents
+    pub fn try_to_index(position: I16Vec3) -> Option<usize> {
+        Self::contains_local(position).then(|| Self::to_index(position))
+    }
+
+    /// Whether a local block coordinate lies within `0..SIZE` on every axis
+    pub fn contains_local(position: I16Vec3) -> bool {
+        position.cmpge(I16Vec3::ZERO).all() && position.cmplt(I16Vec3::splat(SIZE_I16)).all()
+    }
+
+    /// Every block in the chunk with its local coordinates, in index order, empty positions being skipped
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (I16Vec3, &Block)> {
+        self.contents
+            .iter()
+            .enumerate()
+            .filter_map(|(index, block)| {
+                let block = block.as_ref()?;
+                Some((Self::to_block_coordinates_from_index(index)?, block))
+            })
+    }
+
+    /// Returns `None` if `local` is outside of the chunk or there is no block at that position
+    pub fn get(&self, local: I16Vec3) -> Option<&Block> {
+        self.contents[Self::try_to_index(local)?].as_ref()
+    }
+
+    /// Returns `None` if `local` is outside of the chunk or there is no block at that position<br>
+    /// Marks the chunk dirty since the block may be modified through the returned reference
+    pub fn get_mut(&mut self, local: I16Vec3) -> Option<&mut Block> {
+        let index = Self::try_to_index(local)?;
+        self.dirty = true;
+        self.contents[index].as_mut()
+    }
+
     pub fn set_area(&mut self, start: I16Vec3, end: I16Vec3, block: &Block) {
         for x in start.x..=end.x {
             for y in start.y..=end.y {
@@ -173,9 +446,18 @@ impl<'de> Deserialize<'de> for SerializableChunkContents {
                 let mut blocks = SerializableChunkContents::default();
                 for i in 0..CONTENTS_SIZE {
                     let Some(block) = seq.next_element()? else {
-                        break;
+                        return Err(de::Error::invalid_length(i, &self));
                     };
-                    blocks[i] = block;
+                    // Saves may store air explicitly
+                    blocks[i] = Block::non_air(block);
+                }
+                // Counted so the error reports the real length of a sequence that is too long
+                let mut length = CONTENTS_SIZE;
+                while seq.next_element::<de::IgnoredAny>()?.is_some() {
+                    length += 1;
+                }
+                if length != CONTENTS_SIZE {
+                    return Err(de::Error::invalid_length(length, &self));
                 }
 
                 Ok(blocks)
@@ -185,3 +467,332 @@ impl<'de> Deserialize<'de> for SerializableChunkContents {
         deserializer.deserialize_seq(BlockVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_NAMESPACE, Identifier};
+
+    #[test]
+    fn try_to_index_in_range() {
+        assert_eq!(Chunk::try_to_index(I16Vec3::ZERO), Some(0));
+        assert_eq!(Chunk::try_to_index(I16Vec3::new(1, 0, 0)), Some(1));
+        assert_eq!(Chunk::try_to_index(I16Vec3::new(0, 1, 0)), Some(SIZE_USIZE));
+        assert_eq!(
+            Chunk::try_to_index(I16Vec3::new(0, 0, 1)),
+            Some(Z_INDEX_USIZE)
+        );
+        assert_eq!(
+            Chunk::try_to_index(I16Vec3::new(3, 5, 7)),
+            Some(3 + 5 * SIZE_USIZE + 7 * Z_INDEX_USIZE)
+        );
+    }
+
+    #[test]
+    fn try_to_index_at_edges() {
+        let last = SIZE_I16 - 1;
+        for axis in 0..3 {
+            let mut position = I16Vec3::ZERO;
+            position[axis] = last;
+            assert!(Chunk::try_to_index(position).is_some(), "{position}");
+        }
+        assert_eq!(
+            Chunk::try_to_index(I16Vec3::splat(last)),
+            Some(CONTENTS_SIZE - 1)
+        );
+    }
+
+    #[test]
+    fn try_to_index_out_of_range() {
+        for axis in 0..3 {
+            for outside in [SIZE_I16, -1, i16::MIN, i16::MAX] {
+                let mut position = I16Vec3::ZERO;
+                position[axis] = outside;
+                assert_eq!(Chunk::try_to_index(position), None, "{position}");
+            }
+        }
+        // Would alias an index inside the contents if only the final index was checked
+        assert_eq!(Chunk::try_to_index(I16Vec3::new(SIZE_I16, -1, 0)), None);
+        assert_eq!(Chunk::try_to_index(I16Vec3::new(-1, 1, 0)), None);
+    }
+
+    #[test]
+    fn index_round_trips() {
+        for index in 0..CONTENTS_SIZE {
+            let position = Chunk::to_block_coordinates_from_index(index).unwrap();
+            assert!(Chunk::contains_local(position), "{index} -> {position}");
+            assert_eq!(Chunk::try_to_index(position), Some(index));
+        }
+        assert_eq!(Chunk::to_block_coordinates_from_index(usize::MAX), None);
+    }
+
+    #[test]
+    fn chunk_coordinates() {
+        assert_eq!(ChunkGrid::to_chunk_coord(0.), 0);
+        assert_eq!(ChunkGrid::to_chunk_coord(31.9), 0);
+        assert_eq!(ChunkGrid::to_chunk_coord(32.), 1);
+        assert_eq!(ChunkGrid::to_chunk_coord(64.), 2);
+        assert_eq!(ChunkGrid::to_chunk_coord(-0.1), -1);
+        assert_eq!(ChunkGrid::to_chunk_coord(-32.), -1);
+        assert_eq!(ChunkGrid::to_chunk_coord(-32.1), -2);
+        assert_eq!(ChunkGrid::to_chunk_coord(-64.), -2);
+        assert_eq!(
+            ChunkGrid::to_chunk_coordinates(Vec3::new(-1., 32., -64.)),
+            IVec3::new(-1, 1, -2)
+        );
+    }
+
+    /// JSON array of `length` empty blocks
+    fn contents_json(length: usize) -> String {
+        format!("[{}]", vec!["null"; length].join(","))
+    }
+
+    #[test]
+    fn contents_length_is_checked() {
+        assert!(
+            serde_json::from_str::<SerializableChunkContents>(&contents_json(CONTENTS_SIZE))
+                .is_ok()
+        );
+        for length in [CONTENTS_SIZE - 1, CONTENTS_SIZE + 1] {
+            let Err(error) =
+                serde_json::from_str::<SerializableChunkContents>(&contents_json(length))
+            else {
+                panic!("{length} blocks were accepted");
+            };
+            let expected = <serde_json::Error as de::Error>::invalid_length(
+                length,
+                &format!("array of size {CONTENTS_SIZE}").as_str(),
+            );
+            assert!(
+                error.to_string().starts_with(&expected.to_string()),
+                "{error}"
+            );
+        }
+    }
+
+    fn stone() -> Block {
+        Block::new(Identifier::new(DEFAULT_NAMESPACE, "stone"))
+    }
+
+    #[test]
+    fn get_in_range() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        let position = I16Vec3::new(1, SIZE_I16 - 1, 0);
+        chunk.contents[Chunk::to_index(position)] = Some(stone());
+
+        assert!(chunk.get(position).is_some());
+        assert!(chunk.get(I16Vec3::ZERO).is_none());
+        assert!(!chunk.dirty);
+        chunk.get_mut(position).unwrap().identifier = Identifier::new(DEFAULT_NAMESPACE, "dirt");
+        assert!(chunk.dirty);
+        assert_eq!(chunk.get(position).unwrap().identifier.1, "dirt");
+    }
+
+    #[test]
+    fn get_out_of_range() {
+        let mut chunk = Chunk::filled(IVec3::ZERO, &Identifier::new(DEFAULT_NAMESPACE, "stone"));
+        for position in [
+            I16Vec3::new(SIZE_I16, 0, 0),
+            I16Vec3::new(0, SIZE_I16, 0),
+            I16Vec3::new(0, 0, SIZE_I16),
+            I16Vec3::new(-1, 0, 0),
+            I16Vec3::new(0, -1, 0),
+            I16Vec3::new(0, 0, -1),
+            I16Vec3::new(SIZE_I16, -1, 0),
+        ] {
+            assert!(chunk.get(position).is_none(), "{position}");
+            assert!(chunk.get_mut(position).is_none(), "{position}");
+        }
+        assert!(!chunk.dirty);
+    }
+
+    fn grid(chunks: impl IntoIterator<Item = Chunk>) -> ChunkGrid {
+        let mut grid = ChunkGrid::default();
+        for chunk in chunks {
+            grid.0.insert(chunk.position, Arc::new(RwLock::new(chunk)));
+        }
+        grid
+    }
+
+    #[test]
+    fn set_blocks_across_chunks() {
+        let grid = grid([Chunk::new(IVec3::ZERO), Chunk::new(IVec3::X)]);
+        let edits = [
+            (IVec3::new(31, 0, 0), Some(stone())),
+            (IVec3::new(32, 0, 0), Some(stone())),
+            (IVec3::new(0, 5, 0), Some(stone())),
+            // Unloaded, skipped
+            (IVec3::new(-1, 0, 0), Some(stone())),
+        ];
+
+        let mut replaced = Vec::new();
+        let mut modified = grid.set_blocks(&edits, &mut replaced);
+        modified.sort_by_key(|position| position.x);
+        assert_eq!(modified, [IVec3::ZERO, IVec3::X]);
+        replaced.sort_by_key(|(edit, _)| *edit);
+        assert_eq!(
+            replaced
+                .iter()
+                .map(|(edit, block)| (*edit, block.is_none()))
+                .collect::<Vec<_>>(),
+            [(0, true), (1, true), (2, true)]
+        );
+        for (world, _) in &edits[..3] {
+            assert!(grid.get_block(*world).is_some(), "{world}");
+        }
+        for position in [IVec3::ZERO, IVec3::X] {
+            assert!(
+                read_chunk(grid.0.get(&position).unwrap()).dirty,
+                "{position}"
+            );
+        }
+
+        // Replaced blocks are handed back, each chunk is only reported once
+        let mut replaced = Vec::new();
+        let modified = grid.set_blocks(
+            &[
+                (IVec3::new(32, 0, 0), None),
+                (IVec3::new(1, 0, 0), Some(stone())),
+                (IVec3::new(2, 0, 0), Some(stone())),
+                (IVec3::new(-1, 0, 0), None),
+            ],
+            &mut replaced,
+        );
+        assert_eq!(modified.len(), 2);
+        replaced.sort_by_key(|(edit, _)| *edit);
+        assert_eq!(
+            replaced
+                .iter()
+                .map(|(edit, block)| (*edit, block.is_none()))
+                .collect::<Vec<_>>(),
+            [(0, false), (1, true), (2, true)]
+        );
+        assert!(grid.get_block(IVec3::new(32, 0, 0)).is_none());
+        assert!(grid.get_block(IVec3::new(1, 0, 0)).is_some());
+    }
+
+    #[test]
+    fn column_to_surface_crosses_chunks() {
+        let atlas_manager = mesh::tests::atlas_manager();
+        let water = Block::new(mesh::tests::identifier("water"));
+        let mut above = Chunk::new(IVec3::ZERO);
+        let mut below = Chunk::new(IVec3::NEG_Y);
+        below.contents[Chunk::to_index(Chunk::to_block_coordinates(IVec3::new(3, -5, 3)))] =
+            Some(stone());
+        // Water above the floor on both sides of the chunk border is filled over
+        for y in -4..=2 {
+            let chunk = if y < 0 { &mut below } else { &mut above };
+            chunk.contents[Chunk::to_index(Chunk::to_block_coordinates(IVec3::new(3, y, 3)))] =
+                Some(water.clone());
+        }
+        // Glass is solid, unlike water
+        above.contents[Chunk::to_index(Chunk::to_block_coordinates(IVec3::new(5, 4, 3)))] =
+            Some(Block::new(mesh::tests::identifier("glass")));
+        let grid = grid([above, below]);
+
+        let start = IVec3::new(3, 10, 3);
+        assert_eq!(
+            grid.column_to_surface(start, 64, &atlas_manager),
+            (-4..=10)
+                .rev()
+                .map(|y| IVec3::new(3, y, 3))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(grid.column_to_surface(start, 3, &atlas_manager).len(), 3);
+        assert!(
+            grid.column_to_surface(IVec3::new(3, -5, 3), 64, &atlas_manager)
+                .is_empty()
+        );
+        assert_eq!(
+            grid.column_to_surface(IVec3::new(5, 10, 3), 64, &atlas_manager),
+            (5..=10)
+                .rev()
+                .map(|y| IVec3::new(5, y, 3))
+                .collect::<Vec<_>>()
+        );
+        // Stops where the chunks below aren't loaded rather than running into ungenerated space
+        assert_eq!(
+            grid.column_to_surface(IVec3::new(4, 10, 3), 128, &atlas_manager)
+                .len(),
+            43
+        );
+    }
+
+    #[test]
+    fn iter_blocks_sparse() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        let placed = [
+            I16Vec3::ZERO,
+            I16Vec3::new(5, 0, 0),
+            I16Vec3::new(0, 17, 3),
+            I16Vec3::splat(SIZE_I16 - 1),
+        ];
+        for position in placed {
+            chunk.contents[Chunk::to_index(position)] = Some(stone());
+        }
+
+        let yielded = chunk
+            .iter_blocks()
+            .map(|(position, _)| position)
+            .collect::<Vec<_>>();
+        let mut expected = placed.to_vec();
+        expected.sort_by_key(|position| Chunk::to_index(*position));
+        assert_eq!(yielded, expected);
+        assert_eq!(Chunk::new(IVec3::ZERO).iter_blocks().count(), 0);
+    }
+
+    /// Noise returning the same sample everywhere
+    pub(super) struct Constant(pub(super) f32);
+
+    impl SampleableFor<Vec2, f32> for Constant {
+        fn sample(&self, _: Vec2) -> f32 {
+            self.0
+        }
+    }
+
+    impl SampleableFor<Vec3, f32> for Constant {
+        fn sample(&self, _: Vec3) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn generate_tall_column() {
+        // Sampled height of (6.85 * 10.) as i32 + 2 = 70
+        let (noise, caves) = (Constant(6.85), Constant(-1.));
+        let profile = TerrainProfile::default();
+        let chunks = (0..=3)
+            .map(|y| Chunk::generate(IVec3::new(0, y, 0), &noise, &caves, 0.25, &profile))
+            .collect::<Vec<_>>();
+
+        let top = SIZE_I16 - 1;
+        for chunk in &chunks[..2] {
+            let block = chunk.get(I16Vec3::new(0, top, 0)).unwrap();
+            assert_ne!(block.identifier, profile.surface, "{}", chunk.position);
+        }
+        // World y 70 is local y 6 of chunk y=2
+        let surface = chunks[2].get(I16Vec3::new(0, 6, 0)).unwrap();
+        assert_eq!(surface.identifier, profile.surface);
+        assert!(chunks[2].get(I16Vec3::new(0, 7, 0)).is_none());
+        assert_eq!(chunks[2].iter_blocks().count(), 7 * SIZE_USIZE * SIZE_USIZE);
+        assert_eq!(chunks[3].iter_blocks().count(), 0);
+    }
+
+    #[test]
+    fn caves_carve_below_surface() {
+        // Sampled height of (1.05 * 10.) as i32 + 2 = 12
+        let noise = Constant(1.05);
+        let profile = TerrainProfile::default();
+        let solid = Chunk::generate(IVec3::ZERO, &noise, &Constant(0.), 0.25, &profile);
+        let carved = Chunk::generate(IVec3::ZERO, &noise, &Constant(0.), -1., &profile);
+
+        assert_eq!(solid.iter_blocks().count(), 13 * SIZE_USIZE * SIZE_USIZE);
+        // Only the surface is left, caves never open above the terrain
+        assert_eq!(carved.iter_blocks().count(), SIZE_USIZE * SIZE_USIZE);
+        assert!(
+            carved
+                .iter_blocks()
+                .all(|(position, block)| position.y == 12 && block.identifier == profile.surface)
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use bevy::math::IVec3;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+
+use crate::{
+    Identifier,
+    block::Block,
+    chunk::{CONTENTS_SIZE, Chunk},
+};
+
+const BINARY_MAGIC: &[u8; 4] = b"VXCH";
+const BINARY_VERSION: u8 = 2;
+/// Set on a region entry's format tag when the serialized chunk is gzip compressed
+const GZIP_FLAG: u8 = 0x80;
+
+/// On-disk encoding used when saving chunks<br>
+/// Detected by a tag inside region files, or by file extension for loose chunk files from older saves
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// Human readable, kept so saves from before the binary format still open
+    Json,
+    #[default]
+    Binary,
+}
+
+impl SaveFormat {
+    /// Every format in the order they are tried when loading
+    pub const ALL: [Self; 2] = [Self::Binary, Self::Json];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Binary => "chunk",
+        }
+    }
+
+    /// Marks which format a chunk inside a region file was written with
+    pub fn tag(self) -> u8 {
+        match self {
+            Self::Json => 0,
+            Self::Binary => 1,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.tag() == tag)
+    }
+
+    /// Serializes and gzip compresses the chunk, prefixed by its tag, for storing in a region file
+    pub fn encode_entry(self, chunk: &Chunk) -> Result<Vec<u8>, ChunkFormatError> {
+        let serialized_chunk = self.serialize(chunk)?;
+        let mut encoder = GzEncoder::new(vec![self.tag() | GZIP_FLAG], Compression::default());
+        encoder
+            .write_all(&serialized_chunk)
+            .map_err(ChunkFormatError::Io)?;
+        encoder.finish().map_err(ChunkFormatError::Io)
+    }
+
+    /// Reads an entry written by [`SaveFormat::encode_entry`], or an uncompressed one from before compression
+    pub fn decode_entry(data: &[u8]) -> Result<Chunk, ChunkFormatError> {
+        let Some((&tag, serialized_chunk)) = data.split_first() else {
+            return Err(ChunkFormatError::UnexpectedEnd);
+        };
+        let format = Self::from_tag(tag & !GZIP_FLAG).ok_or(ChunkFormatError::UnknownTag(tag))?;
+        if tag & GZIP_FLAG == 0 {
+            return format.deserialize(serialized_chunk);
+        }
+        let mut decompressed = Vec::new();
+        GzDecoder::new(serialized_chunk)
+            .read_to_end(&mut decompressed)
+            .map_err(ChunkFormatError::Io)?;
+        format.deserialize(&decompressed)
+    }
+
+    pub fn serialize(self, chunk: &Chunk) -> Result<Vec<u8>, ChunkFormatError> {
+        match self {
+            Self::Json => serde_json::to_vec(chunk).map_err(ChunkFormatError::Json),
+            Self::Binary => Ok(chunk.to_binary()),
+        }
+    }
+
+    /// The returned chunk's position is left at zero as it is not part of the saved data
+    pub fn deserialize(self, bytes: &[u8]) -> Result<Chunk, ChunkFormatError> {
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(ChunkFormatError::Json),
+            Self::Binary => Chunk::from_binary(bytes),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ChunkFormatError {
+    Json(serde_json::Error),
+    /// Includes gzip streams cut short by a crash mid-write
+    Io(io::Error),
+    UnknownTag(u8),
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    UnexpectedEnd,
+    InvalidIdentifier(String),
+    InvalidPaletteIndex(u16),
+    RunOverflow,
+}
+
+impl fmt::Display for ChunkFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(error) => write!(f, "invalid json chunk: {error}"),
+            Self::Io(error) => write!(f, "failed to decompress chunk: {error}"),
+            Self::UnknownTag(tag) => write!(f, "unknown save format tag {tag}"),
+            Self::InvalidMagic => f.write_str("not a binary chunk file"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported binary chunk version {version}")
+            }
+            Self::UnexpectedEnd => f.write_str("binary chunk ended unexpectedly"),
+            Self::InvalidIdentifier(identifier) => {
+                write!(f, "invalid block identifier \"{identifier}\"")
+            }
+            Self::InvalidPaletteIndex(index) => write!(f, "palette index {index} out of range"),
+            Self::RunOverflow => f.write_str("block runs exceed the chunk size"),
+        }
+    }
+}
+impl std::error::Error for ChunkFormatError {}
+
+impl Chunk {
+    /// Returns the distinct identifiers in the chunk and the run-length encoded index stream<br>
+    /// Index 0 is the sentinel for empty blocks, index `n` refers to `palette[n - 1]`
+    pub fn to_palette(&self) -> (Vec<Identifier>, Vec<(u16, u32)>) {
+        let mut palette: Vec<Identifier> = Vec::new();
+        let mut runs: Vec<(u16, u32)> = Vec::new();
+        for block in self.contents.iter() {
+            let index = match block {
+                None => 0,
+                Some(block) => match palette.iter().position(|entry| *entry == block.identifier) {
+                    Some(position) => position + 1,
+                    None => {
+                        palette.push(block.identifier.clone());
+                        palette.len()
+                    }
+                },
+            } as u16;
+            match runs.last_mut() {
+                Some((last_index, length)) if *last_index == index => *length += 1,
+                _ => runs.push((index, 1)),
+            }
+        }
+        (palette, runs)
+    }
+
+    /// FNV-1a hash of the palette form of the contents, the same for equal contents on every platform and version<br>
+    /// Equal contents always give the same palette since it is built in block order
+    pub fn content_hash(&self) -> u64 {
+        let fnv = |hash: u64, bytes: &[u8]| {
+            bytes.iter().fold(hash, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+        };
+        let (palette, runs) = self.to_palette();
+        let mut hash = 0xcbf29ce484222325;
+        for identifier in &palette {
+            for part in [&identifier.0, &identifier.1] {
+                // Length first so moving characters between the namespace and path changes the hash
+                hash = fnv(hash, &(part.len() as u32).to_le_bytes());
+                hash = fnv(hash, part.as_bytes());
+            }
+        }
+        for (index, length) in runs {
+            hash = fnv(hash, &index.to_le_bytes());
+            hash = fnv(hash, &length.to_le_bytes());
+        }
+        hash
+    }
+
+    /// Blocks not covered by `runs` are left empty
+    pub fn from_palette(
+        position: IVec3,
+        palette: &[Identifier],
+        runs: &[(u16, u32)],
+    ) -> Result<Self, ChunkFormatError> {
+        let mut chunk = Self::new(position);
+        let mut start = 0;
+        for &(index, length) in runs {
+            let end = start + length as usize;
+            if end > CONTENTS_SIZE {
+                return Err(ChunkFormatError::RunOverflow);
+            }
+            if index != 0 {
+                let identifier = palette
+                    .get(index as usize - 1)
+                    .ok_or(ChunkFormatError::InvalidPaletteIndex(index))?;
+                chunk.contents[start..end].fill(Some(Block::new(identifier.clone())));
+            }
+            start = end;
+        }
+        Ok(chunk)
+    }
+
+    /// Layout: magic, version, palette of identifiers, then the runs from [`Chunk::to_palette`]<br>
+    /// All integers are little endian
+    pub fn to_binary(&self) -> Vec<u8> {
+        let (palette, runs) = self.to_palette();
+
+        let mut bytes = Vec::with_capacity(BINARY_MAGIC.len() + 7 + runs.len() * 6);
+        bytes.extend_from_slice(BINARY_MAGIC);
+        bytes.push(BINARY_VERSION);
+        bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+        for identifier in palette {
+            let identifier = identifier.to_string();
+            bytes.extend_from_slice(&(identifier.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(identifier.as_bytes());
+        }
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for (index, length) in runs {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, ChunkFormatError> {
+        let mut reader = ByteReader(bytes);
+        if reader.take(BINARY_MAGIC.len())? != BINARY_MAGIC {
+            return Err(ChunkFormatError::InvalidMagic);
+        }
+        let version = reader.u8()?;
+        if !(1..=BINARY_VERSION).contains(&version) {
+            return Err(ChunkFormatError::UnsupportedVersion(version));
+        }
+
+        let palette_length = reader.u16()?;
+        let mut palette = Vec::with_capacity(palette_length as usize);
+        for _ in 0..palette_length {
+            let length = reader.u16()? as usize;
+            let raw = String::from_utf8_lossy(reader.take(length)?);
+            let identifier = Identifier::parse(&raw)
+                .map_err(|_| ChunkFormatError::InvalidIdentifier(raw.into_owned()))?;
+            palette.push(identifier);
+        }
+
+        let runs = if version == 1 {
+            // Version 1 stored one index per block without run-length encoding
+            (0..CONTENTS_SIZE)
+                .map(|_| Ok((reader.u16()?, 1)))
+                .collect::<Result<Vec<_>, ChunkFormatError>>()?
+        } else {
+            let run_count = reader.u32()?;
+            (0..run_count)
+                .map(|_| Ok((reader.u16()?, reader.u32()?)))
+                .collect::<Result<Vec<_>, ChunkFormatError>>()?
+        };
+        Self::from_palette(IVec3::ZERO, &palette, &runs)
+    }
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], ChunkFormatError> {
+        if self.0.len() < length {
+            return Err(ChunkFormatError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, ChunkFormatError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ChunkFormatError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, ChunkFormatError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::mesh::tests::random_chunk;
+
+    fn identifiers(chunk: &Chunk) -> Vec<Option<&Identifier>> {
+        chunk
+            .contents
+            .iter()
+            .map(|block| block.as_ref().map(|block| &block.identifier))
+            .collect()
+    }
+
+    #[test]
+    fn palette_round_trips() {
+        for seed in 0..4 {
+            let position = IVec3::new(seed as i32, -1, 2);
+            let chunk = random_chunk(position, seed);
+            let (palette, runs) = chunk.to_palette();
+            assert_eq!(
+                runs.iter()
+                    .map(|(_, length)| *length as usize)
+                    .sum::<usize>(),
+                CONTENTS_SIZE
+            );
+
+            let decoded = Chunk::from_palette(position, &palette, &runs).unwrap();
+            assert_eq!(decoded.position, position);
+            assert_eq!(identifiers(&decoded), identifiers(&chunk));
+            assert_eq!(decoded.content_hash(), chunk.content_hash());
+        }
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let chunk = random_chunk(IVec3::new(3, 0, -7), 11);
+        for format in SaveFormat::ALL {
+            let decoded = SaveFormat::decode_entry(&format.encode_entry(&chunk).unwrap()).unwrap();
+            assert_eq!(identifiers(&decoded), identifiers(&chunk), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn invalid_palettes_are_rejected() {
+        let palette = [Identifier::new("test", "stone")];
+        assert!(matches!(
+            Chunk::from_palette(IVec3::ZERO, &palette, &[(2, 1)]),
+            Err(ChunkFormatError::InvalidPaletteIndex(2))
+        ));
+        assert!(matches!(
+            Chunk::from_palette(IVec3::ZERO, &palette, &[(1, CONTENTS_SIZE as u32 + 1)]),
+            Err(ChunkFormatError::RunOverflow)
+        ));
+    }
+
+    #[test]
+    fn entries_are_compressed() {
+        let chunk = random_chunk(IVec3::new(-2, 1, 0), 5);
+        for format in SaveFormat::ALL {
+            let serialized = format.serialize(&chunk).unwrap();
+            let entry = format.encode_entry(&chunk).unwrap();
+            // Around 7 times smaller for binary and 25 times for json
+            assert!(entry.len() < serialized.len() / 4, "{format:?}");
+
+            // Entries written before compression are still read
+            let mut uncompressed = vec![format.tag()];
+            uncompressed.extend(serialized);
+            let decoded = SaveFormat::decode_entry(&uncompressed).unwrap();
+            assert_eq!(identifiers(&decoded), identifiers(&chunk), "{format:?}");
+        }
+    }
+}
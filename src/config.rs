@@ -0,0 +1,224 @@
+use std::{fmt, fs, io, path::Path};
+
+use bevy::{
+    ecs::{
+        resource::Resource,
+        system::{Query, Res, ResMut},
+    },
+    input::keyboard::KeyCode,
+    log::warn,
+    platform::collections::HashMap,
+    time::Time,
+};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    GameSettings, MAX_FOV_DEGREES, MAX_REACH, MIN_FOV_DEGREES,
+    camera_control::MovableCamera,
+    chunk::mesh::MeshStrategy,
+    input::{Action, InputBindings},
+};
+
+pub const CONFIG_PATH: &str = "save/config.ron";
+/// Seconds the settings have to stay unchanged before they are written, so holding down a key doesn't write every frame
+const SAVE_DELAY: f32 = 1.;
+
+/// Settings kept between launches<br>
+/// Fields missing from the file keep their default
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub horizontal_render_distance: i32,
+    pub vertical_render_distance: i32,
+    pub fov_degrees: f32,
+    pub zoom_fov_degrees: f32,
+    pub camera_speed: f32,
+    pub camera_sensitivity: f32,
+    pub camera_acceleration: f32,
+    pub camera_damping: f32,
+    pub mesh_strategy: MeshStrategy,
+    pub reach: f32,
+    pub bindings: HashMap<Action, KeyCode>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let settings = GameSettings::default();
+        let camera = MovableCamera::default();
+        Self {
+            horizontal_render_distance: settings.horizontal_render_distance,
+            vertical_render_distance: settings.vertical_render_distance,
+            fov_degrees: settings.fov_degrees,
+            zoom_fov_degrees: settings.zoom_fov_degrees,
+            camera_speed: camera.speed,
+            camera_sensitivity: camera.sensitivity,
+            camera_acceleration: camera.acceleration,
+            camera_damping: camera.damping,
+            mesh_strategy: settings.mesh_strategy,
+            reach: settings.reach,
+            bindings: InputBindings::default().0,
+        }
+    }
+}
+
+impl Config {
+    /// Actions added since the file was written are given their default key
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let mut config: Self = ron::from_str(&contents).map_err(ConfigError::Parse)?;
+        for (action, key) in InputBindings::default().0 {
+            config.bindings.entry(action).or_insert(key);
+        }
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let contents = ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .map_err(ConfigError::Serialize)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(ConfigError::Io)?;
+        }
+        fs::write(path, contents).map_err(ConfigError::Io)
+    }
+
+    /// Load the config at [`CONFIG_PATH`]<br>
+    /// A missing or unreadable file is replaced with a fresh default config
+    pub fn load_or_default() -> Self {
+        let path = Path::new(CONFIG_PATH);
+        match Self::load(path) {
+            Ok(config) => return config,
+            Err(ConfigError::Io(error)) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => {
+                warn!("Config at {CONFIG_PATH} could not be loaded, using defaults: {error}");
+            }
+        }
+        let config = Self::default();
+        if let Err(error) = config.save(path) {
+            warn!("Default config could not be written to {CONFIG_PATH}: {error}");
+        }
+        config
+    }
+
+    pub fn apply(&self, settings: &mut GameSettings) {
+        settings.horizontal_render_distance = self.horizontal_render_distance;
+        settings.vertical_render_distance = self.vertical_render_distance;
+        settings.clamp_render_distances();
+        settings.fov_degrees = self.fov_degrees.clamp(MIN_FOV_DEGREES, MAX_FOV_DEGREES);
+        settings.zoom_fov_degrees = self
+            .zoom_fov_degrees
+            .clamp(MIN_FOV_DEGREES, MAX_FOV_DEGREES);
+        settings.mesh_strategy = self.mesh_strategy;
+        settings.reach = self.reach.clamp(0., MAX_REACH);
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "io error: {error}"),
+            Self::Parse(error) => write!(f, "parse error: {error}"),
+            Self::Serialize(error) => write!(f, "serialization error: {error}"),
+        }
+    }
+}
+impl std::error::Error for ConfigError {}
+
+/// The config last written to [`CONFIG_PATH`] and any change waiting to be written
+#[derive(Resource)]
+pub struct ConfigFile {
+    saved: Config,
+    /// Changed config and the time it was last seen changing
+    pending: Option<(Config, f32)>,
+}
+
+impl ConfigFile {
+    pub fn new(saved: Config) -> Self {
+        Self {
+            saved,
+            pending: None,
+        }
+    }
+
+    pub fn saved(&self) -> &Config {
+        &self.saved
+    }
+}
+
+/// Write the config once the live settings have differed from the saved ones for [`SAVE_DELAY`]
+pub fn save_config(
+    time: Res<Time>,
+    settings: Res<GameSettings>,
+    bindings: Res<InputBindings>,
+    camera_query: Query<&MovableCamera>,
+    mut config_file: ResMut<ConfigFile>,
+) {
+    let camera = camera_query.single().ok();
+    let current = Config {
+        horizontal_render_distance: settings.horizontal_render_distance,
+        vertical_render_distance: settings.vertical_render_distance,
+        fov_degrees: settings.fov_degrees,
+        zoom_fov_degrees: settings.zoom_fov_degrees,
+        // Keep what was saved until the camera is spawned
+        camera_speed: camera.map_or(config_file.saved.camera_speed, |camera| camera.speed),
+        camera_sensitivity: camera.map_or(config_file.saved.camera_sensitivity, |camera| {
+            camera.sensitivity
+        }),
+        camera_acceleration: camera.map_or(config_file.saved.camera_acceleration, |camera| {
+            camera.acceleration
+        }),
+        camera_damping: camera.map_or(config_file.saved.camera_damping, |camera| camera.damping),
+        mesh_strategy: settings.mesh_strategy,
+        reach: settings.reach,
+        bindings: bindings.0.clone(),
+    };
+    if current == config_file.saved {
+        config_file.pending = None;
+        return;
+    }
+
+    let now = time.elapsed_secs();
+    let unchanged_since = config_file
+        .pending
+        .as_ref()
+        .filter(|(pending, _)| *pending == current)
+        .map(|(_, changed_at)| *changed_at);
+    let Some(changed_at) = unchanged_since else {
+        config_file.pending = Some((current, now));
+        return;
+    };
+    if now - changed_at < SAVE_DELAY {
+        return;
+    }
+    if let Err(error) = current.save(Path::new(CONFIG_PATH)) {
+        warn!("Config could not be saved to {CONFIG_PATH}: {error}");
+    }
+    // Not retried on failure until the settings change again
+    config_file.saved = current;
+    config_file.pending = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reach_is_clamped() {
+        let mut settings = GameSettings::default();
+        for (reach, applied) in [(1000., MAX_REACH), (-3., 0.), (8., 8.)] {
+            Config {
+                reach,
+                ..Default::default()
+            }
+            .apply(&mut settings);
+            assert_eq!(settings.reach, applied);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    color::Color,
+    input::{ButtonInput, keyboard::KeyCode},
+    math::Quat,
+    pbr::{AmbientLight, DirectionalLight},
+    prelude::*,
+    time::Time,
+    transform::components::Transform,
+};
+
+use crate::{
+    GameState,
+    input::{Action, InputBindings},
+};
+
+/// Ambient brightness at midnight, kept above zero so unlit caves aren't pure black
+const NIGHT_AMBIENT_BRIGHTNESS: f32 = 40.;
+const DAY_AMBIENT_BRIGHTNESS: f32 = 300.;
+const NIGHT_AMBIENT_COLOR: Color = Color::srgb(0.55, 0.6, 1.);
+/// Tint mixed in around sunrise and sunset
+const TWILIGHT_AMBIENT_COLOR: Color = Color::srgb(1., 0.7, 0.5);
+/// Sun illuminance at noon, the sun gives no light while below the horizon
+const SUN_ILLUMINANCE: f32 = 3000.;
+/// Fraction of a day stepped by the advance and rewind keys, one hour
+const TIME_STEP: f32 = 1. / 24.;
+
+/// Current time of the day/night cycle
+#[derive(Resource)]
+pub struct TimeOfDay {
+    /// Fraction of the day from 0 to 1, 0 being midnight and 0.5 noon
+    pub time: f32,
+    /// Seconds a full day takes
+    pub day_length: f32,
+    pub paused: bool,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            time: 0.3,
+            day_length: 600.,
+            paused: false,
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// 0 at midnight rising to 1 at noon, following a cosine so it changes smoothly through midnight
+    pub fn daylight(&self) -> f32 {
+        0.5 - 0.5 * (self.time * TAU).cos()
+    }
+
+    /// Sine of the sun's angle above the horizon, negative at night
+    pub fn sun_elevation(&self) -> f32 {
+        ((self.time - 0.25) * TAU).sin()
+    }
+
+    /// Hours and minutes on a 24 hour clock
+    pub fn clock(&self) -> (u32, u32) {
+        let minutes = (self.time * 24. * 60.) as u32;
+        (minutes / 60, minutes % 60)
+    }
+
+    fn advance(&mut self, days: f32) {
+        self.time = (self.time + days).rem_euclid(1.);
+    }
+}
+
+/// Marks the directional light following the time of day
+#[derive(Component)]
+pub struct Sun;
+
+pub struct DayCyclePlugin;
+
+impl Plugin for DayCyclePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .add_systems(Startup, spawn_sun)
+            .add_systems(
+                Update,
+                (
+                    control_time_of_day,
+                    advance_time_of_day.run_if(in_state(GameState::InGame)),
+                    apply_time_of_day,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn spawn_sun(mut commands: Commands) {
+    commands.spawn((Sun, DirectionalLight::default(), Transform::default()));
+}
+
+/// Pauses the cycle or steps it forwards and backwards by an hour, T, ] and [ by default
+fn control_time_of_day(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut time_of_day: ResMut<TimeOfDay>,
+) {
+    if bindings.just_pressed(&keyboard_input, Action::PauseTimeOfDay) {
+        time_of_day.paused = !time_of_day.paused;
+    }
+    if bindings.just_pressed(&keyboard_input, Action::AdvanceTimeOfDay) {
+        time_of_day.advance(TIME_STEP);
+    }
+    if bindings.just_pressed(&keyboard_input, Action::RewindTimeOfDay) {
+        time_of_day.advance(-TIME_STEP);
+    }
+}
+
+fn advance_time_of_day(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    if time_of_day.paused {
+        return;
+    }
+    let days = time.delta_secs() / time_of_day.day_length;
+    time_of_day.advance(days);
+}
+
+fn apply_time_of_day(
+    time_of_day: Res<TimeOfDay>,
+    mut ambient_query: Query<&mut AmbientLight>,
+    sun_query: Single<(&mut DirectionalLight, &mut Transform), With<Sun>>,
+) {
+    let daylight = time_of_day.daylight();
+    // Strongest when the sun is at the horizon
+    let twilight = 1. - time_of_day.sun_elevation().abs();
+    let color = NIGHT_AMBIENT_COLOR
+        .mix(&Color::WHITE, daylight)
+        .mix(&TWILIGHT_AMBIENT_COLOR, twilight.powi(4) * 0.5);
+    for mut ambient_light in ambient_query.iter_mut() {
+        ambient_light.brightness = NIGHT_AMBIENT_BRIGHTNESS
+            + (DAY_AMBIENT_BRIGHTNESS - NIGHT_AMBIENT_BRIGHTNESS) * daylight;
+        ambient_light.color = color;
+    }
+
+    let (mut sun, mut transform) = sun_query.into_inner();
+    sun.illuminance = SUN_ILLUMINANCE * time_of_day.sun_elevation().max(0.);
+    // Shines along -Z at sunrise and straight down at noon
+    transform.rotation = Quat::from_rotation_x(-(time_of_day.time - 0.25) * TAU);
+}
@@ -0,0 +1,215 @@
+use bevy::{
+    ecs::{
+        resource::Resource,
+        system::{Query, Res, SystemParam},
+    },
+    input::{
+        ButtonInput,
+        gamepad::{Gamepad, GamepadButton},
+        keyboard::KeyCode,
+    },
+    math::Vec2,
+    platform::collections::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+/// Named actions bound to a key through [`InputBindings`]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    /// Flies up, or jumps while walking
+    MoveUp,
+    /// Flies down, does nothing while walking
+    MoveDown,
+    Sprint,
+    ToggleMovementMode,
+    PlaceBlock,
+    RemoveBlock,
+    PlaceColumn,
+    PickBlock,
+    /// While held the place and remove actions fill or clear a cube around the targeted block
+    Brush,
+    /// While held the brush also replaces blocks that aren't replaceable
+    BrushOverwrite,
+    /// Selects the block after the selected one
+    NextBlock,
+    /// Selects the block before the selected one
+    PreviousBlock,
+    ToggleRayOverlay,
+    ToggleChunkBorders,
+    IncreaseHorizontalRenderDistance,
+    DecreaseHorizontalRenderDistance,
+    IncreaseVerticalRenderDistance,
+    DecreaseVerticalRenderDistance,
+    IncreaseFov,
+    DecreaseFov,
+    /// Narrows the field of view while held
+    Zoom,
+    Pause,
+    ToggleFullscreen,
+    Screenshot,
+    ToggleMipmaps,
+    /// Switches chunk meshes between one quad per face and greedy meshing
+    ToggleMeshStrategy,
+    ToggleWireframe,
+    ReloadBlockTextures,
+    ExportChunk,
+    /// Discards the camera's chunk and its saved copy so it is generated again from the seed
+    ResetChunk,
+    /// Rebuilds the mesh of every loaded chunk
+    RemeshAll,
+    /// Opens a prompt to type coordinates to move the camera to
+    Teleport,
+    PauseTimeOfDay,
+    AdvanceTimeOfDay,
+    RewindTimeOfDay,
+}
+
+/// Key bound to each [`Action`], actions without a key can't be triggered
+#[derive(Resource)]
+pub struct InputBindings(pub HashMap<Action, KeyCode>);
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        Self(HashMap::from_iter([
+            (Action::MoveForward, KeyCode::KeyW),
+            (Action::MoveBackward, KeyCode::KeyS),
+            (Action::MoveLeft, KeyCode::KeyA),
+            (Action::MoveRight, KeyCode::KeyD),
+            (Action::MoveUp, KeyCode::Space),
+            (Action::MoveDown, KeyCode::ShiftLeft),
+            (Action::Sprint, KeyCode::ControlLeft),
+            (Action::ToggleMovementMode, KeyCode::KeyG),
+            (Action::PlaceBlock, KeyCode::KeyE),
+            (Action::RemoveBlock, KeyCode::KeyQ),
+            (Action::PlaceColumn, KeyCode::KeyV),
+            (Action::PickBlock, KeyCode::KeyF),
+            (Action::Brush, KeyCode::KeyX),
+            (Action::BrushOverwrite, KeyCode::AltLeft),
+            (Action::ToggleRayOverlay, KeyCode::KeyR),
+            (Action::ToggleChunkBorders, KeyCode::KeyB),
+            (
+                Action::IncreaseHorizontalRenderDistance,
+                KeyCode::ArrowRight,
+            ),
+            (Action::DecreaseHorizontalRenderDistance, KeyCode::ArrowLeft),
+            (Action::IncreaseVerticalRenderDistance, KeyCode::ArrowUp),
+            (Action::DecreaseVerticalRenderDistance, KeyCode::ArrowDown),
+            (Action::IncreaseFov, KeyCode::Equal),
+            (Action::DecreaseFov, KeyCode::Minus),
+            (Action::Zoom, KeyCode::KeyC),
+            (Action::Pause, KeyCode::Escape),
+            (Action::ToggleFullscreen, KeyCode::F11),
+            (Action::Screenshot, KeyCode::F2),
+            (Action::ToggleMipmaps, KeyCode::KeyM),
+            (Action::ToggleMeshStrategy, KeyCode::F7),
+            (Action::ToggleWireframe, KeyCode::KeyZ),
+            (Action::ReloadBlockTextures, KeyCode::F5),
+            (Action::ExportChunk, KeyCode::F6),
+            (Action::ResetChunk, KeyCode::F9),
+            (Action::RemeshAll, KeyCode::F10),
+            (Action::Teleport, KeyCode::KeyP),
+            (Action::PauseTimeOfDay, KeyCode::KeyT),
+            (Action::AdvanceTimeOfDay, KeyCode::BracketRight),
+            (Action::RewindTimeOfDay, KeyCode::BracketLeft),
+        ]))
+    }
+}
+
+impl InputBindings {
+    pub fn key(&self, action: Action) -> Option<KeyCode> {
+        self.0.get(&action).copied()
+    }
+
+    /// Whether the key bound to `action` is held
+    pub fn pressed(&self, input: &ButtonInput<KeyCode>, action: Action) -> bool {
+        self.key(action).is_some_and(|key| input.pressed(key))
+    }
+
+    /// Whether the key bound to `action` was pressed this frame
+    pub fn just_pressed(&self, input: &ButtonInput<KeyCode>, action: Action) -> bool {
+        self.key(action).is_some_and(|key| input.just_pressed(key))
+    }
+}
+
+/// Gamepad button bound to each [`Action`], alongside its key in [`InputBindings`]
+#[derive(Resource)]
+pub struct GamepadBindings(pub HashMap<Action, GamepadButton>);
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        Self(HashMap::from_iter([
+            (Action::MoveUp, GamepadButton::South),
+            (Action::MoveDown, GamepadButton::East),
+            (Action::Sprint, GamepadButton::LeftThumb),
+            (Action::PlaceBlock, GamepadButton::RightTrigger2),
+            (Action::RemoveBlock, GamepadButton::LeftTrigger2),
+            (Action::PickBlock, GamepadButton::North),
+            (Action::NextBlock, GamepadButton::RightTrigger),
+            (Action::PreviousBlock, GamepadButton::LeftTrigger),
+            (Action::Pause, GamepadButton::Start),
+        ]))
+    }
+}
+
+/// Stick deflection below which a stick reads as centred, so worn sticks don't drift
+const STICK_DEAD_ZONE: f32 = 0.15;
+
+/// Actions triggered from the keyboard or any connected gamepad
+#[derive(SystemParam)]
+pub struct ActionInput<'w, 's> {
+    pub keyboard: Res<'w, ButtonInput<KeyCode>>,
+    pub bindings: Res<'w, InputBindings>,
+    gamepad_bindings: Res<'w, GamepadBindings>,
+    gamepads: Query<'w, 's, &'static Gamepad>,
+}
+
+impl ActionInput<'_, '_> {
+    /// Whether the key or a gamepad button bound to `action` is held
+    pub fn pressed(&self, action: Action) -> bool {
+        self.bindings.pressed(&self.keyboard, action)
+            || self
+                .gamepad_bindings
+                .0
+                .get(&action)
+                .is_some_and(|button| self.gamepads.iter().any(|gamepad| gamepad.pressed(*button)))
+    }
+
+    /// Whether the key or a gamepad button bound to `action` was pressed this frame
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.bindings.just_pressed(&self.keyboard, action)
+            || self.gamepad_bindings.0.get(&action).is_some_and(|button| {
+                self.gamepads
+                    .iter()
+                    .any(|gamepad| gamepad.just_pressed(*button))
+            })
+    }
+
+    /// Movement stick of every gamepad combined, +Y being forwards, zero without a gamepad
+    pub fn left_stick(&self) -> Vec2 {
+        stick(self.gamepads.iter().map(Gamepad::left_stick))
+    }
+
+    /// Look stick of every gamepad combined, +Y being up, zero without a gamepad
+    pub fn right_stick(&self) -> Vec2 {
+        stick(self.gamepads.iter().map(Gamepad::right_stick))
+    }
+}
+
+/// Sum of the sticks with [`STICK_DEAD_ZONE`] removed from each, rescaled so the stick still reaches full deflection
+fn stick(sticks: impl Iterator<Item = Vec2>) -> Vec2 {
+    sticks
+        .map(|stick| {
+            let length = stick.length();
+            if length <= STICK_DEAD_ZONE {
+                return Vec2::ZERO;
+            }
+            let scaled_length = ((length - STICK_DEAD_ZONE) / (1. - STICK_DEAD_ZONE)).min(1.);
+            stick * (scaled_length / length)
+        })
+        .sum::<Vec2>()
+        .clamp_length_max(1.)
+}
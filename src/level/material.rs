@@ -0,0 +1,31 @@
+use bevy::{
+    asset::{Asset, Handle},
+    image::Image,
+    math::Vec2,
+    pbr::{ExtendedMaterial, MaterialExtension, StandardMaterial},
+    reflect::Reflect,
+    render::render_resource::{AsBindGroup, ShaderRef},
+};
+
+/// Material of chunk meshes, the standard material with its texture sampled through [`TiledAtlas`]
+pub type ChunkMaterial = ExtendedMaterial<StandardMaterial, TiledAtlas>;
+
+/// Repeats a tile of the block atlas across a face, so quads covering several blocks show the texture once per block<br>
+/// Meshes hold the position within the tile in [`Mesh::ATTRIBUTE_UV_0`](bevy::render::mesh::Mesh::ATTRIBUTE_UV_0),
+/// counted in tiles, and the corner of the tile in the atlas in [`Mesh::ATTRIBUTE_UV_1`](bevy::render::mesh::Mesh::ATTRIBUTE_UV_1)<br>
+/// The base material is left without a texture, the atlas is multiplied with its colour in the shader
+#[derive(Asset, AsBindGroup, Reflect, Clone)]
+pub struct TiledAtlas {
+    /// See [`AtlasManager::tile_size`](crate::atlas::AtlasManager::tile_size)
+    #[uniform(100)]
+    pub tile_size: Vec2,
+    #[texture(101)]
+    #[sampler(102)]
+    pub atlas: Handle<Image>,
+}
+
+impl MaterialExtension for TiledAtlas {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/tiled_atlas.wgsl".into()
+    }
+}
@@ -0,0 +1,85 @@
+use std::{fmt, fs, io};
+
+use bevy::math::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::{chunk::generation::GeneratorKind, level::timestamp};
+
+/// Settings a level was created with, stored in `save/<id>/level.json`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LevelMetadata {
+    pub seed: u32,
+    /// Seed as it was typed when the level was created, empty if none was typed or it was lost
+    pub seed_text: String,
+    pub generator: GeneratorKind,
+    /// Where the player is placed when entering the level, `None` until one has been found
+    pub spawn: Option<Vec3>,
+}
+
+impl LevelMetadata {
+    pub fn new(seed_text: &str, generator: GeneratorKind) -> Self {
+        Self {
+            seed: seed_from_text(seed_text),
+            seed_text: seed_text.trim().to_owned(),
+            generator,
+            spawn: None,
+        }
+    }
+
+    /// Best guess for a level saved before metadata existed, levels were always created with seed 0
+    pub fn reconstructed() -> Self {
+        Self {
+            seed: 0,
+            seed_text: String::new(),
+            generator: GeneratorKind::Normal,
+            spawn: None,
+        }
+    }
+
+    pub fn path(level_id: &str) -> String {
+        format!("save/{level_id}/level.json")
+    }
+
+    pub fn load(level_id: &str) -> Result<Self, MetadataError> {
+        let contents = fs::read(Self::path(level_id)).map_err(MetadataError::Io)?;
+        serde_json::from_slice(&contents).map_err(MetadataError::Json)
+    }
+
+    pub fn save(&self, level_id: &str) -> Result<(), MetadataError> {
+        let contents = serde_json::to_vec_pretty(self).map_err(MetadataError::Json)?;
+        fs::create_dir_all(format!("save/{level_id}")).map_err(MetadataError::Io)?;
+        fs::write(Self::path(level_id), contents).map_err(MetadataError::Io)
+    }
+}
+
+/// A numeric `text` is used as the seed directly, any other text is hashed into one<br>
+/// Empty text picks a seed from the current time
+pub fn seed_from_text(text: &str) -> u32 {
+    let text = text.trim();
+    if text.is_empty() {
+        return timestamp() as u32;
+    }
+    if let Ok(seed) = text.parse() {
+        return seed;
+    }
+    // FNV-1a, unlike the std hasher it is guaranteed to give the same seed on every platform and version
+    text.bytes().fold(0x811c9dc5, |hash: u32, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+#[derive(Debug)]
+pub enum MetadataError {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "io error: {error}"),
+            Self::Json(error) => write!(f, "json error: {error}"),
+        }
+    }
+}
+impl std::error::Error for MetadataError {}
@@ -1,64 +1,178 @@
 use std::{
-    fs,
+    fmt, fs,
+    io::{self, BufWriter, Write},
     ops::DerefMut,
-    sync::{Arc, Mutex, RwLock, Weak},
+    path::Path,
+    sync::{
+        Arc, Mutex, RwLock, Weak,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use bevy::{
-    app::{App, Plugin, Update},
+    app::{App, Plugin, PostUpdate, Update},
     asset::{Assets, Handle},
     color::Color,
     ecs::{
+        change_detection::DetectChangesMut,
         entity::Entity,
+        event::{Event, EventReader, EventWriter},
+        hierarchy::ChildOf,
         query::With,
         resource::Resource,
         schedule::IntoScheduleConfigs,
-        system::{Commands, Res, ResMut, Single},
+        system::{Commands, Query, Res, ResMut, Single},
     },
-    math::{IVec2, IVec3, Vec2, Vec2Swizzles},
-    pbr::{MeshMaterial3d, StandardMaterial},
+    image::Image,
+    log::{debug, error, warn},
+    math::{I16Vec3, IVec2, IVec3, Vec2, Vec3, Vec3Swizzles},
+    pbr::{MaterialPlugin, MeshMaterial3d, StandardMaterial},
     platform::collections::{HashMap, HashSet},
     render::{
+        alpha::AlphaMode,
         camera::Camera,
         mesh::{Mesh, Mesh3d},
+        view::{Visibility, VisibilitySystems},
     },
-    state::{condition::in_state, state::OnEnter},
+    state::{condition::in_state, state::OnTransition},
     tasks::{AsyncComputeTaskPool, IoTaskPool},
     transform::components::Transform,
     utils::default,
 };
 use noiz::{Noise, SampleableFor, prelude::common_noise::Perlin, rng::NoiseRng};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    GameSettings, GameState,
+    GameSettings, GameState, Identifier,
     atlas::AtlasManager,
-    block::BlockAtlasManager,
-    chunk::{self, Chunk, ChunkGrid},
+    block::{Block, BlockAtlasManager},
+    chunk::{
+        self, Chunk, ChunkGrid, ChunkNeighbors, NEIGHBOR_OFFSETS, NEIGHBORHOOD_OFFSETS,
+        generation::{
+            self, CaveProperties, GeneratorKind, OreProperties, TerrainProfile, TreeProperties,
+        },
+        light::{self, LightVolume},
+        mesh::{ChunkMeshes, MeshDetail, MeshStrategy, build_lod_mesh},
+        read_chunk,
+        storage::{ChunkFormatError, SaveFormat},
+        write_chunk,
+    },
+    in_game_or_paused,
+    level::{
+        metadata::{LevelMetadata, MetadataError},
+        region::RegionStorage,
+    },
 };
 
+mod material;
+mod metadata;
+mod region;
+
+pub use material::{ChunkMaterial, TiledAtlas};
+
 pub struct LevelPlugin;
 
 impl Plugin for LevelPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::InGame), setup_level)
+        app.add_plugins(MaterialPlugin::<ChunkMaterial>::default())
+            .add_event::<LevelSaveFailed>()
+            .add_event::<ChunkDataLost>()
+            .add_event::<BlockPlaced>()
+            .add_event::<BlockRemoved>()
+            .add_systems(
+                OnTransition {
+                    exited: GameState::MainMenu,
+                    entered: GameState::InGame,
+                },
+                setup_level,
+            )
+            // Chunks already being generated or saved are finished while paused, but no new ones are loaded
             .add_systems(
                 Update,
                 (
-                    mark_nearby_chunks_uninitialized,
+                    mark_nearby_chunks_uninitialized.run_if(in_state(GameState::InGame)),
                     finalize_chunk_generation,
                     handle_remesh_queue,
                     apply_ready_meshes,
                     remove_far_chunks,
+                    update_chunk_recency,
+                    evict_stale_chunks,
+                    unload_inactive_levels,
                     cleanup_saved_chunks,
+                    report_storage_errors,
+                    send_block_edit_events,
+                    log_block_edits,
                 )
-                    .run_if(in_state(GameState::InGame)),
+                    .chain()
+                    .run_if(in_game_or_paused),
+            )
+            // Runs before visibility is computed so a level switched away from is hidden the same frame
+            .add_systems(
+                PostUpdate,
+                hide_inactive_level_chunks
+                    .before(VisibilitySystems::CheckVisibility)
+                    .run_if(in_game_or_paused),
             );
     }
 }
 
-/// Resource from which all level data is defined and accessed
+/// Every loaded level keyed by its id, one of which is active<br>
+/// Generation, meshing and block edits only happen in the active level, the chunks of other levels are saved and unloaded
+/// by [`unload_inactive_levels`] and loaded again once they are active
 #[derive(Resource)]
+pub struct Levels {
+    levels: HashMap<String, Level>,
+    /// Always the id of a level in `levels`
+    active: String,
+}
+
+impl Levels {
+    /// Only `level`, which is active
+    fn new(level: Level) -> Self {
+        let active = level.level_properties.id.clone();
+        Self {
+            levels: HashMap::from_iter([(active.clone(), level)]),
+            active,
+        }
+    }
+
+    pub fn active(&self) -> &Level {
+        &self.levels[&self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Level {
+        self.levels
+            .get_mut(&self.active)
+            .expect("Active level not loaded")
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Level> {
+        self.levels.values_mut()
+    }
+
+    /// Make the loaded level `id` active, returns whether it is loaded<br>
+    /// Chunks of inactive levels are hidden by [`hide_inactive_level_chunks`] until they are unloaded
+    pub fn set_active(&mut self, id: &str) -> bool {
+        if !self.levels.contains_key(id) {
+            return false;
+        }
+        id.clone_into(&mut self.active);
+        true
+    }
+
+    /// Add `level` without making it active, a level already loaded with the same id is kept instead
+    fn insert(&mut self, level: Level) {
+        let id = level.level_properties.id.clone();
+        if self.levels.contains_key(&id) {
+            warn!("Level \"{id}\" is already loaded");
+            return;
+        }
+        self.levels.insert(id, level);
+    }
+}
+
+/// All data of a single level, see [`Levels`]
 pub struct Level {
     level_properties: LevelProperties,
     chunk_properties: ChunkProperties,
@@ -72,21 +186,654 @@ impl Level {
         self.mesh_properties.remesh.insert(position);
     }
 
+    /// Queue a remesh of every loaded chunk sharing a face, edge or corner with the chunk at `position`
+    fn rebuild_neighbor_meshes(&mut self, position: IVec3) {
+        for offset in NEIGHBORHOOD_OFFSETS {
+            let neighbor = position + offset;
+            if self.chunk_properties.chunk_grid.0.contains_key(&neighbor) {
+                self.rebuild_mesh(neighbor);
+            }
+        }
+    }
+
+    /// Queue a remesh of the chunk containing the block at `world`
+    /// and of every loaded chunk touching that block, across a face, edge or corner
+    fn rebuild_block_meshes(&mut self, world: IVec3) {
+        let chunk_position = ChunkGrid::to_chunk_coordinates(world.as_vec3());
+        self.rebuild_mesh(chunk_position);
+        let local = Chunk::to_block_coordinates(world).as_ivec3();
+        for offset in NEIGHBORHOOD_OFFSETS {
+            // The block has to be on the chunk's border along every axis the neighbour is offset along,
+            // blocks further in can't be culled against or occlude anything in it
+            let touches = (0..3).all(|axis| {
+                offset[axis] == 0 || !(0..chunk::SIZE_I32).contains(&(local[axis] + offset[axis]))
+            });
+            if !touches {
+                continue;
+            }
+            let neighbor = chunk_position + offset;
+            if self.chunk_properties.chunk_grid.0.contains_key(&neighbor) {
+                self.mesh_properties.remesh.insert(neighbor);
+            }
+        }
+    }
+
+    /// Queue a remesh of the neighbours meshed without the newly loaded chunk at `position` whose mesh it changes,
+    /// those with blocks on their border within a block of blocks on its border, which can cull their faces or occlude them<br>
+    /// Neighbours not meshed yet will see the chunk when they are, and a neighbour whose mesh state is locked is always queued
+    fn rebuild_meshes_bordering(&mut self, position: IVec3) {
+        let chunk_grid = &self.chunk_properties.chunk_grid.0;
+        let Some(chunk) = chunk_grid.get(&position) else {
+            return;
+        };
+        let chunk = read_chunk(chunk);
+        let mesh_states = self.mesh_properties.mesh_states.try_read().ok();
+        for offset in NEIGHBORHOOD_OFFSETS {
+            let neighbor_position = position + offset;
+            let Some(neighbor) = chunk_grid.get(&neighbor_position) else {
+                continue;
+            };
+            let meshed = self
+                .bevy_properties
+                .chunk_entities
+                .contains_key(&neighbor_position)
+                || mesh_states
+                    .as_ref()
+                    .is_none_or(|mesh_states| mesh_states.contains_key(&neighbor_position));
+            if meshed && borders_meet(&chunk, &read_chunk(neighbor), offset) {
+                self.mesh_properties.remesh.insert(neighbor_position);
+            }
+        }
+    }
+
+    /// Queue a remesh of every loaded chunk containing any of `identifiers`, such as those whose atlas locations changed
+    pub fn rebuild_meshes_containing(&mut self, identifiers: &[Identifier]) {
+        if identifiers.is_empty() {
+            return;
+        }
+        for (position, chunk) in self.chunk_properties.chunk_grid.0.iter() {
+            let chunk = read_chunk(chunk);
+            if chunk
+                .contents
+                .iter()
+                .flatten()
+                .any(|block| identifiers.contains(&block.identifier))
+            {
+                self.mesh_properties.remesh.insert(*position);
+            }
+        }
+    }
+
+    /// Queue a remesh of every chunk with a meshing task running or a mesh waiting to be applied,
+    /// such as after replacing the [`AtlasManager`] those tasks were given
+    pub fn requeue_pending_meshes(&mut self) {
+        let mesh_states = self
+            .mesh_properties
+            .mesh_states
+            .read()
+            .expect("Mesh states rw poisoned");
+        self.mesh_properties
+            .remesh
+            .extend(mesh_states.keys().copied());
+    }
+
+    /// Queue a remesh of every loaded chunk, such as after changing how meshes are built<br>
+    /// The queue is worked through at [`GameSettings::max_chunk_tasks_per_frame`] chunks per frame, nearest first
+    pub fn rebuild_all_meshes(&mut self) {
+        self.mesh_properties
+            .remesh
+            .extend(self.chunk_properties.chunk_grid.0.keys().copied());
+    }
+
+    /// See [`ChunkGrid::get_block`]
+    pub fn get_block(&self, world: IVec3) -> Option<Block> {
+        self.chunk_properties.chunk_grid.get_block(world)
+    }
+
+    /// Set the block at world coordinates `world` and queue the meshes it affects for a rebuild<br>
+    /// Does nothing if the block's chunk is not loaded
+    pub fn set_block(&mut self, world: IVec3, block: Option<Block>) {
+        let block = Block::non_air(block);
+        if let Some(replaced) = self
+            .chunk_properties
+            .chunk_grid
+            .set_block(world, block.clone())
+        {
+            self.record_edit(world, replaced, &block);
+            self.rebuild_block_meshes(world);
+        }
+    }
+
+    /// Set many blocks while only taking a single write lock per affected chunk<br>
+    /// Each modified chunk and each loaded chunk bordering an edit is queued for a rebuild once<br>
+    /// Edits that fall inside chunks which are not loaded are skipped
+    pub fn set_blocks(&mut self, edits: impl IntoIterator<Item = (IVec3, Option<Block>)>) {
+        let edits = edits
+            .into_iter()
+            .map(|(world, block)| (world, Block::non_air(block)))
+            .collect::<Vec<(IVec3, Option<Block>)>>();
+        let mut replaced = Vec::new();
+        self.chunk_properties
+            .chunk_grid
+            .set_blocks(&edits, &mut replaced);
+        for (edit, replaced) in replaced {
+            let (world, block) = &edits[edit];
+            self.record_edit(*world, replaced, block);
+            self.rebuild_block_meshes(*world);
+        }
+    }
+
+    /// Queue [`BlockRemoved`] and [`BlockPlaced`] events for an edit that replaced `replaced` with `block`
+    fn record_edit(&mut self, world: IVec3, replaced: Option<Block>, block: &Option<Block>) {
+        let removed = replaced.map(|replaced| replaced.identifier);
+        let placed = block.as_ref().map(|block| block.identifier.clone());
+        if removed != placed {
+            self.chunk_properties.edits.push((world, removed, placed));
+        }
+    }
+
+    /// Where the camera is placed when entering the level
+    pub fn spawn_point(&self) -> Vec3 {
+        self.level_properties.metadata.spawn.unwrap_or(Vec3::ZERO)
+    }
+
+    /// Height of the highest block in column `x`, `z` of the chunk at `position`, `None` if the column is empty<br>
+    /// A chunk that isn't loaded is loaded or generated on this thread without being added to the grid,
+    /// one that can't be read counts as empty
+    fn highest_block_in_chunk(&self, position: IVec3, x: i16, z: i16) -> Option<i16> {
+        let highest = |chunk: &Chunk| {
+            (0..chunk::SIZE_I16)
+                .rev()
+                .find(|&y| chunk.get(I16Vec3::new(x, y, z)).is_some())
+        };
+        if let Some(chunk) = self.chunk_properties.chunk_grid.0.get(&position) {
+            return highest(&read_chunk(chunk));
+        }
+        let properties = &self.level_properties;
+        let (noise, cave_noise) = properties.noise();
+        let chunk = load_or_generate_chunk(
+            &properties.regions,
+            &properties.id,
+            &self.chunk_properties.data_lost,
+            position,
+            &properties.metadata.generator,
+            &properties.terrain_profile,
+            &noise,
+            &cave_noise,
+            properties.caves.threshold,
+            &properties.ores,
+            &properties.trees,
+            properties.metadata.seed,
+        );
+        match chunk {
+            Ok(chunk) => highest(&chunk),
+            Err(error) => {
+                warn!("Failed to read saved chunk at {position} while finding spawn: {error}");
+                None
+            }
+        }
+    }
+
+    /// Position [`SPAWN_CLEARANCE`] blocks above the highest block of the world column at `column`<br>
+    /// Starts from the chunk at y=0, climbing while the column fills it to the top and then descending to the first block
+    fn find_spawn(&self, column: IVec2) -> Vec3 {
+        let chunk_column = IVec2::new(
+            ChunkGrid::to_chunk_coord(column.x as f32),
+            ChunkGrid::to_chunk_coord(column.y as f32),
+        );
+        let (x, z) = (
+            Chunk::to_block_coord(column.x),
+            Chunk::to_block_coord(column.y),
+        );
+        let highest_at = |chunk_y| {
+            self.highest_block_in_chunk(IVec3::new(chunk_column.x, chunk_y, chunk_column.y), x, z)
+        };
+
+        let mut chunk_y = 0;
+        let mut highest = highest_at(chunk_y);
+        let mut climbed = false;
+        while highest == Some(chunk::SIZE_I16 - 1) && chunk_y < SPAWN_SEARCH_CHUNKS {
+            chunk_y += 1;
+            highest = highest_at(chunk_y);
+            climbed = true;
+        }
+        let surface = 'search: {
+            // The column filled the chunk below to its top
+            if climbed && highest.is_none() {
+                break 'search Some(chunk_y * chunk::SIZE_I32 - 1);
+            }
+            while chunk_y > -SPAWN_SEARCH_CHUNKS {
+                if let Some(y) = highest {
+                    break 'search Some(chunk_y * chunk::SIZE_I32 + y as i32);
+                }
+                chunk_y -= 1;
+                highest = highest_at(chunk_y);
+            }
+            None
+        };
+        let surface = surface.unwrap_or_else(|| {
+            warn!("No ground found below {column}, spawning at y=0");
+            -1
+        });
+        Vec3::new(
+            column.x as f32 + 0.5,
+            (surface + 1) as f32 + SPAWN_CLEARANCE,
+            column.y as f32 + 0.5,
+        )
+    }
+
     pub fn get_chunk_grid(&self) -> &ChunkGrid {
         &self.chunk_properties.chunk_grid
     }
+
+    /// Whether the chunk at `position` currently has an entity drawing its mesh
+    pub fn has_chunk_entities(&self, position: IVec3) -> bool {
+        self.bevy_properties.chunk_entities.contains_key(&position)
+    }
+
+    /// Mesh the loaded chunk at `position` and write it to `path` as a Wavefront OBJ in world coordinates<br>
+    /// Nothing is written when the chunk has no visible faces
+    pub fn export_chunk_obj(
+        &self,
+        position: IVec3,
+        atlas_manager: &Arc<AtlasManager>,
+        path: &Path,
+    ) -> io::Result<()> {
+        let chunk = self
+            .chunk_properties
+            .chunk_grid
+            .0
+            .get(&position)
+            .map(Arc::downgrade)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "chunk is not loaded"))?;
+        let neighbors = self.chunk_properties.chunk_grid.neighbors(position);
+        let meshes = LightVolume::propagate(&chunk, &neighbors, atlas_manager)
+            .and_then(|light| {
+                self.mesh_properties.strategy.build(
+                    chunk,
+                    neighbors,
+                    Arc::downgrade(atlas_manager),
+                    &light,
+                    false,
+                )
+            })
+            .ok_or_else(|| io::Error::other("chunk was unloaded while meshing"))?;
+        if meshes.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        meshes.write_obj(position.as_vec3() * chunk::SIZE_F32, &mut writer)?;
+        writer.flush()
+    }
+
+    /// Throw away the loaded chunk at `position` and its saved copy, edits included, so it is generated again from the seed<br>
+    /// Its entities are despawned and any remesh of it is dropped, `mark_nearby_chunks_uninitialized` then loads it again
+    /// like any other missing chunk<br>
+    /// Fails without changing anything while the chunk is still being generated or saved
+    pub fn reset_chunk(&mut self, commands: &mut Commands, position: IVec3) -> io::Result<()> {
+        if !self.chunk_properties.chunk_grid.0.contains_key(&position) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "chunk is not loaded",
+            ));
+        }
+        let generating = match self.chunk_properties.chunk_states.try_read() {
+            Ok(chunk_states) => chunk_states.contains_key(&position),
+            // Held by a generation or save task, which may be this chunk's
+            Err(_) => true,
+        } || self.chunk_properties.generating.contains_key(&position);
+        if generating || self.chunk_properties.removed.contains(&position) {
+            return Err(io::Error::other("chunk is being generated or saved"));
+        }
+        // Arc clone needed so that the chunk can be removed while the lock is held
+        let mesh_states_lock = self.mesh_properties.mesh_states.clone();
+        let Ok(mut mesh_states) = mesh_states_lock.try_write() else {
+            return Err(io::Error::other("chunk meshes are being updated"));
+        };
+
+        // Deleted before the chunk is removed, generation of it must not find the old copy
+        self.level_properties.regions.clear(position)?;
+        for format in SaveFormat::ALL {
+            fs::remove_file(chunk_path(&self.level_properties.id, position, format)).or_else(
+                |error| match error.kind() {
+                    io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(error),
+                },
+            )?;
+        }
+
+        self.chunk_properties.chunk_grid.0.remove(&position);
+        self.chunk_properties.last_near.remove(&position);
+        self.chunk_properties.evicted.remove(&position);
+        self.mesh_properties.remesh.remove(&position);
+        // A remesh in flight finds no state to store its mesh in
+        mesh_states.remove(&position);
+        // Also despawns the transparent and emissive child entities
+        if let Some(entities) = self.bevy_properties.chunk_entities.remove(&position) {
+            self.bevy_properties.vertex_count -= entities.vertex_count;
+            commands.entity(entities.opaque).despawn();
+        }
+        // Faces culled against the old contents are drawn until the chunk is generated again
+        self.rebuild_neighbor_meshes(position);
+        Ok(())
+    }
+
+    /// Stop generating chunks and unload every loaded one, saving modified chunks<br>
+    /// Chunks that can't be unloaded yet stay in the grid, so this is repeated until it is empty
+    fn unload_all(&mut self, max_saves_in_flight: usize) {
+        for (_, cancelled) in self.chunk_properties.generating.drain() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+        let chunks = self
+            .chunk_properties
+            .chunk_grid
+            .0
+            .drain()
+            .collect::<Vec<(IVec3, Arc<RwLock<Chunk>>)>>();
+        unload_chunks(self, chunks, max_saves_in_flight);
+    }
+
+    /// Queues every loaded chunk for a remesh when `strategy` differs from the current one
+    pub fn set_mesh_strategy(&mut self, strategy: MeshStrategy) {
+        if self.mesh_properties.strategy != strategy {
+            self.mesh_properties.strategy = strategy;
+            self.rebuild_all_meshes();
+        }
+    }
+
+    /// Best-effort view of where every known chunk currently is in the generation and meshing pipeline<br>
+    /// States that are locked by a background task at the time of the call are reported as `Unknown`
+    pub fn chunk_pipeline_snapshot(&self) -> HashMap<IVec3, (GenerationStage, MeshStage)> {
+        let mut snapshot = self
+            .chunk_properties
+            .chunk_grid
+            .0
+            .keys()
+            .map(|position| (*position, (GenerationStage::Loaded, MeshStage::Idle)))
+            .collect::<HashMap<IVec3, (GenerationStage, MeshStage)>>();
+
+        match self.chunk_properties.chunk_states.try_read() {
+            Ok(chunk_states) => {
+                for (position, state) in chunk_states.iter() {
+                    let stage = GenerationStage::of(state);
+                    snapshot
+                        .entry(*position)
+                        .or_insert((stage, MeshStage::Idle))
+                        .0 = stage;
+                }
+            }
+            Err(_) => {
+                for (stage, _) in snapshot.values_mut() {
+                    *stage = GenerationStage::Unknown;
+                }
+            }
+        }
+
+        for position in self.mesh_properties.remesh.iter() {
+            snapshot
+                .entry(*position)
+                .or_insert((GenerationStage::Unknown, MeshStage::Queued))
+                .1 = MeshStage::Queued;
+        }
+        if let Ok(mesh_states) = self.mesh_properties.mesh_states.try_read() {
+            for (position, state) in mesh_states.iter() {
+                let stage = MeshStage::of(state);
+                snapshot
+                    .entry(*position)
+                    .or_insert((GenerationStage::Unknown, stage))
+                    .1 = stage;
+            }
+        }
+
+        snapshot
+    }
+
+    /// Entry of the chunk at `position` in [`Level::chunk_pipeline_snapshot`] without building the whole snapshot,
+    /// `None` if the chunk is in neither the chunk grid nor the pipeline
+    pub fn chunk_pipeline_stage(&self, position: IVec3) -> Option<(GenerationStage, MeshStage)> {
+        let loaded = self.chunk_properties.chunk_grid.0.contains_key(&position);
+        let mut generation = loaded.then_some(GenerationStage::Loaded);
+        match self.chunk_properties.chunk_states.try_read() {
+            Ok(chunk_states) => {
+                generation = chunk_states
+                    .get(&position)
+                    .map(GenerationStage::of)
+                    .or(generation);
+            }
+            Err(_) => generation = generation.map(|_| GenerationStage::Unknown),
+        }
+
+        let mut mesh = self
+            .mesh_properties
+            .remesh
+            .contains(&position)
+            .then_some(MeshStage::Queued);
+        if let Ok(mesh_states) = self.mesh_properties.mesh_states.try_read() {
+            mesh = mesh_states.get(&position).map(MeshStage::of).or(mesh);
+        }
+
+        match (generation, mesh) {
+            (None, None) => None,
+            (generation, mesh) => Some((
+                generation.unwrap_or(GenerationStage::Unknown),
+                mesh.unwrap_or(MeshStage::Idle),
+            )),
+        }
+    }
+
+    /// Cheap counts of the work loaded and pending, for the debug text
+    pub fn mesh_stats(&self) -> MeshStats {
+        MeshStats {
+            loaded_chunks: self.chunk_properties.chunk_grid.0.len(),
+            pending_generation: self
+                .chunk_properties
+                .chunk_states
+                .try_read()
+                .ok()
+                .map(|chunk_states| chunk_states.len()),
+            pending_meshes: self
+                .mesh_properties
+                .mesh_states
+                .try_read()
+                .ok()
+                .map(|mesh_states| self.mesh_properties.remesh.len() + mesh_states.len()),
+            vertices: self.bevy_properties.vertex_count,
+        }
+    }
+}
+
+/// Snapshot of how much the level is rendering and how much is still being worked on
+pub struct MeshStats {
+    pub loaded_chunks: usize,
+    /// Chunks being loaded or generated, `None` when a background task held the lock
+    pub pending_generation: Option<usize>,
+    /// Chunks queued for or being meshed, `None` when a background task held the lock
+    pub pending_meshes: Option<usize>,
+    /// Vertices in every chunk mesh currently applied to an entity
+    pub vertices: usize,
+}
+
+/// Public mirror of a chunk's generation state for diagnostics
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GenerationStage {
+    /// Waiting on a background task to load or generate the chunk
+    Uninitialized,
+    /// Generated but not yet inserted into the chunk grid
+    Ready,
+    /// Present in the chunk grid
+    Loaded,
+    /// Saved and waiting to be cleaned up
+    Removed,
+    /// State was locked by a background task when queried
+    Unknown,
+}
+
+impl GenerationStage {
+    fn of(state: &Mutex<ChunkGenerationState>) -> Self {
+        match state.try_lock().as_deref() {
+            Ok(ChunkGenerationState::Uninitialized) => Self::Uninitialized,
+            Ok(ChunkGenerationState::Ready(_)) => Self::Ready,
+            Ok(ChunkGenerationState::Removed) => Self::Removed,
+            Err(_) => Self::Unknown,
+        }
+    }
+}
+
+/// Public mirror of a chunk's mesh state for diagnostics
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MeshStage {
+    /// No mesh work pending
+    Idle,
+    /// Waiting in the remesh queue for a meshing task
+    Queued,
+    /// Being meshed by a background task
+    Unmeshed,
+    /// Meshed but not yet applied to the chunk entity
+    Ready,
+    /// State was locked by a background task when queried
+    Unknown,
+}
+
+impl MeshStage {
+    fn of(state: &Mutex<ChunkMeshState>) -> Self {
+        match state.try_lock().as_deref() {
+            Ok(ChunkMeshState::Unmeshed) => Self::Unmeshed,
+            Ok(ChunkMeshState::Ready(..)) => Self::Ready,
+            Err(_) => Self::Unknown,
+        }
+    }
 }
 
+/// World chosen in the main menu, loaded when entering [`GameState::InGame`]
+#[derive(Resource)]
+pub struct LevelSelection {
+    /// Name of the world's directory under `save/`
+    pub id: String,
+    /// Settings of a world being created, `None` when loading an existing world
+    pub new_world: Option<NewWorld>,
+}
+
+/// Chosen in the main menu for a world being created, stored in its [`LevelMetadata`]
+pub struct NewWorld {
+    /// Seed as it was typed
+    pub seed: String,
+    pub generator: GeneratorKind,
+}
+
+/// Blocks left empty above the surface when looking for a spawn point
+const SPAWN_CLEARANCE: f32 = 2.;
+/// Chunks searched above and below the first chunk checked when looking for the surface
+const SPAWN_SEARCH_CHUNKS: i32 = 16;
+
 struct LevelProperties {
     id: String,
-    seed: u32,
+    metadata: LevelMetadata,
+    terrain_profile: TerrainProfile,
+    caves: CaveProperties,
+    /// Ores placed into the base block of generated terrain, each with its own rarity
+    ores: Vec<OreProperties>,
+    trees: TreeProperties,
+    save_format: SaveFormat,
+    regions: RegionStorage,
+    /// Cleared when the save directory can't be created, chunks are then discarded when unloaded
+    persistent: bool,
+}
+
+impl LevelProperties {
+    /// Heightmap and cave noise for the level's seed
+    fn noise(&self) -> (Noise<Perlin>, Noise<Perlin>) {
+        // In future this should be derived from the biome
+        let noise = Noise::<Perlin> {
+            seed: NoiseRng(self.metadata.seed),
+            frequency: 1. / chunk::SIZE_F32,
+            ..Default::default()
+        };
+        // Offset seed so caves don't line up with the heightmap
+        let cave_noise = Noise::<Perlin> {
+            seed: NoiseRng(self.metadata.seed.wrapping_add(1)),
+            frequency: self.caves.frequency,
+            ..Default::default()
+        };
+        (noise, cave_noise)
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelSaveError {
+    Io(io::Error),
+    Serialization(ChunkFormatError),
+}
+
+impl fmt::Display for LevelSaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "io error: {error}"),
+            Self::Serialization(error) => write!(f, "serialization error: {error}"),
+        }
+    }
+}
+impl std::error::Error for LevelSaveError {}
+
+/// Sent when part of the level could not be written to disk<br>
+/// `position` is the chunk that was lost, `None` if the failure was not specific to a chunk
+#[derive(Event)]
+pub struct LevelSaveFailed {
+    pub position: Option<IVec3>,
+    pub error: LevelSaveError,
+}
+
+/// Sent when saved data for a chunk could not be read and the chunk was regenerated instead<br>
+/// `backup` is where the unreadable data was moved to, `None` if moving it failed
+#[derive(Event)]
+pub struct ChunkDataLost {
+    pub position: IVec3,
+    pub error: String,
+    pub backup: Option<String>,
+}
+
+/// Sent after a block is placed through [`Level::set_block`] or [`Level::set_blocks`], including when it replaced another block
+#[derive(Event)]
+pub struct BlockPlaced {
+    pub world: IVec3,
+    pub block: Identifier,
 }
 
+/// Sent after a block is removed or replaced by another through [`Level::set_block`] or [`Level::set_blocks`]<br>
+/// Sent before the [`BlockPlaced`] of the block replacing it
+#[derive(Event)]
+pub struct BlockRemoved {
+    pub world: IVec3,
+    pub block: Identifier,
+}
+
+/// Chunks within this many chunks of the camera on every axis count as near to it
+const NEAR_CHUNK_DISTANCE: i32 = 1;
+
 #[derive(Default)]
 struct ChunkProperties {
     chunk_grid: ChunkGrid,
     chunk_states: Arc<RwLock<HashMap<IVec3, Mutex<ChunkGenerationState>>>>,
+    /// Cancellation flag of every chunk with a generation task in flight, set when the chunk leaves render distance
+    generating: HashMap<IVec3, Arc<AtomicBool>>,
     removed: HashSet<IVec3>,
+    /// Incremented once per frame, used as the timestamp for `last_near`
+    tick: u32,
+    /// Tick at which each loaded chunk was last near the camera
+    last_near: HashMap<IVec3, u32>,
+    /// Chunks unloaded by the loaded chunk cap that should not be loaded again until the camera is near them
+    evicted: HashSet<IVec3>,
+    /// Background save tasks writing a chunk to disk, see [`GameSettings::max_saves_in_flight`]
+    saves_in_flight: Arc<AtomicUsize>,
+    /// Failures from background save tasks waiting to be reported on the main thread
+    save_errors: Arc<Mutex<Vec<(IVec3, LevelSaveError)>>>,
+    /// Corrupt chunks found by background generation tasks waiting to be reported on the main thread
+    data_lost: Arc<Mutex<Vec<ChunkDataLost>>>,
+    /// Block edits waiting to be sent as events, as the position and the identifiers of the removed and placed blocks
+    edits: Vec<(IVec3, Option<Identifier>, Option<Identifier>)>,
 }
 
 enum ChunkGenerationState {
@@ -97,127 +844,344 @@ enum ChunkGenerationState {
 
 #[derive(Default)]
 struct MeshProperties {
+    strategy: MeshStrategy,
     remesh: HashSet<IVec3>,
     mesh_states: Arc<RwLock<HashMap<IVec3, Mutex<ChunkMeshState>>>>,
+    /// Chunks pushed by meshing tasks and queued for remeshing on the main thread, those whose light changed because of
+    /// a neighbour and those whose task gave up because the chunk or the [`AtlasManager`] it held was replaced
+    requeue: Arc<Mutex<Vec<IVec3>>>,
 }
 
 enum ChunkMeshState {
     Unmeshed,
-    Ready(Option<Mesh>),
+    Ready(ChunkMeshes, MeshDetail),
 }
 
 struct BevyProperties {
-    chunk_entities: HashMap<IVec3, Entity>,
-    chunk_material: Handle<StandardMaterial>,
+    chunk_entities: HashMap<IVec3, ChunkEntities>,
+    /// Sum of `vertex_count` over `chunk_entities`, kept up to date instead of counting every frame
+    vertex_count: usize,
+    chunk_material: Handle<ChunkMaterial>,
+    transparent_chunk_material: Handle<ChunkMaterial>,
+    /// Unlit so emissive faces keep their brightness in the dark
+    emissive_chunk_material: Handle<ChunkMaterial>,
+}
+
+/// The transparent and emissive entities are children of the opaque one so they share its transform, visibility and lifetime
+#[derive(Clone, Copy)]
+struct ChunkEntities {
+    opaque: Entity,
+    transparent: Entity,
+    /// Only spawned once the chunk has emissive faces, as most chunks never do
+    emissive: Option<Entity>,
+    /// Vertices across the meshes of all three entities
+    vertex_count: usize,
+    /// Detail the current meshes were built at, see [`mesh_detail`]
+    detail: MeshDetail,
 }
 
-fn setup_level(
+fn tiled_atlas(atlas_texture: &Handle<Image>) -> TiledAtlas {
+    TiledAtlas {
+        tile_size: AtlasManager::tile_size(),
+        atlas: atlas_texture.clone(),
+    }
+}
+
+pub fn setup_level(
     mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut block_atlas_manager: ResMut<BlockAtlasManager>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    mut save_failed_events: EventWriter<LevelSaveFailed>,
+    game_settings: Res<GameSettings>,
+    selection: Res<LevelSelection>,
+    levels: Option<ResMut<Levels>>,
 ) {
-    let level = Level {
+    let id = &selection.id;
+    let metadata = match &selection.new_world {
+        Some(new_world) => {
+            let metadata = LevelMetadata::new(&new_world.seed, new_world.generator.clone());
+            if let Err(error) = metadata.save(id) {
+                error!("Failed to write metadata of new level \"{id}\": {error}");
+            }
+            metadata
+        }
+        None => load_metadata(id),
+    };
+    let atlas_texture = block_atlas_manager
+        .0
+        .atlas_texture()
+        .expect("Block atlas not yet built");
+    let mut level = Level {
         level_properties: LevelProperties {
-            id: "debug".to_owned(),
-            seed: 0,
+            id: id.clone(),
+            metadata,
+            terrain_profile: TerrainProfile::default(),
+            caves: CaveProperties::default(),
+            ores: generation::default_ores(),
+            trees: TreeProperties::default(),
+            save_format: SaveFormat::default(),
+            regions: RegionStorage::new(format!("save/{id}/region")),
+            persistent: true,
         },
         chunk_properties: ChunkProperties::default(),
-        mesh_properties: MeshProperties::default(),
+        mesh_properties: MeshProperties {
+            strategy: game_settings.mesh_strategy,
+            ..default()
+        },
         bevy_properties: BevyProperties {
             chunk_entities: Default::default(),
-            chunk_material: materials.add(StandardMaterial {
-                base_color_texture: Some(
-                    Arc::make_mut(&mut block_atlas_manager.0)
-                        .atlas_texture()
-                        .expect("Block atlas not yet built"),
-                ),
-                base_color: Color::WHITE,
-                ..default()
+            vertex_count: 0,
+            // Vertex colors written by the mesher (ambient occlusion) are multiplied with the base color
+            chunk_material: materials.add(ChunkMaterial {
+                base: StandardMaterial {
+                    base_color: Color::WHITE,
+                    ..default()
+                },
+                extension: tiled_atlas(&atlas_texture),
+            }),
+            transparent_chunk_material: materials.add(ChunkMaterial {
+                base: StandardMaterial {
+                    base_color: Color::WHITE,
+                    alpha_mode: AlphaMode::Blend,
+                    ..default()
+                },
+                extension: tiled_atlas(&atlas_texture),
+            }),
+            emissive_chunk_material: materials.add(ChunkMaterial {
+                base: StandardMaterial {
+                    base_color: Color::WHITE,
+                    unlit: true,
+                    ..default()
+                },
+                extension: tiled_atlas(&atlas_texture),
             }),
         },
     };
-    fs::create_dir_all(format!("save/{}/chunk", level.level_properties.id))
-        .expect("Failed to create save directory");
-    commands.insert_resource(level);
+    if level.level_properties.metadata.spawn.is_none() {
+        let spawn = level.find_spawn(IVec2::ZERO);
+        level.level_properties.metadata.spawn = Some(spawn);
+        if let Err(error) = level.level_properties.metadata.save(id) {
+            error!("Failed to save spawn point of level \"{id}\": {error}");
+        }
+    }
+    if let Err(error) = fs::create_dir_all(format!("save/{id}/region")) {
+        let error = LevelSaveError::Io(error);
+        error!(
+            "Failed to create save directory, changes to level \"{id}\" will not be saved: {error}"
+        );
+        level.level_properties.persistent = false;
+        save_failed_events.write(LevelSaveFailed {
+            position: None,
+            error,
+        });
+    }
+    match levels {
+        Some(mut levels) => {
+            levels.insert(level);
+            levels.set_active(id);
+        }
+        None => commands.insert_resource(Levels::new(level)),
+    }
+}
+
+/// Read the metadata of an existing level<br>
+/// A level saved before metadata existed is given [`LevelMetadata::reconstructed`]
+fn load_metadata(id: &str) -> LevelMetadata {
+    match LevelMetadata::load(id) {
+        Ok(metadata) => metadata,
+        Err(MetadataError::Io(error)) if error.kind() == io::ErrorKind::NotFound => {
+            warn!(
+                "Level \"{id}\" has no metadata, assuming the defaults it would have been created with"
+            );
+            let metadata = LevelMetadata::reconstructed();
+            if let Err(error) = metadata.save(id) {
+                error!("Failed to write metadata of level \"{id}\": {error}");
+            }
+            metadata
+        }
+        // Left on disk so it can be fixed by hand rather than overwritten
+        Err(error) => {
+            warn!("Metadata of level \"{id}\" could not be read, using defaults: {error}");
+            LevelMetadata::reconstructed()
+        }
+    }
+}
+
+/// The loaded area is a cylinder around the camera's chunk with the horizontal render distance as its radius<br>
+/// Used for both loading and unloading so chunks at the edge don't thrash between the two
+fn is_in_render_distance(
+    position: IVec3,
+    camera_position: IVec3,
+    game_settings: &GameSettings,
+) -> bool {
+    let diff = position - camera_position;
+    let radius = game_settings.horizontal_render_distance;
+    diff.xz().length_squared() <= radius * radius
+        && diff.y.abs() <= game_settings.vertical_render_distance
+}
+
+/// Chunks further than [`GameSettings::lod_distance`] from the camera's chunk horizontally are meshed at [`MeshDetail::Coarse`]
+fn mesh_detail(
+    position: IVec3,
+    camera_position: IVec3,
+    game_settings: &GameSettings,
+) -> MeshDetail {
+    let diff = position - camera_position;
+    let distance = game_settings.lod_distance;
+    if diff.xz().length_squared() > distance * distance {
+        MeshDetail::Coarse
+    } else {
+        MeshDetail::Full
+    }
 }
 
 fn mark_nearby_chunks_uninitialized(
-    level: Res<Level>,
+    mut levels: ResMut<Levels>,
     game_settings: Res<GameSettings>,
     camera_query: Single<&Transform, With<Camera>>,
 ) {
-    let Ok(mut chunk_states) = level.chunk_properties.chunk_states.try_write() else {
+    let level = levels.active_mut();
+    // Arc clone needed so that `generating` can be written while the lock is held
+    let chunk_states_lock = level.chunk_properties.chunk_states.clone();
+    let Ok(mut chunk_states) = chunk_states_lock.try_write() else {
         return;
     };
 
     let camera_position = ChunkGrid::to_chunk_coordinates(camera_query.translation);
-    let render_distance = IVec2::new(
+    let render_distance = IVec3::new(
         game_settings.horizontal_render_distance,
         game_settings.vertical_render_distance,
+        game_settings.horizontal_render_distance,
     );
-    let min = camera_position - render_distance.xyx();
-    let max = camera_position + render_distance.xyx();
-
-    // In future this should be derived from the biome
-    let noise = Noise::<Perlin> {
-        seed: NoiseRng(level.level_properties.seed),
-        frequency: 1. / chunk::SIZE_F32,
-        ..Default::default()
-    };
+    let min = camera_position - render_distance;
+    let max = camera_position + render_distance;
 
-    let task_pool = AsyncComputeTaskPool::get();
-    for x in min.x..max.x {
-        for y in min.y..max.y {
-            for z in min.z..max.z {
+    let (noise, cave_noise) = level.level_properties.noise();
+
+    let mut candidates = Vec::new();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
                 let position = IVec3::new(x, y, z);
 
-                if chunk_states.contains_key(&position)
+                if !is_in_render_distance(position, camera_position, &game_settings)
+                    || chunk_states.contains_key(&position)
                     || level.chunk_properties.chunk_grid.0.contains_key(&position)
                     || level.chunk_properties.removed.contains(&position)
+                    || (level.chunk_properties.evicted.contains(&position)
+                        && (position - camera_position).abs().max_element() > NEAR_CHUNK_DISTANCE)
                 {
                     continue;
                 }
-
-                chunk_states.insert(position, Mutex::new(ChunkGenerationState::Uninitialized));
-                task_pool
-                    .spawn(create_chunk(
-                        level.chunk_properties.chunk_states.clone(),
-                        level.level_properties.id.clone(),
-                        position,
-                        noise,
-                    ))
-                    .detach();
+                candidates.push(position);
             }
         }
     }
+    sort_by_priority(&mut candidates, &camera_query);
+    candidates.truncate(game_settings.max_chunk_tasks_per_frame);
+
+    let task_pool = AsyncComputeTaskPool::get();
+    for position in candidates {
+        chunk_states.insert(position, Mutex::new(ChunkGenerationState::Uninitialized));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        level
+            .chunk_properties
+            .generating
+            .insert(position, cancelled.clone());
+        task_pool
+            .spawn(create_chunk(
+                level.chunk_properties.chunk_states.clone(),
+                cancelled,
+                level.chunk_properties.data_lost.clone(),
+                level.level_properties.regions.clone(),
+                level.level_properties.id.clone(),
+                position,
+                level.level_properties.metadata.generator.clone(),
+                level.level_properties.terrain_profile.clone(),
+                noise,
+                cave_noise,
+                level.level_properties.caves.threshold,
+                level.level_properties.ores.clone(),
+                level.level_properties.trees.clone(),
+                level.level_properties.metadata.seed,
+            ))
+            .detach();
+    }
 }
 
+/// Chunks behind the camera count as this many times further away when ordering work
+const BEHIND_CAMERA_PRIORITY_SCALE: f32 = 1.5;
+
+/// Order chunk positions so the work the player is most likely to see is spawned first<br>
+/// Task pools start tasks roughly in the order they are spawned
+fn sort_by_priority(positions: &mut [IVec3], camera_transform: &Transform) {
+    let forward = camera_transform.forward();
+    positions.sort_by_cached_key(|position| {
+        let offset = (position.as_vec3() + 0.5) * chunk::SIZE_F32 - camera_transform.translation;
+        let mut distance = offset.length();
+        if forward.dot(offset) < 0. {
+            distance *= BEHIND_CAMERA_PRIORITY_SCALE;
+        }
+        // Distances are never negative so their bit patterns sort in the same order
+        distance.to_bits()
+    });
+}
+
+/// Gives up before loading or generating and again before storing the chunk once `cancelled` is set,
+/// removing the chunk's state so it can be queued again when it comes back into render distance<br>
+/// Also gives up when the saved chunk couldn't be read, so it is retried instead of being regenerated over
+#[allow(clippy::too_many_arguments)]
 async fn create_chunk(
     chunk_states: Arc<RwLock<HashMap<IVec3, Mutex<ChunkGenerationState>>>>,
+    cancelled: Arc<AtomicBool>,
+    data_lost: Arc<Mutex<Vec<ChunkDataLost>>>,
+    regions: RegionStorage,
     file_path: String,
     position: IVec3,
+    generator: GeneratorKind,
+    terrain_profile: TerrainProfile,
     noise: impl SampleableFor<Vec2, f32>,
+    cave_noise: impl SampleableFor<Vec3, f32>,
+    cave_threshold: f32,
+    ores: Vec<OreProperties>,
+    trees: TreeProperties,
+    seed: u32,
 ) {
-    let chunk = 'load: {
-        let path = format!(
-            "save/{}/chunk/{}_{}_{}.json",
-            file_path, position.x, position.y, position.z
-        );
-        if let Ok(serialized_chunk) = fs::read_to_string(path) {
-            let mut deserializer = serde_json::Deserializer::from_str(&serialized_chunk);
-            match Chunk::deserialize(&mut deserializer) {
-                Ok(mut deserialized_chunk) => {
-                    deserialized_chunk.position = position;
-                    break 'load deserialized_chunk;
-                }
-                Err(error) => {
-                    eprintln!("Failed to deserialize chunk at {position}: {error:?}")
-                }
-            }
+    let forget = || {
+        chunk_states
+            .write()
+            .expect("Chunk states rw poisoned")
+            .remove(&position);
+    };
+    if cancelled.load(Ordering::Relaxed) {
+        forget();
+        return;
+    }
+    let chunk = match load_or_generate_chunk(
+        &regions,
+        &file_path,
+        &data_lost,
+        position,
+        &generator,
+        &terrain_profile,
+        &noise,
+        &cave_noise,
+        cave_threshold,
+        &ores,
+        &trees,
+        seed,
+    ) {
+        Ok(chunk) => chunk,
+        Err(error) => {
+            warn!("Failed to read saved chunk at {position}, retrying later: {error}");
+            forget();
+            return;
         }
-
-        Chunk::generate(position, &noise)
     };
+    if cancelled.load(Ordering::Relaxed) {
+        forget();
+        return;
+    }
 
     let chunk_states = chunk_states.read().expect("Chunk states rw poisoned");
     let Some(state_mutex) = chunk_states.get(&position) else {
@@ -230,7 +1194,119 @@ async fn create_chunk(
     *state = ChunkGenerationState::Ready(Some(chunk));
 }
 
-fn finalize_chunk_generation(mut level: ResMut<Level>) {
+/// Load the chunk at `position` from disk, or generate it if it was never saved<br>
+/// Fails only if reading the saved chunk failed, in which case it may still be readable later
+#[allow(clippy::too_many_arguments)]
+fn load_or_generate_chunk(
+    regions: &RegionStorage,
+    file_path: &str,
+    data_lost: &Mutex<Vec<ChunkDataLost>>,
+    position: IVec3,
+    generator: &GeneratorKind,
+    terrain_profile: &TerrainProfile,
+    noise: &impl SampleableFor<Vec2, f32>,
+    cave_noise: &impl SampleableFor<Vec3, f32>,
+    cave_threshold: f32,
+    ores: &[OreProperties],
+    trees: &TreeProperties,
+    seed: u32,
+) -> io::Result<Chunk> {
+    if let Some(mut deserialized_chunk) = load_chunk(regions, file_path, position, data_lost)? {
+        deserialized_chunk.position = position;
+        deserialized_chunk.saved_hash = Some(deserialized_chunk.content_hash());
+        return Ok(deserialized_chunk);
+    }
+    Ok(match generator {
+        GeneratorKind::Normal => {
+            let mut chunk =
+                Chunk::generate(position, noise, cave_noise, cave_threshold, terrain_profile);
+            // Offset seeds like the cave noise so ores and trees don't follow either noise
+            chunk.generate_ores(seed.wrapping_add(2), ores, &terrain_profile.base);
+            chunk.generate_trees(
+                seed.wrapping_add(3),
+                trees,
+                terrain_profile.sea_level,
+                |column| Chunk::terrain_height(noise, column),
+            );
+            // Trees stand above the terrain the sky light was generated for
+            chunk.sky_light =
+                light::propagate_sky_light(&chunk, &chunk.sky_entry, |_| light::MAX_LIGHT);
+            chunk
+        }
+        GeneratorKind::Flat { layers } => Chunk::generate_flat(position, layers),
+        GeneratorKind::Showcase { blocks } => Chunk::generate_showcase(position, blocks),
+    })
+}
+
+/// Chunks in region files take priority over loose files left by older saves<br>
+/// Data that fails to deserialize is moved aside so it isn't retried on every load, data that fails to be read is
+/// left in place and the error returned, as it isn't lost
+fn load_chunk(
+    regions: &RegionStorage,
+    file_path: &str,
+    position: IVec3,
+    data_lost: &Mutex<Vec<ChunkDataLost>>,
+) -> io::Result<Option<Chunk>> {
+    let report = |error: String, backup: Option<String>| {
+        data_lost
+            .lock()
+            .expect("Data lost mutex poisoned")
+            .push(ChunkDataLost {
+                position,
+                error,
+                backup,
+            });
+    };
+
+    if let Some(data) = regions.read(position)? {
+        match SaveFormat::decode_entry(&data) {
+            Ok(chunk) => return Ok(Some(chunk)),
+            Err(error) => report(
+                error.to_string(),
+                backed_up(
+                    position,
+                    regions.backup_corrupt(position, &data, timestamp()),
+                ),
+            ),
+        }
+    }
+
+    for format in SaveFormat::ALL {
+        let path = chunk_path(file_path, position, format);
+        let serialized_chunk = match fs::read(&path) {
+            Ok(serialized_chunk) => serialized_chunk,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+        match format.deserialize(&serialized_chunk) {
+            Ok(chunk) => return Ok(Some(chunk)),
+            Err(error) => {
+                // Renamed over the empty file reserving the backup's name
+                let backup = region::create_backup(&format!("{path}.corrupt-{}", timestamp()))
+                    .and_then(|(_, backup_path)| {
+                        fs::rename(&path, &backup_path).map(|_| backup_path)
+                    });
+                report(error.to_string(), backed_up(position, backup));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn backed_up(position: IVec3, backup: io::Result<String>) -> Option<String> {
+    backup
+        .inspect_err(|error| warn!("Failed to back up corrupt chunk at {position}: {error}"))
+        .ok()
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn finalize_chunk_generation(mut levels: ResMut<Levels>) {
+    let level = levels.active_mut();
     let finished_chunks = {
         let Ok(mut chunk_states) = level.chunk_properties.chunk_states.try_write() else {
             return;
@@ -253,19 +1329,84 @@ fn finalize_chunk_generation(mut level: ResMut<Level>) {
         finished_chunks
     };
     for (position, chunk) in finished_chunks {
+        level.chunk_properties.generating.remove(&position);
         if level.chunk_properties.removed.contains(&position) {
             continue;
         }
-        level
-            .chunk_properties
+        let chunk_properties = &mut level.chunk_properties;
+        chunk_properties
             .chunk_grid
             .0
             .insert(position, Arc::new(RwLock::new(chunk)));
+        chunk_properties.evicted.remove(&position);
+        chunk_properties
+            .last_near
+            .insert(position, chunk_properties.tick);
         level.mesh_properties.remesh.insert(position);
+        level.rebuild_meshes_bordering(position);
     }
 }
 
-fn handle_remesh_queue(mut level: ResMut<Level>, block_manager: Res<BlockAtlasManager>) {
+/// Whether a block on the border of `chunk` facing its neighbour at `offset` lies within one block of a block on the
+/// neighbour's border, along the axes the border extends in<br>
+/// Blocks further apart can't cull each other's faces or affect each other's ambient occlusion
+fn borders_meet(chunk: &Chunk, neighbor: &Chunk, offset: IVec3) -> bool {
+    let free_axes = (0..3)
+        .filter(|&axis| offset[axis] == 0)
+        .collect::<Vec<usize>>();
+    let extent = |free_axis: usize| {
+        if free_axis < free_axes.len() {
+            chunk::SIZE_I16
+        } else {
+            1
+        }
+    };
+    let (width, height) = (extent(0), extent(1));
+    // Block `u`, `v` of the border layer along the free axes, on the chunk's side or the neighbour's
+    let border_block = |u: i16, v: i16, own: bool| {
+        let mut local = I16Vec3::ZERO;
+        for axis in 0..3 {
+            local[axis] = match (offset[axis], own) {
+                (0, _) => {
+                    if free_axes.first() == Some(&axis) {
+                        u
+                    } else {
+                        v
+                    }
+                }
+                (1, true) | (-1, false) => chunk::SIZE_I16 - 1,
+                _ => 0,
+            };
+        }
+        local
+    };
+
+    let index = |u: i16, v: i16| (u + v * width) as usize;
+    let mut near = vec![false; (width * height) as usize];
+    for u in 0..width {
+        for v in 0..height {
+            if chunk.get(border_block(u, v, true)).is_none() {
+                continue;
+            }
+            for near_u in (u - 1).max(0)..=(u + 1).min(width - 1) {
+                for near_v in (v - 1).max(0)..=(v + 1).min(height - 1) {
+                    near[index(near_u, near_v)] = true;
+                }
+            }
+        }
+    }
+    (0..width).any(|u| {
+        (0..height).any(|v| near[index(u, v)] && neighbor.get(border_block(u, v, false)).is_some())
+    })
+}
+
+fn handle_remesh_queue(
+    mut levels: ResMut<Levels>,
+    block_manager: Res<BlockAtlasManager>,
+    game_settings: Res<GameSettings>,
+    camera_query: Single<&Transform, With<Camera>>,
+) {
+    let level = levels.active_mut();
     // Arc clone needed so that remesh_queue can be drained while write lock is in scope
     let mesh_states = level.mesh_properties.mesh_states.clone();
     let Ok(mut mesh_states) = mesh_states.try_write() else {
@@ -273,8 +1414,32 @@ fn handle_remesh_queue(mut level: ResMut<Level>, block_manager: Res<BlockAtlasMa
     };
 
     let mesh_states_lock = level.mesh_properties.mesh_states.clone();
+    let requeue = std::mem::take(
+        &mut *level
+            .mesh_properties
+            .requeue
+            .lock()
+            .expect("Requeue mutex poisoned"),
+    );
+    level.mesh_properties.remesh.extend(requeue);
+    let camera_position = ChunkGrid::to_chunk_coordinates(camera_query.translation);
+    // Chunks that crossed the level of detail distance since they were meshed, unless a remesh is already under way
+    for (position, entities) in level.bevy_properties.chunk_entities.iter() {
+        if entities.detail != mesh_detail(*position, camera_position, &game_settings)
+            && !mesh_states.contains_key(position)
+        {
+            level.mesh_properties.remesh.insert(*position);
+        }
+    }
+    let mut positions = level.mesh_properties.remesh.drain().collect::<Vec<IVec3>>();
+    positions.retain(|position| level.chunk_properties.chunk_grid.0.contains_key(position));
+    sort_by_priority(&mut positions, &camera_query);
+    let deferred =
+        positions.split_off(positions.len().min(game_settings.max_chunk_tasks_per_frame));
+    level.mesh_properties.remesh.extend(deferred);
+
     let task_pool = AsyncComputeTaskPool::get();
-    for position in level.mesh_properties.remesh.drain().collect::<Vec<IVec3>>() {
+    for position in positions {
         let Some(chunk) = level.chunk_properties.chunk_grid.0.get(&position) else {
             continue;
         };
@@ -282,21 +1447,68 @@ fn handle_remesh_queue(mut level: ResMut<Level>, block_manager: Res<BlockAtlasMa
         task_pool
             .spawn(remesh_chunk(
                 mesh_states_lock.clone(),
+                level.mesh_properties.requeue.clone(),
                 Arc::downgrade(chunk),
+                level.chunk_properties.chunk_grid.neighbors(position),
                 Arc::downgrade(&block_manager.0),
+                level.mesh_properties.strategy,
+                mesh_detail(position, camera_position, &game_settings),
+                game_settings.ambient_occlusion,
                 position,
             ))
             .detach();
     }
 }
 
+/// Propagates the chunk's block and sky light before meshing it, queueing neighbours lit by it when the light near their face changed<br>
+/// Queues the chunk itself again if either [`Weak`] was invalidated, which leaves its state to the next task
+/// instead of `Unmeshed` forever. Chunks that were unloaded are dropped from the queue
+#[allow(clippy::too_many_arguments)]
 async fn remesh_chunk(
     mesh_states: Arc<RwLock<HashMap<IVec3, Mutex<ChunkMeshState>>>>,
+    requeue: Arc<Mutex<Vec<IVec3>>>,
     chunk: Weak<RwLock<Chunk>>,
+    neighbors: ChunkNeighbors,
     atlas_manager: Weak<AtlasManager>,
+    strategy: MeshStrategy,
+    detail: MeshDetail,
+    ambient_occlusion: bool,
     position: IVec3,
 ) {
-    let Some(mesh) = chunk::mesh::build_mesh(chunk, atlas_manager) else {
+    let retry = || {
+        requeue
+            .lock()
+            .expect("Requeue mutex poisoned")
+            .push(position);
+    };
+    let Some(light) = atlas_manager
+        .upgrade()
+        .and_then(|atlas_manager| LightVolume::propagate(&chunk, &neighbors, &atlas_manager))
+    else {
+        retry();
+        return;
+    };
+    {
+        let Some(rw_lock) = chunk.upgrade() else {
+            retry();
+            return;
+        };
+        let changed_faces = light.store(&mut write_chunk(&rw_lock));
+        requeue.lock().expect("Requeue mutex poisoned").extend(
+            changed_faces
+                .into_iter()
+                .map(|direction| position + NEIGHBOR_OFFSETS[direction]),
+        );
+    }
+
+    let mesh = match detail {
+        MeshDetail::Full => {
+            strategy.build(chunk, neighbors, atlas_manager, &light, ambient_occlusion)
+        }
+        MeshDetail::Coarse => build_lod_mesh(chunk, neighbors, atlas_manager, &light),
+    };
+    let Some(mesh) = mesh else {
+        retry();
         return;
     };
 
@@ -308,64 +1520,135 @@ async fn remesh_chunk(
     if !matches!(*state, ChunkMeshState::Unmeshed) {
         return;
     }
-    *state = ChunkMeshState::Ready(mesh);
+    *state = ChunkMeshState::Ready(mesh, detail);
 }
 
 fn apply_ready_meshes(
     mut commands: Commands,
-    mut level: ResMut<Level>,
+    mut levels: ResMut<Levels>,
     mut meshes: ResMut<Assets<Mesh>>,
+    game_settings: Res<GameSettings>,
+    camera_query: Single<&Transform, With<Camera>>,
 ) {
+    let level = levels.active_mut();
     let finished_meshes = {
         let Ok(mut mesh_states) = level.mesh_properties.mesh_states.try_write() else {
             return;
         };
-        let finished_meshes = mesh_states
+        let mut ready = mesh_states
             .iter()
-            .filter_map(|(position, state)| {
-                let Ok(mut state) = state.try_lock() else {
+            .filter(|(_, state)| {
+                state
+                    .try_lock()
+                    .is_ok_and(|state| matches!(*state, ChunkMeshState::Ready(..)))
+            })
+            .map(|(position, _)| *position)
+            .collect::<Vec<IVec3>>();
+        sort_by_priority(&mut ready, &camera_query);
+        ready.truncate(game_settings.max_meshes_applied_per_frame);
+
+        let finished_meshes = ready
+            .into_iter()
+            .filter_map(|position| {
+                let Ok(mut state) = mesh_states.get(&position)?.try_lock() else {
                     return None;
                 };
-                let ChunkMeshState::Ready(mesh) = state.deref_mut() else {
+                let ChunkMeshState::Ready(chunk_meshes, detail) = state.deref_mut() else {
                     return None;
                 };
-                Some((*position, mesh.take()))
+                Some((position, std::mem::take(chunk_meshes), *detail))
             })
-            .collect::<Vec<(IVec3, Option<Mesh>)>>();
-        for (position, _) in finished_meshes.iter() {
+            .collect::<Vec<(IVec3, ChunkMeshes, MeshDetail)>>();
+        for (position, ..) in finished_meshes.iter() {
             mesh_states.remove(position);
         }
         finished_meshes
     };
     let removed_meshes = finished_meshes
         .iter()
-        .map(|(position, _)| *position)
+        .map(|(position, ..)| *position)
         .collect::<Vec<IVec3>>();
-    for (position, mesh) in finished_meshes {
-        if let Some(entity) = level.bevy_properties.chunk_entities.get(&position) {
-            let mut entity = commands.entity(*entity);
+    for (position, chunk_meshes, detail) in finished_meshes {
+        let entities = match level.bevy_properties.chunk_entities.get(&position) {
+            Some(entities) => *entities,
+            None => {
+                let opaque = commands
+                    .spawn((
+                        MeshMaterial3d(level.bevy_properties.chunk_material.clone()),
+                        Transform::from_xyz(
+                            position.x as f32 * chunk::SIZE_F32,
+                            position.y as f32 * chunk::SIZE_F32,
+                            position.z as f32 * chunk::SIZE_F32,
+                        ),
+                        Visibility::default(),
+                    ))
+                    .id();
+                let transparent = commands
+                    .spawn((
+                        MeshMaterial3d(level.bevy_properties.transparent_chunk_material.clone()),
+                        Transform::default(),
+                        ChildOf(opaque),
+                    ))
+                    .id();
+                let entities = ChunkEntities {
+                    opaque,
+                    transparent,
+                    emissive: None,
+                    vertex_count: 0,
+                    detail,
+                };
+                level
+                    .bevy_properties
+                    .chunk_entities
+                    .insert(position, entities);
+                entities
+            }
+        };
+        let emissive = match (entities.emissive, chunk_meshes.emissive.is_some()) {
+            (None, true) => {
+                let emissive = commands
+                    .spawn((
+                        MeshMaterial3d(level.bevy_properties.emissive_chunk_material.clone()),
+                        Transform::default(),
+                        ChildOf(entities.opaque),
+                    ))
+                    .id();
+                if let Some(entities) = level.bevy_properties.chunk_entities.get_mut(&position) {
+                    entities.emissive = Some(emissive);
+                }
+                Some(emissive)
+            }
+            (emissive, _) => emissive,
+        };
+        let vertex_count = [
+            &chunk_meshes.opaque,
+            &chunk_meshes.transparent,
+            &chunk_meshes.emissive,
+        ]
+        .into_iter()
+        .flatten()
+        .map(Mesh::count_vertices)
+        .sum::<usize>();
+        level.bevy_properties.vertex_count += vertex_count;
+        level.bevy_properties.vertex_count -= entities.vertex_count;
+        if let Some(entities) = level.bevy_properties.chunk_entities.get_mut(&position) {
+            entities.vertex_count = vertex_count;
+            entities.detail = detail;
+        }
+        for (entity, mesh) in [
+            (Some(entities.opaque), chunk_meshes.opaque),
+            (Some(entities.transparent), chunk_meshes.transparent),
+            (emissive, chunk_meshes.emissive),
+        ] {
+            let Some(entity) = entity else {
+                continue;
+            };
+            let mut entity = commands.entity(entity);
             match mesh {
                 Some(mesh) => entity.insert(Mesh3d(meshes.add(mesh))),
                 None => entity.remove::<Mesh3d>(),
             };
-            continue;
         }
-
-        let mut entity = commands.spawn((
-            MeshMaterial3d(level.bevy_properties.chunk_material.clone()),
-            Transform::from_xyz(
-                position.x as f32 * chunk::SIZE_F32,
-                position.y as f32 * chunk::SIZE_F32,
-                position.z as f32 * chunk::SIZE_F32,
-            ),
-        ));
-        if let Some(mesh) = mesh {
-            entity.insert(Mesh3d(meshes.add(mesh)));
-        };
-        level
-            .bevy_properties
-            .chunk_entities
-            .insert(position, entity.id());
     }
     let mesh_states = level.mesh_properties.mesh_states.clone();
     AsyncComputeTaskPool::get()
@@ -379,30 +1662,157 @@ fn apply_ready_meshes(
 }
 
 fn remove_far_chunks(
-    mut level: ResMut<Level>,
+    mut levels: ResMut<Levels>,
     game_settings: Res<GameSettings>,
     camera_query: Single<&Transform, With<Camera>>,
 ) {
+    let level = levels.active_mut();
     let camera_position = ChunkGrid::to_chunk_coordinates(camera_query.translation);
-    let render_distance = IVec2::new(
-        game_settings.horizontal_render_distance,
-        game_settings.vertical_render_distance,
-    );
+    let is_far =
+        |position: &IVec3| !is_in_render_distance(*position, camera_position, &game_settings);
     let far_chunks = level
         .chunk_properties
         .chunk_grid
         .0
-        .extract_if(|position, _| {
-            let diff = (position - camera_position).abs();
-            diff.x > render_distance.x || diff.y > render_distance.y || diff.z > render_distance.x
+        .extract_if(|position, _| is_far(position))
+        .collect::<Vec<(IVec3, Arc<RwLock<Chunk>>)>>();
+    level
+        .chunk_properties
+        .evicted
+        .retain(|position| !is_far(position));
+    // Tasks for chunks the camera moved away from stop instead of finishing a chunk that would be unloaded straight away
+    for (_, cancelled) in level
+        .chunk_properties
+        .generating
+        .extract_if(|position, _| is_far(position))
+    {
+        cancelled.store(true, Ordering::Relaxed);
+    }
+
+    unload_chunks(level, far_chunks, game_settings.max_saves_in_flight);
+}
+
+fn update_chunk_recency(
+    mut levels: ResMut<Levels>,
+    camera_query: Single<&Transform, With<Camera>>,
+) {
+    let level = levels.active_mut();
+    let camera_position = ChunkGrid::to_chunk_coordinates(camera_query.translation);
+    let chunk_properties = &mut level.chunk_properties;
+    chunk_properties.tick = chunk_properties.tick.wrapping_add(1);
+    for x in -NEAR_CHUNK_DISTANCE..=NEAR_CHUNK_DISTANCE {
+        for y in -NEAR_CHUNK_DISTANCE..=NEAR_CHUNK_DISTANCE {
+            for z in -NEAR_CHUNK_DISTANCE..=NEAR_CHUNK_DISTANCE {
+                let position = camera_position + IVec3::new(x, y, z);
+                if chunk_properties.chunk_grid.0.contains_key(&position) {
+                    chunk_properties
+                        .last_near
+                        .insert(position, chunk_properties.tick);
+                }
+            }
+        }
+    }
+}
+
+/// Choose which of the `loaded` chunks to evict so that no more than `max_loaded` remain<br>
+/// Chunks that have gone the longest without being near the camera are evicted first, ties are broken by distance from the camera
+fn select_evictions(
+    loaded: impl Iterator<Item = IVec3>,
+    last_near: &HashMap<IVec3, u32>,
+    tick: u32,
+    camera_position: IVec3,
+    max_loaded: usize,
+) -> Vec<IVec3> {
+    let mut loaded = loaded.collect::<Vec<IVec3>>();
+    if loaded.len() <= max_loaded {
+        return Vec::new();
+    }
+    loaded.sort_by_key(|position| {
+        let staleness = last_near
+            .get(position)
+            .map_or(u32::MAX, |last_near| tick.wrapping_sub(*last_near));
+        (
+            std::cmp::Reverse(staleness),
+            std::cmp::Reverse(position.distance_squared(camera_position)),
+        )
+    });
+    loaded.truncate(loaded.len() - max_loaded);
+    loaded
+}
+
+fn evict_stale_chunks(
+    mut levels: ResMut<Levels>,
+    game_settings: Res<GameSettings>,
+    camera_query: Single<&Transform, With<Camera>>,
+) {
+    let level = levels.active_mut();
+    let chunk_properties = &level.chunk_properties;
+    let evictions = select_evictions(
+        chunk_properties.chunk_grid.0.keys().copied(),
+        &chunk_properties.last_near,
+        chunk_properties.tick,
+        ChunkGrid::to_chunk_coordinates(camera_query.translation),
+        game_settings.max_loaded_chunks,
+    );
+    if evictions.is_empty() {
+        return;
+    }
+
+    let evicted_chunks = evictions
+        .into_iter()
+        .filter_map(|position| {
+            let chunk = level.chunk_properties.chunk_grid.0.remove(&position)?;
+            level.chunk_properties.evicted.insert(position);
+            Some((position, chunk))
         })
         .collect::<Vec<(IVec3, Arc<RwLock<Chunk>>)>>();
 
+    unload_chunks(level, evicted_chunks, game_settings.max_saves_in_flight);
+}
+
+/// Unload every chunk of the levels that aren't active, saving the modified ones, until their grids are empty
+fn unload_inactive_levels(mut levels: ResMut<Levels>, game_settings: Res<GameSettings>) {
+    let levels = &mut *levels;
+    for (id, level) in levels.levels.iter_mut() {
+        if *id != levels.active {
+            level.unload_all(game_settings.max_saves_in_flight);
+        }
+    }
+}
+
+/// Save and queue removal of chunks which have already been taken out of the chunk grid<br>
+/// Chunks still referenced elsewhere, and modified chunks while `max_saves_in_flight` saves are being written,
+/// are put back into the grid to be retried later<br>
+/// A chunk is in `removed` from when its save is queued until it is cleaned up, so it is never queued twice
+fn unload_chunks(
+    level: &mut Level,
+    chunks: Vec<(IVec3, Arc<RwLock<Chunk>>)>,
+    max_saves_in_flight: usize,
+) {
     let task_pool = IoTaskPool::get();
-    for (position, chunk) in far_chunks {
+    for (position, chunk) in chunks {
         if level.chunk_properties.removed.contains(&position) {
             continue;
         }
+        // Unmodified chunks aren't written, so they are always unloaded straight away
+        let writes = level.level_properties.persistent && read_chunk(&chunk).dirty;
+        if writes
+            && level
+                .chunk_properties
+                .saves_in_flight
+                .load(Ordering::Relaxed)
+                >= max_saves_in_flight
+        {
+            // Safe because we just removed this key from the map
+            unsafe {
+                level
+                    .chunk_properties
+                    .chunk_grid
+                    .0
+                    .insert_unique_unchecked(position, chunk);
+            }
+            continue;
+        }
 
         let chunk = match Arc::try_unwrap(chunk) {
             Ok(chunk) => chunk,
@@ -420,26 +1830,53 @@ fn remove_far_chunks(
         };
 
         level.chunk_properties.removed.insert(position);
+        level.chunk_properties.last_near.remove(&position);
         level.mesh_properties.remesh.remove(&position);
 
+        // Only ever incremented here on the main thread, so the budget check above can't be raced past
+        let in_flight = writes.then(|| {
+            level
+                .chunk_properties
+                .saves_in_flight
+                .fetch_add(1, Ordering::Relaxed);
+            SaveInFlight(level.chunk_properties.saves_in_flight.clone())
+        });
         task_pool
             .spawn(save_chunk(
                 level.chunk_properties.chunk_states.clone(),
                 level.mesh_properties.mesh_states.clone(),
-                level.level_properties.id.clone(),
+                level.chunk_properties.save_errors.clone(),
+                in_flight,
+                level
+                    .level_properties
+                    .persistent
+                    .then(|| level.level_properties.regions.clone()),
+                level.level_properties.save_format,
                 chunk,
             ))
             .detach();
     }
 }
 
+/// Counts a save towards [`ChunkProperties::saves_in_flight`] until dropped, however the save task ends
+struct SaveInFlight(Arc<AtomicUsize>);
+
+impl Drop for SaveInFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 async fn save_chunk(
     chunk_states: Arc<RwLock<HashMap<IVec3, Mutex<ChunkGenerationState>>>>,
     mesh_states: Arc<RwLock<HashMap<IVec3, Mutex<ChunkMeshState>>>>,
-    file_path: String,
+    save_errors: Arc<Mutex<Vec<(IVec3, LevelSaveError)>>>,
+    _in_flight: Option<SaveInFlight>,
+    regions: Option<RegionStorage>,
+    save_format: SaveFormat,
     chunk: RwLock<Chunk>,
 ) {
-    let chunk = chunk.read().expect("Chunk rw poisoned");
+    let chunk = read_chunk(&chunk);
     mesh_states
         .write()
         .expect("Mesh states rw poisoned")
@@ -449,22 +1886,106 @@ async fn save_chunk(
         .expect("Chunk states rw poisoned")
         .insert(chunk.position, Mutex::new(ChunkGenerationState::Removed));
 
-    match serde_json::to_string(&*chunk) {
-        Ok(serialized_chunk) => {
-            fs::write(
-                format!(
-                    "save/{}/chunk/{}_{}_{}.json",
-                    file_path, chunk.position.x, chunk.position.y, chunk.position.z
-                ),
-                serialized_chunk,
-            )
-            .expect("Failed to write chunk");
+    // Unmodified chunks are regenerated from the seed when next loaded
+    let Some(regions) = regions.filter(|_| chunk.dirty) else {
+        return;
+    };
+    // Edits that were undone leave the chunk dirty with the contents it was loaded with
+    if chunk.saved_hash == Some(chunk.content_hash()) {
+        return;
+    }
+    let result = save_format
+        .encode_entry(&chunk)
+        .map_err(LevelSaveError::Serialization)
+        .and_then(|data| {
+            regions
+                .write(chunk.position, &data)
+                .map_err(LevelSaveError::Io)
+        });
+    if let Err(error) = result {
+        save_errors
+            .lock()
+            .expect("Save errors mutex poisoned")
+            .push((chunk.position, error));
+    }
+}
+
+fn report_storage_errors(
+    levels: Res<Levels>,
+    mut save_failed_events: EventWriter<LevelSaveFailed>,
+    mut data_lost_events: EventWriter<ChunkDataLost>,
+) {
+    // Inactive levels are still finishing saves started before they were unloaded
+    for level in levels.levels.values() {
+        if let Ok(mut save_errors) = level.chunk_properties.save_errors.try_lock() {
+            for (position, error) in save_errors.drain(..) {
+                error!("Failed to save chunk at {position}: {error}");
+                save_failed_events.write(LevelSaveFailed {
+                    position: Some(position),
+                    error,
+                });
+            }
+        }
+        if let Ok(mut data_lost) = level.chunk_properties.data_lost.try_lock() {
+            for event in data_lost.drain(..) {
+                warn!(
+                    "Saved data for chunk at {} could not be read and was regenerated: {}",
+                    event.position, event.error
+                );
+                data_lost_events.write(event);
+            }
+        }
+    }
+}
+
+fn send_block_edit_events(
+    mut levels: ResMut<Levels>,
+    mut placed_events: EventWriter<BlockPlaced>,
+    mut removed_events: EventWriter<BlockRemoved>,
+) {
+    let level = levels.active_mut();
+    for (world, removed, placed) in level.chunk_properties.edits.drain(..) {
+        if let Some(block) = removed {
+            removed_events.write(BlockRemoved { world, block });
         }
-        Err(error) => eprintln!("Failed to serialize chunk at {}: {error:?}", chunk.position),
+        if let Some(block) = placed {
+            placed_events.write(BlockPlaced { world, block });
+        }
+    }
+}
+
+fn log_block_edits(
+    mut placed_events: EventReader<BlockPlaced>,
+    mut removed_events: EventReader<BlockRemoved>,
+) {
+    for event in removed_events.read() {
+        debug!("Removed {} at {}", event.block, event.world);
+    }
+    for event in placed_events.read() {
+        debug!("Placed {} at {}", event.block, event.world);
+    }
+}
+
+/// Location of a chunk saved on its own, before chunks were grouped into regions
+fn chunk_path(level_id: &str, position: IVec3, format: SaveFormat) -> String {
+    format!(
+        "save/{}/chunk/{}_{}_{}.{}",
+        level_id,
+        position.x,
+        position.y,
+        position.z,
+        format.extension()
+    )
+}
+
+/// Saves finish in inactive levels too, see [`unload_inactive_levels`]
+fn cleanup_saved_chunks(mut commands: Commands, mut levels: ResMut<Levels>) {
+    for level in levels.iter_mut() {
+        cleanup_saved_level_chunks(&mut commands, level);
     }
 }
 
-fn cleanup_saved_chunks(mut commands: Commands, mut level: ResMut<Level>) {
+fn cleanup_saved_level_chunks(commands: &mut Commands, level: &mut Level) {
     let removed_chunks = {
         let Ok(mut chunk_states) = level.chunk_properties.chunk_states.try_write() else {
             return;
@@ -482,8 +2003,515 @@ fn cleanup_saved_chunks(mut commands: Commands, mut level: ResMut<Level>) {
 
     for (position, _) in removed_chunks {
         level.chunk_properties.removed.remove(&position);
-        if let Some(entity) = level.bevy_properties.chunk_entities.remove(&position) {
-            commands.entity(entity).despawn();
+        // Also despawns the transparent and emissive child entities
+        if let Some(entities) = level.bevy_properties.chunk_entities.remove(&position) {
+            level.bevy_properties.vertex_count -= entities.vertex_count;
+            commands.entity(entities.opaque).despawn();
+        }
+        // Faces previously culled against this chunk need to be drawn again
+        level.rebuild_neighbor_meshes(position);
+    }
+}
+
+/// Hide every chunk entity of inactive levels<br>
+/// Chunks of the active level are left to Bevy's frustum culling, which uses the bounds of their meshes
+fn hide_inactive_level_chunks(levels: Res<Levels>, mut visibility_query: Query<&mut Visibility>) {
+    for (id, level) in levels.levels.iter() {
+        let active = *id == levels.active;
+        for entities in level.bevy_properties.chunk_entities.values() {
+            // The transparent and emissive entities inherit this visibility
+            let Ok(mut visibility) = visibility_query.get_mut(entities.opaque) else {
+                continue;
+            };
+            visibility.set_if_neq(if active {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        ecs::{event::Events, system::RunSystemOnce, world::World},
+        tasks::TaskPool,
+    };
+
+    use super::*;
+
+    /// Empty level that isn't saved, with its regions in a temporary directory named after `name`
+    fn level(name: &str) -> Level {
+        Level {
+            level_properties: LevelProperties {
+                id: format!("test_{name}"),
+                metadata: LevelMetadata::new("", GeneratorKind::Normal),
+                terrain_profile: TerrainProfile::default(),
+                caves: CaveProperties::default(),
+                ores: Vec::new(),
+                trees: TreeProperties::default(),
+                save_format: SaveFormat::default(),
+                regions: region::tests::storage(name),
+                persistent: false,
+            },
+            chunk_properties: ChunkProperties::default(),
+            mesh_properties: MeshProperties::default(),
+            bevy_properties: BevyProperties {
+                chunk_entities: Default::default(),
+                vertex_count: 0,
+                chunk_material: Handle::default(),
+                transparent_chunk_material: Handle::default(),
+                emissive_chunk_material: Handle::default(),
+            },
+        }
+    }
+
+    /// Add an empty chunk at `position` to the level's grid
+    fn insert_chunk(level: &mut Level, position: IVec3) {
+        level
+            .chunk_properties
+            .chunk_grid
+            .0
+            .insert(position, Arc::new(RwLock::new(Chunk::new(position))));
+    }
+
+    #[test]
+    fn inactive_levels_save_modified_chunks() {
+        IoTaskPool::get_or_init(TaskPool::new);
+        let mut inactive = level("inactive");
+        inactive.level_properties.persistent = true;
+        let position = IVec3::new(2, 0, -3);
+        let mut chunk = Chunk::new(position);
+        chunk.dirty = true;
+        inactive
+            .chunk_properties
+            .chunk_grid
+            .0
+            .insert(position, Arc::new(RwLock::new(chunk)));
+        let regions = inactive.level_properties.regions.clone();
+        let mut levels = Levels::new(level("active"));
+        levels.insert(inactive);
+
+        let mut world = World::new();
+        world.insert_resource(levels);
+        world.insert_resource(GameSettings::default());
+        world.run_system_once(unload_inactive_levels).unwrap();
+        let inactive = &world.resource::<Levels>().levels["test_inactive"];
+        assert!(inactive.chunk_properties.chunk_grid.0.is_empty());
+        assert!(inactive.chunk_properties.removed.contains(&position));
+
+        let saved = (0..200).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            regions.read(position).unwrap().is_some()
+        });
+        assert!(saved, "modified chunk of an inactive level wasn't saved");
+    }
+
+    /// Chunk at `position` with every block below `height` filled
+    fn filled_chunk(position: IVec3, height: i16) -> Chunk {
+        let mut chunk = Chunk::new(position);
+        if height > 0 {
+            chunk.set_area(
+                I16Vec3::ZERO,
+                I16Vec3::new(chunk::SIZE_I16 - 1, height - 1, chunk::SIZE_I16 - 1),
+                &Block::new(Identifier::new("test", "stone")),
+            );
+        }
+        chunk
+    }
+
+    /// Insert `chunk` as if it was meshed already
+    fn insert_meshed(level: &mut Level, chunk: Chunk) {
+        let position = chunk.position;
+        level
+            .chunk_properties
+            .chunk_grid
+            .0
+            .insert(position, Arc::new(RwLock::new(chunk)));
+        level.bevy_properties.chunk_entities.insert(
+            position,
+            ChunkEntities {
+                opaque: Entity::PLACEHOLDER,
+                transparent: Entity::PLACEHOLDER,
+                emissive: None,
+                vertex_count: 0,
+                detail: MeshDetail::Full,
+            },
+        );
+    }
+
+    #[test]
+    fn only_changed_neighbors_are_remeshed() {
+        let mut level = level("bordering");
+        // Ground filling the chunk up to half its height, with empty sky above
+        insert_meshed(&mut level, filled_chunk(IVec3::X, 16));
+        insert_meshed(&mut level, filled_chunk(IVec3::NEG_X, 16));
+        insert_meshed(&mut level, filled_chunk(IVec3::Y, 0));
+        insert_meshed(&mut level, filled_chunk(IVec3::NEG_Y, 32));
+        // Across an edge, meeting the bottom of the new chunk but not its empty top
+        insert_meshed(&mut level, filled_chunk(IVec3::new(1, -1, 0), 32));
+        insert_meshed(&mut level, filled_chunk(IVec3::new(-1, 1, 0), 32));
+        // Not meshed yet, so it will see the new chunk anyway
+        level
+            .chunk_properties
+            .chunk_grid
+            .0
+            .insert(IVec3::Z, Arc::new(RwLock::new(filled_chunk(IVec3::Z, 32))));
+
+        level.chunk_properties.chunk_grid.0.insert(
+            IVec3::ZERO,
+            Arc::new(RwLock::new(filled_chunk(IVec3::ZERO, 16))),
+        );
+        level.rebuild_meshes_bordering(IVec3::ZERO);
+        let mut remeshed = level
+            .mesh_properties
+            .remesh
+            .iter()
+            .copied()
+            .collect::<Vec<IVec3>>();
+        remeshed.sort_by_key(|position| position.to_array());
+        let mut expected = vec![IVec3::X, IVec3::NEG_X, IVec3::NEG_Y, IVec3::new(1, -1, 0)];
+        expected.sort_by_key(|position| position.to_array());
+        assert_eq!(remeshed, expected);
+    }
+
+    /// World holding `level` as the only level and a camera at the origin, with at most `budget` tasks started per frame
+    fn budget_world(level: Level, budget: usize) -> World {
+        AsyncComputeTaskPool::get_or_init(TaskPool::new);
+        let mut world = World::new();
+        world.insert_resource(Levels::new(level));
+        world.insert_resource(GameSettings {
+            max_chunk_tasks_per_frame: budget,
+            ..Default::default()
+        });
+        world.spawn((Camera::default(), Transform::default()));
+        world
+    }
+
+    #[test]
+    fn generation_spawns_the_budget() {
+        let mut level = level("generation_budget");
+        // Generates nothing, so the tasks finish straight away
+        level.level_properties.metadata.generator = GeneratorKind::Flat { layers: Vec::new() };
+        let mut world = budget_world(level, 5);
+
+        world
+            .run_system_once(mark_nearby_chunks_uninitialized)
+            .unwrap();
+        let level = world.resource::<Levels>().active();
+        let generating = &level.chunk_properties.generating;
+        assert_eq!(generating.len(), 5);
+        assert_eq!(level.chunk_properties.chunk_states.read().unwrap().len(), 5);
+        // Nearest first
+        assert!(generating.contains_key(&IVec3::ZERO));
+    }
+
+    #[test]
+    fn meshing_spawns_the_budget() {
+        let mut level = level("mesh_budget");
+        for x in -10..10 {
+            insert_chunk(&mut level, IVec3::new(x, 0, 0));
+            level.rebuild_mesh(IVec3::new(x, 0, 0));
+        }
+        let mut world = budget_world(level, 5);
+        world.insert_resource(BlockAtlasManager(Arc::new(
+            chunk::mesh::tests::atlas_manager(),
+        )));
+
+        world.run_system_once(handle_remesh_queue).unwrap();
+        let level = world.resource::<Levels>().active();
+        let mesh_states = level.mesh_properties.mesh_states.read().unwrap();
+        assert_eq!(mesh_states.len(), 5);
+        assert!(mesh_states.contains_key(&IVec3::ZERO));
+        // The rest waits for later frames
+        assert_eq!(level.mesh_properties.remesh.len(), 15);
+    }
+
+    #[test]
+    fn stalest_chunks_are_evicted_first() {
+        let (recent, stale, far_stale, never_near) = (
+            IVec3::new(5, 0, 0),
+            IVec3::new(1, 0, 0),
+            IVec3::new(-3, 0, 0),
+            IVec3::new(0, 2, 0),
+        );
+        let loaded = [recent, stale, far_stale, never_near];
+        let last_near = HashMap::from_iter([(recent, 9), (stale, 4), (far_stale, 4)]);
+        let evictions = |max_loaded| {
+            select_evictions(loaded.into_iter(), &last_near, 10, IVec3::ZERO, max_loaded)
+        };
+
+        assert!(evictions(4).is_empty());
+        assert!(evictions(10).is_empty());
+        assert_eq!(evictions(3), vec![never_near]);
+        // Equally stale chunks go furthest first
+        assert_eq!(evictions(2), vec![never_near, far_stale]);
+        assert_eq!(evictions(0), vec![never_near, far_stale, stale, recent]);
+    }
+
+    #[test]
+    fn staleness_survives_the_tick_wrapping() {
+        let (before_wrap, after_wrap) = (IVec3::X, IVec3::Y);
+        let last_near = HashMap::from_iter([(before_wrap, u32::MAX - 1), (after_wrap, 1)]);
+        let evictions = select_evictions(
+            [after_wrap, before_wrap].into_iter(),
+            &last_near,
+            2,
+            IVec3::ZERO,
+            1,
+        );
+        assert_eq!(evictions, vec![before_wrap]);
+    }
+
+    #[test]
+    fn single_block_exports_as_a_cube() {
+        let mut level = level("export");
+        let position = IVec3::new(1, 0, -1);
+        let mut chunk = Chunk::new(position);
+        chunk.contents[Chunk::to_index(I16Vec3::new(3, 4, 5))] =
+            Some(Block::new(chunk::mesh::tests::identifier("stone")));
+        level
+            .chunk_properties
+            .chunk_grid
+            .0
+            .insert(position, Arc::new(RwLock::new(chunk)));
+        let atlas_manager = Arc::new(chunk::mesh::tests::atlas_manager());
+        let path =
+            std::env::temp_dir().join(format!("voxel_game_export_{}.obj", std::process::id()));
+
+        level
+            .export_chunk_obj(position, &atlas_manager, &path)
+            .unwrap();
+        let obj = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let positions = obj
+            .lines()
+            .filter_map(|line| line.strip_prefix("v "))
+            .collect::<Vec<&str>>();
+        assert_eq!(positions.len(), 8);
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("f ")).count(),
+            12
+        );
+        // In world coordinates
+        assert!(positions.contains(&"35 4 -27"));
+        assert!(positions.contains(&"36 5 -26"));
+    }
+
+    #[test]
+    fn empty_chunk_exports_nothing() {
+        let mut level = level("export_empty");
+        insert_chunk(&mut level, IVec3::ZERO);
+        let atlas_manager = Arc::new(chunk::mesh::tests::atlas_manager());
+        let path = std::env::temp_dir().join(format!(
+            "voxel_game_export_empty_{}.obj",
+            std::process::id()
+        ));
+
+        level
+            .export_chunk_obj(IVec3::ZERO, &atlas_manager, &path)
+            .unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn edits_send_block_events() {
+        let mut level = level("events");
+        insert_chunk(&mut level, IVec3::NEG_ONE);
+        let mut app = App::new();
+        app.add_event::<BlockPlaced>()
+            .add_event::<BlockRemoved>()
+            .insert_resource(Levels::new(level))
+            .add_systems(Update, send_block_edit_events);
+        let world = IVec3::new(-3, -20, -31);
+        let stone = Identifier::new("test", "stone");
+
+        let set_block = |app: &mut App, block: Option<Block>| {
+            app.world_mut()
+                .resource_mut::<Levels>()
+                .active_mut()
+                .set_block(world, block);
+            app.update();
+        };
+        set_block(&mut app, Some(Block::new(stone.clone())));
+        let placed = app.world().resource::<Events<BlockPlaced>>();
+        let placed = placed.get_cursor().read(placed).collect::<Vec<_>>();
+        assert_eq!(placed.len(), 1);
+        assert_eq!((placed[0].world, &placed[0].block), (world, &stone));
+        assert!(app.world().resource::<Events<BlockRemoved>>().is_empty());
+
+        set_block(&mut app, None);
+        let removed = app.world().resource::<Events<BlockRemoved>>();
+        let removed = removed.get_cursor().read(removed).collect::<Vec<_>>();
+        assert_eq!(removed.len(), 1);
+        assert_eq!((removed[0].world, &removed[0].block), (world, &stone));
+    }
+
+    #[test]
+    fn edge_edit_remeshes_chunks_sharing_the_edge() {
+        let mut level = level("edge_edit");
+        for offset in NEIGHBORHOOD_OFFSETS {
+            insert_chunk(&mut level, offset);
+        }
+        insert_chunk(&mut level, IVec3::ZERO);
+        // On the chunk's +X and -Y borders and away from its Z borders
+        level.set_block(
+            IVec3::new(31, 0, 15),
+            Some(Block::new(Identifier::new("test", "stone"))),
+        );
+
+        let mut remeshed = level
+            .mesh_properties
+            .remesh
+            .iter()
+            .copied()
+            .collect::<Vec<IVec3>>();
+        remeshed.sort_by_key(|position| position.to_array());
+        let mut expected = vec![IVec3::ZERO, IVec3::X, IVec3::NEG_Y, IVec3::new(1, -1, 0)];
+        expected.sort_by_key(|position| position.to_array());
+        assert_eq!(remeshed, expected);
+    }
+
+    #[test]
+    fn fill_remeshes_touched_chunks_once() {
+        let mut level = level("fill");
+        for x in -1..=2 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    insert_chunk(&mut level, IVec3::new(x, y, z));
+                }
+            }
+        }
+        // Straddles the border between the chunks at x=0 and x=1, and lies on the bottom and front borders of both
+        let stone = Block::new(Identifier::new("test", "stone"));
+        let mut edits = Vec::new();
+        for x in 28..38 {
+            for y in 0..10 {
+                for z in 0..10 {
+                    edits.push((IVec3::new(x, y, z), Some(stone.clone())));
+                }
+            }
+        }
+        level.set_blocks(edits);
+
+        let mut remeshed = level
+            .mesh_properties
+            .remesh
+            .iter()
+            .copied()
+            .collect::<Vec<IVec3>>();
+        remeshed.sort_by_key(|position| position.to_array());
+        let mut expected = Vec::new();
+        for x in 0..=1 {
+            for y in -1..=0 {
+                for z in -1..=0 {
+                    expected.push(IVec3::new(x, y, z));
+                }
+            }
+        }
+        expected.sort_by_key(|position| position.to_array());
+        assert_eq!(remeshed, expected);
+    }
+
+    #[test]
+    fn snapshot_reflects_pipeline_states() {
+        let mut level = level("snapshot");
+        let (loaded, meshing, generating, generated, queued) =
+            (IVec3::ZERO, IVec3::X, IVec3::Y, IVec3::Z, IVec3::NEG_X);
+        insert_chunk(&mut level, loaded);
+        insert_chunk(&mut level, meshing);
+        {
+            let mut chunk_states = level.chunk_properties.chunk_states.write().unwrap();
+            chunk_states.insert(generating, Mutex::new(ChunkGenerationState::Uninitialized));
+            chunk_states.insert(generated, Mutex::new(ChunkGenerationState::Ready(None)));
+        }
+        level
+            .mesh_properties
+            .mesh_states
+            .write()
+            .unwrap()
+            .insert(meshing, Mutex::new(ChunkMeshState::Unmeshed));
+        level.mesh_properties.remesh.insert(queued);
+
+        let snapshot = level.chunk_pipeline_snapshot();
+        assert_eq!(snapshot.len(), 5);
+        for (position, stage) in snapshot.iter() {
+            assert_eq!(
+                level.chunk_pipeline_stage(*position),
+                Some(*stage),
+                "{position}"
+            );
         }
+        assert_eq!(level.chunk_pipeline_stage(IVec3::splat(100)), None);
+        assert_eq!(
+            snapshot[&loaded],
+            (GenerationStage::Loaded, MeshStage::Idle)
+        );
+        assert_eq!(
+            snapshot[&meshing],
+            (GenerationStage::Loaded, MeshStage::Unmeshed)
+        );
+        assert_eq!(
+            snapshot[&generating],
+            (GenerationStage::Uninitialized, MeshStage::Idle)
+        );
+        assert_eq!(
+            snapshot[&generated],
+            (GenerationStage::Ready, MeshStage::Idle)
+        );
+        assert_eq!(
+            snapshot[&queued],
+            (GenerationStage::Unknown, MeshStage::Queued)
+        );
+
+        // A state held by a background task can't be read
+        let chunk_states = level.chunk_properties.chunk_states.read().unwrap();
+        let _guard = chunk_states[&generating].lock().unwrap();
+        assert_eq!(
+            level.chunk_pipeline_snapshot()[&generating].0,
+            GenerationStage::Unknown
+        );
+        assert_eq!(
+            level.chunk_pipeline_stage(generating),
+            Some((GenerationStage::Unknown, MeshStage::Idle))
+        );
+    }
+
+    #[test]
+    fn garbage_chunk_is_backed_up() {
+        let regions = region::tests::storage("garbage");
+        let data_lost = Mutex::new(Vec::new());
+        let position = IVec3::new(3, -1, 2);
+        let garbage = b"not a chunk".to_vec();
+        regions.write(position, &garbage).unwrap();
+
+        let loaded = load_chunk(&regions, "test_garbage", position, &data_lost).unwrap();
+        assert!(loaded.is_none());
+        let data_lost = data_lost.into_inner().unwrap();
+        assert_eq!(data_lost.len(), 1);
+        assert_eq!(data_lost[0].position, position);
+        let backup = data_lost[0]
+            .backup
+            .as_ref()
+            .expect("garbage wasn't backed up");
+        assert_eq!(fs::read(backup).unwrap(), garbage);
+        // Regenerated from now on instead of reporting the loss again
+        assert_eq!(regions.read(position).unwrap(), None);
+    }
+
+    #[test]
+    fn saved_chunk_is_loaded() {
+        let regions = region::tests::storage("saved");
+        let data_lost = Mutex::new(Vec::new());
+        let position = IVec3::new(-5, 0, 1);
+        let data = SaveFormat::Binary
+            .encode_entry(&Chunk::new(position))
+            .unwrap();
+        regions.write(position, &data).unwrap();
+
+        let loaded = load_chunk(&regions, "test_saved", position, &data_lost).unwrap();
+        assert!(loaded.is_some());
+        assert!(data_lost.into_inner().unwrap().is_empty());
     }
 }
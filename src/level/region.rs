@@ -0,0 +1,366 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{math::IVec3, platform::collections::HashMap};
+
+/// Width of a region in chunks along each axis
+pub const REGION_SIZE: i32 = 16;
+const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+/// Each header entry is a little endian `u32` offset followed by a `u32` length
+const HEADER_ENTRY_SIZE: u64 = 8;
+const HEADER_SIZE: u64 = REGION_VOLUME as u64 * HEADER_ENTRY_SIZE;
+/// Size past which a region is compacted before growing further, if most of it is overwritten data<br>
+/// Well below the `u32` offsets' limit, past which every save to the region would fail
+const COMPACT_THRESHOLD: u64 = 64 << 20;
+
+/// Stores a cube of [`REGION_SIZE`] chunks per file behind a header of offsets and lengths<br>
+/// Chunk data is written over its previous copy when it fits and appended otherwise, the header only being updated
+/// once the data is fully written. A crash while overwriting leaves the chunk undecodable, which is backed up and
+/// regenerated when loaded, a crash while appending leaves the previous copy readable<br>
+/// Space left behind by appended and cleared chunks is reclaimed by compacting the region once it passes [`COMPACT_THRESHOLD`]
+#[derive(Clone)]
+pub struct RegionStorage {
+    directory: String,
+    /// Only holds regions being accessed, see [`RegionStorage::with_lock`]
+    locks: Arc<Mutex<HashMap<IVec3, Arc<Mutex<()>>>>>,
+}
+
+impl RegionStorage {
+    pub fn new(directory: String) -> Self {
+        Self {
+            directory,
+            locks: Default::default(),
+        }
+    }
+
+    pub fn to_region_coordinates(chunk_position: IVec3) -> IVec3 {
+        chunk_position.div_euclid(IVec3::splat(REGION_SIZE))
+    }
+
+    /// Index of the chunk's entry within its region's header
+    fn slot(chunk_position: IVec3) -> usize {
+        let local = chunk_position.rem_euclid(IVec3::splat(REGION_SIZE));
+        (local.x + local.y * REGION_SIZE + local.z * REGION_SIZE * REGION_SIZE) as usize
+    }
+
+    fn path(&self, region: IVec3) -> String {
+        format!(
+            "{}/r_{}_{}_{}.region",
+            self.directory, region.x, region.y, region.z
+        )
+    }
+
+    /// Every access to a region file goes through its lock so concurrent saves can't interleave header writes<br>
+    /// The lock is removed again once no other access holds or waits on it, so regions visited once don't stay in `locks`
+    fn with_lock<T>(&self, region: IVec3, access: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        let lock = self
+            .locks
+            .lock()
+            .expect("Region locks mutex poisoned")
+            .entry(region)
+            .or_default()
+            .clone();
+        let result = {
+            let _guard = lock.lock().expect("Region lock poisoned");
+            access()
+        };
+        let mut locks = self.locks.lock().expect("Region locks mutex poisoned");
+        // Held by the map and this access only, any other access clones it while holding `locks`
+        if Arc::strong_count(&lock) == 2 {
+            locks.remove(&region);
+        }
+        result
+    }
+
+    /// Returns `None` if the chunk has never been written to its region
+    pub fn read(&self, chunk_position: IVec3) -> io::Result<Option<Vec<u8>>> {
+        let region = Self::to_region_coordinates(chunk_position);
+        self.with_lock(region, || {
+            let mut file = match File::open(self.path(region)) {
+                Ok(file) => file,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(error) => return Err(error),
+            };
+            let Some((offset, length)) = read_entry(&mut file, Self::slot(chunk_position))? else {
+                return Ok(None);
+            };
+            let mut data = vec![0; length as usize];
+            file.seek(SeekFrom::Start(offset as u64))?;
+            file.read_exact(&mut data)?;
+            Ok(Some(data))
+        })
+    }
+
+    /// Copies a chunk's undecodable data next to the region as `c_<x>_<y>_<z>.corrupt-<timestamp>`
+    /// and clears its entry so the chunk is regenerated instead of failing on every load<br>
+    /// Returns the path of the backup
+    pub fn backup_corrupt(
+        &self,
+        chunk_position: IVec3,
+        data: &[u8],
+        timestamp: u64,
+    ) -> io::Result<String> {
+        let (mut file, backup_path) = create_backup(&format!(
+            "{}/c_{}_{}_{}.corrupt-{timestamp}",
+            self.directory, chunk_position.x, chunk_position.y, chunk_position.z
+        ))?;
+        file.write_all(data)?;
+        self.clear(chunk_position)?;
+        Ok(backup_path)
+    }
+
+    /// Clears the chunk's entry so it reads as never written, its data is left in the file until the region is compacted
+    pub fn clear(&self, chunk_position: IVec3) -> io::Result<()> {
+        let region = Self::to_region_coordinates(chunk_position);
+        self.with_lock(region, || {
+            let mut file = match OpenOptions::new().write(true).open(self.path(region)) {
+                Ok(file) => file,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+                Err(error) => return Err(error),
+            };
+            // A header that was never fully written has no entries to clear, and writing it would leave it partial
+            if file.metadata()?.len() < HEADER_SIZE {
+                return Ok(());
+            }
+            file.seek(SeekFrom::Start(
+                Self::slot(chunk_position) as u64 * HEADER_ENTRY_SIZE,
+            ))?;
+            file.write_all(&[0; HEADER_ENTRY_SIZE as usize])
+        })
+    }
+
+    pub fn write(&self, chunk_position: IVec3, data: &[u8]) -> io::Result<()> {
+        let region = Self::to_region_coordinates(chunk_position);
+        let length = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "chunk data is too large"))?;
+        self.with_lock(region, || {
+            let path = self.path(region);
+            if fs::metadata(&path)
+                .is_ok_and(|metadata| metadata.len() + data.len() as u64 > COMPACT_THRESHOLD)
+            {
+                compact(&path)?;
+            }
+
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+            if file.metadata()?.len() < HEADER_SIZE {
+                file.set_len(HEADER_SIZE)?;
+            }
+
+            let slot = Self::slot(chunk_position);
+            let offset = match read_entry(&mut file, slot)? {
+                Some((offset, previous_length)) if length <= previous_length => offset as u64,
+                _ => file.seek(SeekFrom::End(0))?,
+            };
+            let offset = u32::try_from(offset)
+                .map_err(|_| io::Error::new(io::ErrorKind::FileTooLarge, "region file is full"))?;
+            file.seek(SeekFrom::Start(offset as u64))?;
+            file.write_all(data)?;
+
+            // The header is only updated once the data is fully written
+            write_entry(&mut file, slot, offset, length)
+        })
+    }
+}
+
+/// Rewrite the region at `path` with only the data its header points to, packed right after the header<br>
+/// Does nothing unless most of the file is data no entry points to. The region is written to a temporary file
+/// which then replaces it, so a crash leaves either the old or the compacted region whole
+fn compact(path: &str) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let file_length = file.metadata()?.len();
+    if file_length < HEADER_SIZE {
+        return Ok(());
+    }
+    let mut header = vec![0; HEADER_SIZE as usize];
+    file.read_exact(&mut header)?;
+    let entries = header
+        .chunks_exact(HEADER_ENTRY_SIZE as usize)
+        .map(|entry| parse_entry(entry, file_length))
+        .collect::<Vec<Option<(u32, u32)>>>();
+    let live = entries
+        .iter()
+        .flatten()
+        .map(|(_, length)| *length as u64)
+        .sum::<u64>();
+    if file_length - HEADER_SIZE <= 2 * live {
+        return Ok(());
+    }
+
+    let temporary_path = format!("{path}.compacting");
+    let mut compacted = File::create(&temporary_path)?;
+    compacted.set_len(HEADER_SIZE)?;
+    let mut offset = HEADER_SIZE as u32;
+    for (slot, entry) in entries.into_iter().enumerate() {
+        let Some((previous_offset, length)) = entry else {
+            continue;
+        };
+        let mut data = vec![0; length as usize];
+        file.seek(SeekFrom::Start(previous_offset as u64))?;
+        file.read_exact(&mut data)?;
+        compacted.seek(SeekFrom::Start(offset as u64))?;
+        compacted.write_all(&data)?;
+        write_entry(&mut compacted, slot, offset, length)?;
+        // No larger than the offsets in the old file, which fit in a u32
+        offset += length;
+    }
+    compacted.sync_all()?;
+    drop(file);
+    fs::rename(&temporary_path, path)
+}
+
+fn write_entry(file: &mut File, slot: usize, offset: u32, length: u32) -> io::Result<()> {
+    let mut entry = [0; HEADER_ENTRY_SIZE as usize];
+    entry[..4].copy_from_slice(&offset.to_le_bytes());
+    entry[4..].copy_from_slice(&length.to_le_bytes());
+    file.seek(SeekFrom::Start(slot as u64 * HEADER_ENTRY_SIZE))?;
+    file.write_all(&entry)
+}
+
+/// Returns `None` for an empty entry or one written past the end of a truncated file
+fn read_entry(file: &mut File, slot: usize) -> io::Result<Option<(u32, u32)>> {
+    let file_length = file.metadata()?.len();
+    if file_length < HEADER_SIZE {
+        return Ok(None);
+    }
+    let mut entry = [0; HEADER_ENTRY_SIZE as usize];
+    file.seek(SeekFrom::Start(slot as u64 * HEADER_ENTRY_SIZE))?;
+    file.read_exact(&mut entry)?;
+    Ok(parse_entry(&entry, file_length))
+}
+
+/// Offset and length of a header `entry`, see [`read_entry`]
+fn parse_entry(entry: &[u8], file_length: u64) -> Option<(u32, u32)> {
+    let offset = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+    let length = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+    if length == 0 || offset as u64 + length as u64 > file_length {
+        return None;
+    }
+    Some((offset, length))
+}
+
+/// Creates a new file at `path`, numbered `<path>-<n>` until the name isn't taken so an earlier backup is never overwritten<br>
+/// Returns the file and the path it was created at
+pub fn create_backup(path: &str) -> io::Result<(File, String)> {
+    for number in 1.. {
+        let backup_path = match number {
+            1 => path.to_owned(),
+            _ => format!("{path}-{number}"),
+        };
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup_path)
+        {
+            Ok(file) => return Ok((file, backup_path)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Storage in a directory of its own under the system's temporary directory, emptied first
+    pub(crate) fn storage(name: &str) -> RegionStorage {
+        let directory =
+            std::env::temp_dir().join(format!("voxel_game_region_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        RegionStorage::new(directory.to_string_lossy().into_owned())
+    }
+
+    fn file_length(storage: &RegionStorage, chunk_position: IVec3) -> u64 {
+        let region = RegionStorage::to_region_coordinates(chunk_position);
+        fs::metadata(storage.path(region)).unwrap().len()
+    }
+
+    #[test]
+    fn overwrite_reuses_space() {
+        let storage = storage("overwrite");
+        let position = IVec3::new(1, -2, 3);
+        storage.write(position, &[1; 100]).unwrap();
+        let length = file_length(&storage, position);
+
+        storage.write(position, &[2; 100]).unwrap();
+        storage.write(position, &[3; 60]).unwrap();
+        assert_eq!(file_length(&storage, position), length);
+        assert_eq!(storage.read(position).unwrap(), Some(vec![3; 60]));
+
+        // Too large for the old space, so appended
+        storage.write(position, &[4; 200]).unwrap();
+        assert_eq!(file_length(&storage, position), length + 200);
+        assert_eq!(storage.read(position).unwrap(), Some(vec![4; 200]));
+    }
+
+    #[test]
+    fn compaction_keeps_every_chunk() {
+        let storage = storage("compaction");
+        let kept = [IVec3::ZERO, IVec3::new(5, 6, 7), IVec3::new(15, 15, 15)];
+        for (index, position) in kept.iter().enumerate() {
+            storage.write(*position, &[index as u8 + 1; 10]).unwrap();
+        }
+        let cleared = IVec3::new(2, 0, 0);
+        storage.write(cleared, &[9; 1000]).unwrap();
+        storage.clear(cleared).unwrap();
+        // Growing a chunk leaves its old copy behind
+        storage.write(kept[1], &[20; 500]).unwrap();
+
+        let path = storage.path(IVec3::ZERO);
+        compact(&path).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            HEADER_SIZE + 10 + 500 + 10
+        );
+        assert_eq!(storage.read(kept[0]).unwrap(), Some(vec![1; 10]));
+        assert_eq!(storage.read(kept[1]).unwrap(), Some(vec![20; 500]));
+        assert_eq!(storage.read(kept[2]).unwrap(), Some(vec![3; 10]));
+        assert_eq!(storage.read(cleared).unwrap(), None);
+
+        // Mostly live data is left alone
+        compact(&path).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            HEADER_SIZE + 10 + 500 + 10
+        );
+    }
+
+    #[test]
+    fn idle_region_locks_are_dropped() {
+        let storage = storage("locks");
+        for x in 0..4 {
+            storage
+                .write(IVec3::new(x * REGION_SIZE, 0, 0), &[1; 10])
+                .unwrap();
+            storage.read(IVec3::new(x * REGION_SIZE, 0, 0)).unwrap();
+        }
+        assert!(storage.locks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn backups_in_the_same_second_are_kept() {
+        let storage = storage("backups");
+        let position = IVec3::new(4, 0, -4);
+        let mut backups = Vec::new();
+        for data in [[1; 10], [2; 10], [3; 10]] {
+            storage.write(position, &data).unwrap();
+            backups.push(storage.backup_corrupt(position, &data, 7).unwrap());
+            assert_eq!(storage.read(position).unwrap(), None);
+        }
+        backups.dedup();
+        assert_eq!(backups.len(), 3);
+        for (backup, data) in backups.iter().zip([1, 2, 3]) {
+            assert_eq!(fs::read(backup).unwrap(), vec![data; 10]);
+        }
+    }
+}
@@ -28,68 +28,118 @@
  *  > Game state
  *      - Startup
  *      - Resource parsing/atlasing
- *      - Paused
+ *      - Paused [✓]
  */
 
-use std::sync::Arc;
+use std::{
+    fmt, fs,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use bevy::{
     DefaultPlugins,
     app::{App, Update},
-    asset::Assets,
+    asset::{AssetServer, Assets, Handle, LoadState, io::file::FileAssetReader},
     color::{Alpha, Color},
     core_pipeline::core_3d::Camera3d,
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
     ecs::{
+        change_detection::DetectChangesMut,
         component::Component,
         entity::Entity,
         event::EventReader,
+        observer::Trigger,
         query::{With, Without},
         resource::Resource,
-        schedule::IntoScheduleConfigs,
-        system::{Commands, Res, ResMut, Single},
+        schedule::{
+            IntoScheduleConfigs,
+            common_conditions::{resource_changed, resource_exists},
+        },
+        system::{Commands, Local, Query, Res, ResMut, Single},
     },
     image::Image,
-    input::{ButtonInput, keyboard::KeyCode, mouse::MouseWheel},
-    math::{IVec3, Vec3, primitives::Cuboid},
-    pbr::{AmbientLight, MeshMaterial3d, StandardMaterial},
+    input::{
+        ButtonInput,
+        keyboard::KeyCode,
+        mouse::{MouseButton, MouseWheel},
+    },
+    log::{error, info, warn},
+    math::{FloatExt, IVec3, Rect, UVec2, Vec2, Vec3, primitives::Cuboid},
+    pbr::{
+        AmbientLight, DistanceFog, FogFalloff, MeshMaterial3d, StandardMaterial,
+        wireframe::{NoWireframe, Wireframe, WireframeColor, WireframeConfig, WireframePlugin},
+    },
+    platform::collections::HashMap,
     prelude::PluginGroup,
     render::{
-        camera::{Camera, PerspectiveProjection, Projection},
-        mesh::{Mesh, Mesh3d},
+        RenderPlugin,
+        alpha::AlphaMode,
+        camera::{Camera, ClearColor, PerspectiveProjection, Projection},
+        mesh::{Mesh, Mesh3d, VertexAttributeValues},
+        settings::{WgpuFeatures, WgpuSettings},
         texture::ImagePlugin,
+        view::{
+            Visibility,
+            screenshot::{Screenshot, ScreenshotCaptured, save_to_disk},
+        },
     },
     state::{
         app::AppExtStates,
         commands::CommandsStatesExt,
         condition::in_state,
-        state::{OnEnter, States},
+        state::{OnEnter, OnExit, OnTransition, State, States},
     },
-    text::TextLayout,
+    text::{TextFont, TextLayout},
+    time::{Time, Timer, TimerMode},
     transform::components::Transform,
     ui::{BackgroundColor, Node, PositionType, Val, widget::Text},
-    window::{PrimaryWindow, Window},
+    utils::default,
+    window::{
+        MonitorSelection, PrimaryWindow, VideoModeSelection, Window, WindowMode, WindowPlugin,
+        WindowResolution,
+    },
 };
 use bevy_asset_loader::loading_state::{
     LoadingState, LoadingStateAppExt, config::ConfigureLoadingState,
 };
-use serde::{Deserialize, Serialize};
+use serde::{
+    Deserialize, Serialize, Serializer,
+    de::{self, Visitor},
+};
 
 use crate::{
-    block::{Block, BlockAssets, BlockAtlasManager, BlockRay},
-    camera_control::MovableCamera,
-    chunk::{Chunk, ChunkGrid},
-    level::Level,
+    atlas::TextureAnimation,
+    block::{
+        Block, BlockAssets, BlockAtlasManager, BlockProperties, BlockRay, BlockRenderLayer,
+        BlockTextureFile, RaycastHit, find_block_textures,
+    },
+    camera_control::{MovableCamera, MovementMode, PlayerPhysics},
+    chunk::{self, Chunk, ChunkGrid, mesh::MeshStrategy},
+    config::{Config, ConfigFile},
+    day_cycle::TimeOfDay,
+    input::{Action, ActionInput, GamepadBindings, InputBindings},
+    level::{BlockRemoved, ChunkDataLost, LevelSaveFailed, Levels},
 };
 
 mod atlas;
+mod benchmark;
 mod block;
 mod camera_control;
 mod chunk;
+mod config;
+mod day_cycle;
+mod input;
 mod level;
+mod menu;
+mod sound;
+mod teleport;
 
 pub const DEFAULT_NAMESPACE: &str = "builtin";
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Identifier(pub String, pub String);
 
 impl Identifier {
@@ -98,7 +148,7 @@ impl Identifier {
     }
 
     pub fn as_string(&self) -> String {
-        format!("{}:{}", self.0, self.1)
+        self.to_string()
     }
 
     pub fn with_path(&self, path: &str) -> Self {
@@ -108,20 +158,203 @@ impl Identifier {
     pub fn with_namespace(&self, namespace: &str) -> Self {
         Self(namespace.to_owned(), self.1.clone())
     }
+
+    /// Parse a `namespace:path` string, splitting on the first `:`<br>
+    /// A string without a namespace is placed in [`DEFAULT_NAMESPACE`]
+    pub fn parse(string: &str) -> Result<Self, IdentifierError> {
+        if string.chars().any(char::is_whitespace) {
+            return Err(IdentifierError::Whitespace);
+        }
+        let (namespace, path) = string
+            .split_once(':')
+            .unwrap_or((DEFAULT_NAMESPACE, string));
+        if namespace.is_empty() {
+            return Err(IdentifierError::EmptyNamespace);
+        }
+        if path.is_empty() {
+            return Err(IdentifierError::EmptyPath);
+        }
+        Ok(Self::new(namespace, path))
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.0, self.1)
+    }
+}
+
+impl Serialize for Identifier {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
 }
 
+impl<'de> Deserialize<'de> for Identifier {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct IdentifierVisitor;
+
+        impl<'de> Visitor<'de> for IdentifierVisitor {
+            type Value = Identifier;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("\"namespace:path\" string or [namespace, path] sequence")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Identifier::parse(value).map_err(E::custom)
+            }
+
+            // Saves written before identifiers were serialized as strings store them as a two element sequence
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let namespace: String = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let path: String = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                Ok(Identifier(namespace, path))
+            }
+        }
+
+        deserializer.deserialize_any(IdentifierVisitor)
+    }
+}
+
+impl FromStr for Identifier {
+    type Err = IdentifierError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Self::parse(string)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentifierError {
+    EmptyNamespace,
+    EmptyPath,
+    Whitespace,
+}
+
+impl fmt::Display for IdentifierError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyNamespace => f.write_str("identifier namespace is empty"),
+            Self::EmptyPath => f.write_str("identifier path is empty"),
+            Self::Whitespace => f.write_str("identifier contains whitespace"),
+        }
+    }
+}
+
+impl std::error::Error for IdentifierError {}
+
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Default, States)]
 enum GameState {
     #[default]
     AssetLoading,
     CreateAtlases,
+    /// Choosing or creating the world to play
+    MainMenu,
     InGame,
+    /// The level stays loaded but the player and time of day are frozen and the cursor is released
+    Paused,
 }
 
+/// Run condition for systems that keep the loaded level up to date whether or not the game is paused
+fn in_game_or_paused(state: Res<State<GameState>>) -> bool {
+    matches!(state.get(), GameState::InGame | GameState::Paused)
+}
+
+/// Marks the text shown while [`GameState::Paused`]
+#[derive(Component)]
+struct PauseMenu;
+
+const MIN_FOV_DEGREES: f32 = 30.;
+const MAX_FOV_DEGREES: f32 = 120.;
+/// Degrees the field of view changes by each key press
+const FOV_STEP_DEGREES: f32 = 5.;
+/// Seconds zooming in or out fully takes
+const ZOOM_DURATION: f32 = 0.15;
+/// Largest radius the brush can be resized to, a cube of 17 blocks across
+const MAX_BRUSH_RADIUS: i32 = 8;
+/// Upper limit for [`GameSettings::reach`] so long rays can't stall meshing with far away edits
+const MAX_REACH: f32 = 64.;
+
 #[derive(Resource)]
 struct GameSettings {
+    /// Radius in chunks of the loaded cylinder around the camera
     horizontal_render_distance: i32,
+    /// Chunks loaded above and below the camera
     vertical_render_distance: i32,
+    /// Largest either render distance can be set to, both are at least 0
+    max_render_distance: i32,
+    /// Hard cap on loaded chunks, the least recently near chunks are unloaded past this regardless of render distance
+    max_loaded_chunks: usize,
+    /// Generation tasks and meshing tasks started per frame, each counted separately<br>
+    /// Remaining work is deferred to later frames, nearest to the camera first
+    max_chunk_tasks_per_frame: usize,
+    /// Finished chunk meshes uploaded per frame
+    max_meshes_applied_per_frame: usize,
+    /// Chunks further than this many chunks from the camera horizontally are meshed at a coarser detail, see
+    /// [`build_lod_mesh`](chunk::mesh::build_lod_mesh)<br>
+    /// At or beyond the horizontal render distance every chunk is meshed at full detail
+    lod_distance: i32,
+    /// Modified chunks being written to disk at once, further chunks stay loaded and are retried next frame
+    max_saves_in_flight: usize,
+    /// Maximum length of a column placed by the place column action
+    column_fill_depth: u32,
+    /// Blocks the brush reaches out from the targeted block along each axis, 0 editing only the targeted block
+    brush_radius: i32,
+    /// Furthest distance from the camera a block can be interacted with, between 0 and [`MAX_REACH`]
+    reach: f32,
+    /// Darken chunk mesh vertices surrounded by blocks, disabling it makes meshing cheaper
+    ambient_occlusion: bool,
+    /// Sample lower resolution copies of the block atlas for distant faces, disabling it keeps textures crisp at the cost of shimmering
+    mipmaps: bool,
+    /// How chunk meshes are built, applied to every level
+    mesh_strategy: MeshStrategy,
+    /// Vertical field of view of the camera, between [`MIN_FOV_DEGREES`] and [`MAX_FOV_DEGREES`]
+    fov_degrees: f32,
+    /// Field of view the camera narrows to while the zoom key is held
+    zoom_fov_degrees: f32,
+    /// Fade terrain into the fog color towards the horizontal render distance, hiding chunks popping in and out
+    fog: bool,
+    fog_color: Color,
+    /// How far in from the horizontal render distance the fog starts, from 0 at the edge to 1 at the camera
+    fog_density: f32,
+    window: WindowSettings,
+}
+
+impl GameSettings {
+    /// Keep both render distances between 0 and `max_render_distance`<br>
+    /// A negative distance would load no chunks at all, which looks like the world froze
+    fn clamp_render_distances(&mut self) {
+        self.horizontal_render_distance = self
+            .horizontal_render_distance
+            .clamp(0, self.max_render_distance);
+        self.vertical_render_distance = self
+            .vertical_render_distance
+            .clamp(0, self.max_render_distance);
+    }
+
+    /// Rough number of chunks within render distance, the loaded area being a cylinder
+    fn chunks_in_render_distance(&self) -> usize {
+        let radius = self.horizontal_render_distance as f32 + 0.5;
+        let height = 2 * self.vertical_render_distance + 1;
+        (std::f32::consts::PI * radius * radius) as usize * height as usize
+    }
 }
 
 impl Default for GameSettings {
@@ -129,13 +362,142 @@ impl Default for GameSettings {
         Self {
             horizontal_render_distance: 3,
             vertical_render_distance: 3,
+            max_render_distance: 32,
+            max_loaded_chunks: 2048,
+            max_chunk_tasks_per_frame: 32,
+            max_meshes_applied_per_frame: 16,
+            lod_distance: 8,
+            max_saves_in_flight: 16,
+            column_fill_depth: 64,
+            brush_radius: 2,
+            reach: 5.,
+            ambient_occlusion: true,
+            mipmaps: true,
+            mesh_strategy: MeshStrategy::default(),
+            fov_degrees: 90.,
+            zoom_fov_degrees: 30.,
+            fog: true,
+            // The sky is the clear color, so terrain fades into it
+            fog_color: ClearColor::default().0,
+            fog_density: 0.5,
+            window: WindowSettings::default(),
+        }
+    }
+}
+
+struct WindowSettings {
+    title: String,
+    resolution: UVec2,
+    fullscreen: bool,
+    /// Use borderless fullscreen instead of exclusive fullscreen
+    borderless: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            title: "Voxel Game".to_owned(),
+            resolution: UVec2::new(1280, 720),
+            fullscreen: false,
+            borderless: true,
+        }
+    }
+}
+
+impl WindowSettings {
+    fn window_mode(&self) -> WindowMode {
+        match (self.fullscreen, self.borderless) {
+            (false, _) => WindowMode::Windowed,
+            (true, true) => WindowMode::BorderlessFullscreen(MonitorSelection::Current),
+            (true, false) => {
+                WindowMode::Fullscreen(MonitorSelection::Current, VideoModeSelection::Current)
+            }
         }
     }
+
+    fn to_window(&self) -> Window {
+        Window {
+            title: self.title.clone(),
+            resolution: WindowResolution::new(self.resolution.x as f32, self.resolution.y as f32),
+            mode: self.window_mode(),
+            ..default()
+        }
+    }
+
+    /// Flip between windowed and the configured fullscreen mode, returning the new mode
+    fn toggle_fullscreen(&mut self) -> WindowMode {
+        self.fullscreen = !self.fullscreen;
+        self.window_mode()
+    }
+}
+
+/// Block placed by block interactions
+#[derive(Resource)]
+struct SelectedBlock(Identifier);
+
+impl Default for SelectedBlock {
+    fn default() -> Self {
+        Self(Identifier::new(DEFAULT_NAMESPACE, "dirt"))
+    }
+}
+
+/// Keys selecting the registered block with the same index
+const BLOCK_SELECTION_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// Seconds the remove key has to be held to break a block, per point of hardness
+const BREAK_TIME_PER_HARDNESS: f32 = 1.;
+/// How much the block outline grows by the time the block it surrounds breaks, as a fraction of its size
+const BREAK_OUTLINE_GROWTH: f32 = 0.25;
+
+/// Block the camera is looking at within reach, cast once per frame by `update_current_target`
+#[derive(Default, Resource)]
+struct CurrentTarget(Option<RaycastHit>);
+
+/// Crosshair colour while a block is targeted, and while nothing is
+const CROSSHAIR_TARGET_COLOR: Color = Color::srgb(0.6, 1., 0.6);
+const CROSSHAIR_IDLE_COLOR: Color = Color::srgb(0.6, 0.6, 0.6);
+
+#[derive(Component)]
+struct Crosshair;
+
+/// Block being broken by holding the remove key and how long it has been held on it
+#[derive(Default, Resource)]
+struct BlockBreaking {
+    target: Option<IVec3>,
+    elapsed: f32,
+}
+
+#[derive(Clone, Copy)]
+enum BlockInteraction {
+    Place,
+    Remove,
+    /// Place a column of blocks downwards from the targeted position until reaching solid ground
+    PlaceColumn,
+    /// Select the targeted block
+    Pick,
+    /// Fill a cube around the targeted block, see [`GameSettings::brush_radius`]
+    BrushPlace,
+    /// Clear a cube around the targeted block
+    BrushRemove,
 }
 
 #[derive(Component)]
 struct DebugText;
 
+/// Marks UI left out of screenshots taken while holding one of [`CLEAN_SCREENSHOT_MODIFIERS`]
+#[derive(Component)]
+struct Hud;
+
 #[derive(Component)]
 struct DebugBlockOutline;
 
@@ -145,46 +507,397 @@ struct DebugBlockNormalOutline;
 #[derive(Default, Resource)]
 struct PersistentDebugInformation {
     ray_mesh_entities: Vec<Entity>,
-    constant_ray_mesh_entities: Vec<Entity>,
+    ray_overlay: RayOverlayPool,
+    ray_overlay_assets: Option<RayOverlayAssets>,
     show_constant_entities: bool,
+    /// Outline entity of every loaded chunk and whether the chunk had a mesh entity when it was spawned
+    chunk_border_entities: HashMap<IVec3, (Entity, bool)>,
+    chunk_border_assets: Option<ChunkBorderAssets>,
+    show_chunk_borders: bool,
+    break_particle_assets: Option<BreakParticleAssets>,
+    /// Shown in the debug text so the player knows when changes are not being saved or were lost
+    last_storage_error: Option<String>,
+}
+
+/// Only every `RAY_OVERLAY_SAMPLE_RATE`th ray step is drawn by the constant ray overlay
+const RAY_OVERLAY_SAMPLE_RATE: usize = 2;
+/// Maximum number of ray steps drawn by the constant ray overlay each frame
+const RAY_OVERLAY_MAX_SAMPLES: usize = 64;
+
+type DebugCube = (
+    Mesh3d,
+    MeshMaterial3d<StandardMaterial>,
+    Transform,
+    NoWireframe,
+);
+
+/// Mesh and material handles shared by every ray step cube
+#[derive(Clone)]
+struct RayOverlayAssets {
+    position_mesh: Handle<Mesh>,
+    position_material: Handle<StandardMaterial>,
+    normal_mesh: Handle<Mesh>,
+    normal_material: Handle<StandardMaterial>,
+}
+
+impl RayOverlayAssets {
+    fn new(meshes: &mut Assets<Mesh>, materials: &mut Assets<StandardMaterial>) -> Self {
+        Self {
+            position_mesh: meshes.add(Cuboid::from_length(0.1)),
+            position_material: materials
+                .add(StandardMaterial::from_color(Color::srgba(1., 0., 1., 0.75))),
+            normal_mesh: meshes.add(Cuboid::from_length(0.05)),
+            normal_material: materials
+                .add(StandardMaterial::from_color(Color::srgba(0., 1., 0., 0.75))),
+        }
+    }
+
+    fn position_cube(&self, position: Vec3) -> DebugCube {
+        (
+            Mesh3d(self.position_mesh.clone()),
+            MeshMaterial3d(self.position_material.clone()),
+            Transform::from_translation(position),
+            NoWireframe,
+        )
+    }
+
+    fn normal_cube(&self, position: Vec3) -> DebugCube {
+        (
+            Mesh3d(self.normal_mesh.clone()),
+            MeshMaterial3d(self.normal_material.clone()),
+            Transform::from_translation(position),
+            NoWireframe,
+        )
+    }
+}
+
+/// Chunk outlines of a meshed chunk
+const CHUNK_BORDER_COLOR: Color = Color::srgb(0., 1., 0.);
+/// Chunk outlines of a chunk without a mesh entity, either still meshing or empty
+const CHUNK_BORDER_UNMESHED_COLOR: Color = Color::srgb(1., 0., 0.);
+
+/// Mesh and material handles shared by every chunk outline
+#[derive(Clone)]
+struct ChunkBorderAssets {
+    mesh: Handle<Mesh>,
+    /// Fully transparent so only the wireframe is visible
+    material: Handle<StandardMaterial>,
+}
+
+impl ChunkBorderAssets {
+    fn new(meshes: &mut Assets<Mesh>, materials: &mut Assets<StandardMaterial>) -> Self {
+        Self {
+            mesh: meshes.add(Cuboid::from_length(chunk::SIZE_F32)),
+            material: materials.add(StandardMaterial {
+                base_color: Color::NONE,
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..default()
+            }),
+        }
+    }
+
+    fn outline(
+        &self,
+        position: IVec3,
+        meshed: bool,
+    ) -> (
+        Mesh3d,
+        MeshMaterial3d<StandardMaterial>,
+        Transform,
+        Wireframe,
+        WireframeColor,
+    ) {
+        let color = if meshed {
+            CHUNK_BORDER_COLOR
+        } else {
+            CHUNK_BORDER_UNMESHED_COLOR
+        };
+        (
+            Mesh3d(self.mesh.clone()),
+            MeshMaterial3d(self.material.clone()),
+            // Cuboids are centered on their translation
+            Transform::from_translation(
+                position.as_vec3() * chunk::SIZE_F32 + chunk::SIZE_F32 / 2.,
+            ),
+            Wireframe,
+            WireframeColor { color },
+        )
+    }
+}
+
+/// Particles spawned when a block is removed, one flying out towards each corner of the block
+const BREAK_PARTICLE_CORNERS: [Vec3; 8] = [
+    Vec3::new(-1., -1., -1.),
+    Vec3::new(1., -1., -1.),
+    Vec3::new(-1., 1., -1.),
+    Vec3::new(1., 1., -1.),
+    Vec3::new(-1., -1., 1.),
+    Vec3::new(1., -1., 1.),
+    Vec3::new(-1., 1., 1.),
+    Vec3::new(1., 1., 1.),
+];
+const BREAK_PARTICLE_SIZE: f32 = 0.15;
+/// Seconds a break particle lives for, it shrinks away over that time
+const BREAK_PARTICLE_LIFETIME: f32 = 0.6;
+/// Blocks per second the particles leave the block at, upwards speed is added on top
+const BREAK_PARTICLE_SPEED: f32 = 2.;
+const BREAK_PARTICLE_GRAVITY: f32 = 9.81;
+/// Removed blocks given particles each frame, so removing many blocks at once spawns a bounded number of entities
+const MAX_BREAK_PARTICLE_BURSTS: usize = 4;
+
+/// A piece of a removed block, despawned once `lifetime` finishes
+#[derive(Component)]
+struct BreakParticle {
+    velocity: Vec3,
+    lifetime: Timer,
+}
+
+/// Material and per block meshes shared by every break particle
+#[derive(Clone)]
+struct BreakParticleAssets {
+    /// Uses the block atlas, the meshes' UVs pick out part of a block's texture
+    material: Handle<StandardMaterial>,
+    /// Atlas location the meshes were made for and a mesh for each quarter of the block's texture<br>
+    /// Remade when the atlas is rebuilt and the location changes
+    block_meshes: HashMap<Identifier, (Rect, [Handle<Mesh>; 4])>,
+}
+
+impl BreakParticleAssets {
+    fn new(atlas_texture: Handle<Image>, materials: &mut Assets<StandardMaterial>) -> Self {
+        Self {
+            material: materials.add(StandardMaterial {
+                base_color_texture: Some(atlas_texture),
+                base_color: Color::WHITE,
+                ..default()
+            }),
+            block_meshes: HashMap::default(),
+        }
+    }
+
+    fn meshes(
+        &mut self,
+        location: Rect,
+        block: &Identifier,
+        meshes: &mut Assets<Mesh>,
+    ) -> &[Handle<Mesh>; 4] {
+        let (cached_location, cached_meshes) = self
+            .block_meshes
+            .entry(block.clone())
+            .or_insert_with(|| (Rect::default(), Default::default()));
+        if *cached_location != location {
+            let half_size = location.size() / 2.;
+            *cached_location = location;
+            *cached_meshes = [Vec2::ZERO, Vec2::X, Vec2::Y, Vec2::ONE].map(|quarter| {
+                let min = location.min + quarter * half_size;
+                meshes.add(break_particle_mesh(Rect::from_corners(
+                    min,
+                    min + half_size,
+                )))
+            });
+        }
+        cached_meshes
+    }
+}
+
+/// Cube showing `location` of the atlas on every face
+fn break_particle_mesh(location: Rect) -> Mesh {
+    let mut mesh = Mesh::from(Cuboid::from_length(BREAK_PARTICLE_SIZE));
+    if let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0) {
+        for uv in uvs.iter_mut() {
+            *uv = (location.min + Vec2::from(*uv) * location.size()).to_array();
+        }
+    }
+    mesh
+}
+
+/// Pool of (position, normal) cube entity pairs reused by the constant ray overlay between frames
+#[derive(Default)]
+struct RayOverlayPool {
+    entities: Vec<(Entity, Entity)>,
+    used: usize,
+    step: usize,
+}
+
+impl RayOverlayPool {
+    /// Return every pooled entity to the unused set, to be called once at the start of each frame
+    fn reset(&mut self) {
+        self.used = 0;
+        self.step = 0;
+    }
+
+    /// Advance one ray step, returning the pool slot to draw it with if it should be drawn
+    fn next_slot(&mut self) -> Option<usize> {
+        let step = self.step;
+        self.step += 1;
+        if step % RAY_OVERLAY_SAMPLE_RATE != 0 || self.used >= RAY_OVERLAY_MAX_SAMPLES {
+            return None;
+        }
+        self.used += 1;
+        Some(self.used - 1)
+    }
+
+    /// Move the entities of `slot` to the given cubes, spawning them if the slot is new
+    fn place(
+        &mut self,
+        commands: &mut Commands,
+        slot: usize,
+        position_cube: DebugCube,
+        normal_cube: DebugCube,
+    ) {
+        match self.entities.get(slot) {
+            Some(&(position, normal)) => {
+                commands
+                    .entity(position)
+                    .insert((position_cube.2, Visibility::Visible));
+                commands
+                    .entity(normal)
+                    .insert((normal_cube.2, Visibility::Visible));
+            }
+            None => self.entities.push((
+                commands.spawn(position_cube).id(),
+                commands.spawn(normal_cube).id(),
+            )),
+        }
+    }
+
+    /// Hide all entities not placed since the last reset
+    fn hide_unused(&self, commands: &mut Commands) {
+        for &(position, normal) in self.entities.iter().skip(self.used) {
+            commands.entity(position).insert(Visibility::Hidden);
+            commands.entity(normal).insert(Visibility::Hidden);
+        }
+    }
 }
 
 fn main() {
+    if cfg!(feature = "mesh-benchmark") {
+        benchmark::run();
+        return;
+    }
+
+    let config = Config::load_or_default();
+    let mut settings = GameSettings::default();
+    config.apply(&mut settings);
     App::new()
-        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest())) // TODO; replace with only those needed
+        .add_plugins(
+            DefaultPlugins // TODO; replace with only those needed
+                .set(ImagePlugin::default_nearest())
+                .set(WindowPlugin {
+                    primary_window: Some(settings.window.to_window()),
+                    ..default()
+                })
+                // Needed for drawing wireframes
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        features: WgpuFeatures::POLYGON_MODE_LINE,
+                        ..default()
+                    }
+                    .into(),
+                    ..default()
+                }),
+        )
+        .add_plugins(WireframePlugin::default())
+        .add_plugins(FrameTimeDiagnosticsPlugin::default())
         .add_plugins(camera_control::CameraMovementPlugin)
+        .add_plugins(day_cycle::DayCyclePlugin)
         .add_plugins(level::LevelPlugin)
-        .init_resource::<GameSettings>()
+        .add_plugins(menu::MainMenuPlugin)
+        .add_plugins(sound::BlockSoundPlugin)
+        .add_plugins(teleport::TeleportPlugin)
+        .insert_resource(settings)
+        .insert_resource(InputBindings(config.bindings.clone()))
+        .init_resource::<GamepadBindings>()
+        .insert_resource(ConfigFile::new(config))
         .init_resource::<PersistentDebugInformation>()
+        .init_resource::<BlockBreaking>()
+        .init_resource::<CurrentTarget>()
+        .init_resource::<SelectedBlock>()
         .init_resource::<BlockAtlasManager>()
         .init_state::<GameState>()
         .add_loading_state(
             LoadingState::new(GameState::AssetLoading)
                 .continue_to_state(GameState::CreateAtlases)
-                .load_collection::<BlockAssets>(),
+                .load_collection::<BlockAssets>()
+                .load_collection::<sound::SoundAssets>(),
         )
-        .add_systems(OnEnter(GameState::CreateAtlases), setup_atlases)
-        .add_systems(OnEnter(GameState::InGame), setup_world)
+        .add_systems(OnEnter(GameState::CreateAtlases), load_block_textures)
         .add_systems(
             Update,
-            (update_debug_text, handle_debug_input).run_if(in_state(GameState::InGame)),
+            setup_atlases.run_if(resource_exists::<PendingBlockTextures>),
+        )
+        // Only on the first entry, returning from the pause menu resumes the existing world
+        .add_systems(
+            OnTransition {
+                exited: GameState::MainMenu,
+                entered: GameState::InGame,
+            },
+            setup_world.after(level::setup_level),
+        )
+        .add_systems(OnEnter(GameState::Paused), open_pause_menu)
+        .add_systems(OnExit(GameState::Paused), close_pause_menu)
+        .add_systems(Update, (toggle_fullscreen, config::save_config))
+        .add_systems(Update, toggle_pause.run_if(in_game_or_paused))
+        .add_systems(
+            Update,
+            (
+                select_block,
+                update_debug_text,
+                update_current_target,
+                handle_debug_input.after(update_current_target),
+                update_crosshair.after(update_current_target),
+                break_targeted_block.after(update_current_target),
+                spawn_break_particles,
+                update_break_particles,
+                animate_block_textures,
+                toggle_mipmaps,
+                toggle_mesh_strategy,
+                adjust_fov,
+                update_fog
+                    .after(handle_debug_input)
+                    .run_if(resource_changed::<GameSettings>),
+                toggle_wireframe,
+                update_chunk_borders,
+                take_screenshot,
+                export_camera_chunk,
+                reset_camera_chunk,
+                remesh_all_chunks,
+                reload_block_textures,
+            )
+                .run_if(in_state(GameState::InGame)),
         )
         .run();
 }
 
+fn animate_block_textures(
+    time: Res<Time>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    mut textures: ResMut<Assets<Image>>,
+    mut current_frames: Local<HashMap<Identifier, u32>>,
+) {
+    block_atlas_manager.0.update_animations(
+        time.elapsed_secs(),
+        &mut current_frames,
+        &mut textures,
+    );
+}
+
 fn setup_world(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    config_file: Res<ConfigFile>,
+    settings: Res<GameSettings>,
+    levels: Res<Levels>,
     window_query: Single<&mut Window, With<PrimaryWindow>>,
 ) {
+    let level = levels.active();
     // Setup window
     let mut window = window_query.into_inner();
-    window.cursor_options.grab_mode = bevy::window::CursorGrabMode::Confined;
-    window.cursor_options.visible = false;
+    grab_cursor(&mut window, true);
     window.focused = true;
 
-    // Setup camera
+    // Setup camera, the ambient light follows the time of day
     commands.spawn((
         AmbientLight {
             brightness: 300.,
@@ -192,18 +905,25 @@ fn setup_world(
         },
         Camera3d::default(),
         MovableCamera {
-            speed: 15.,
-            sensitivity: 0.002,
+            speed: config_file.saved().camera_speed,
+            sensitivity: config_file.saved().camera_sensitivity,
+            acceleration: config_file.saved().camera_acceleration,
+            damping: config_file.saved().camera_damping,
+            ..Default::default()
         },
+        PlayerPhysics::default(),
+        Transform::from_translation(level.spawn_point()),
         Projection::from(PerspectiveProjection {
-            fov: 90_f32.to_radians(),
+            fov: settings.fov_degrees.to_radians(),
             ..Default::default()
         }),
     ));
 
     // Crosshair
     commands.spawn((
-        BackgroundColor(Color::WHITE),
+        Crosshair,
+        Hud,
+        BackgroundColor(CROSSHAIR_IDLE_COLOR),
         Node {
             position_type: PositionType::Absolute,
             justify_self: bevy::ui::JustifySelf::Center,
@@ -220,6 +940,7 @@ fn setup_world(
         Mesh3d(meshes.add(Cuboid::from_length(1.02))),
         MeshMaterial3d(materials.add(StandardMaterial::from_color(Color::WHITE.with_alpha(0.5)))),
         Transform::from_translation(Vec3::ZERO),
+        NoWireframe,
     ));
 
     commands.spawn((
@@ -227,11 +948,13 @@ fn setup_world(
         Mesh3d(meshes.add(Cuboid::from_length(1.01))),
         MeshMaterial3d(materials.add(StandardMaterial::from_color(Color::srgba(1., 1., 0., 0.25)))),
         Transform::from_translation(Vec3::ZERO),
+        NoWireframe,
     ));
 
     // Debug info
     commands.spawn((
         DebugText,
+        Hud,
         Text::default(),
         Node {
             position_type: PositionType::Absolute,
@@ -242,7 +965,8 @@ fn setup_world(
     ));
 
     commands.spawn((
-        Text::new("[Mouse Wheel]: Change camera movement speed\n[Arrow Keys]: Change render distance\n[E]: Place block\n[Q]: Remove block\n[R]: Toggle ray overlay"),
+        Hud,
+        Text::new("[Mouse Wheel]: Change camera movement speed\n[Left Ctrl]: Sprint\n[Arrow Keys]: Change render distance\n[-/=]: Change field of view\n[C]: Hold to zoom\n[E]: Place block\n[Q]: Hold to break block\n[1-9]: Select block\n[F/Middle Mouse]: Pick targeted block\n[V]: Place column down to the ground\n[X]: Hold to make E/Q fill/clear a cube, Mouse Wheel resizes it, Left Alt overwrites blocks\n[R]: Toggle ray overlay\n[B]: Toggle chunk borders\n[Z]: Toggle wireframe\n[F2]: Screenshot, hold Alt to hide the UI\n[F6]: Export the current chunk as OBJ\n[F7]: Toggle greedy meshing\n[F9]: Regenerate the current chunk, discarding its edits\n[F10]: Remesh all loaded chunks\n[P]: Teleport to coordinates\n[F11]: Toggle fullscreen\n[Esc]: Pause\n[Gamepad]: Sticks to move and look, triggers to place and break, bumpers to select block"),
         TextLayout::new_with_justify(bevy::text::JustifyText::Right),
         Node {
             position_type: PositionType::Absolute,
@@ -253,58 +977,819 @@ fn setup_world(
     ));
 }
 
+/// Block textures found on disk, held until they finish loading and are added to the atlas
+#[derive(Resource)]
+struct PendingBlockTextures(Vec<(BlockTextureFile, Handle<Image>)>);
+
+/// Seconds each frame of an animated block texture is shown for
+const BLOCK_ANIMATION_FRAME_DURATION: f32 = 0.25;
+
+/// Runs when atlases are first created and again from the reload key, registering any new texture files
+fn load_block_textures(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let assets_directory = FileAssetReader::get_base_path().join("assets");
+    let files = find_block_textures(&assets_directory).unwrap_or_else(|error| {
+        error!(
+            "Failed to read block textures from {}: {error}",
+            assets_directory.display()
+        );
+        Vec::new()
+    });
+    commands.insert_resource(PendingBlockTextures(
+        files
+            .into_iter()
+            .map(|file| {
+                let handle = asset_server.load(file.asset_path.clone());
+                (file, handle)
+            })
+            .collect(),
+    ));
+}
+
 fn setup_atlases(
     mut commands: Commands,
     block_assets: Res<BlockAssets>,
-    textures: ResMut<Assets<Image>>,
+    pending_textures: Res<PendingBlockTextures>,
+    asset_server: Res<AssetServer>,
+    settings: Res<GameSettings>,
+    state: Res<State<GameState>>,
+    levels: Option<ResMut<Levels>>,
+    mut textures: ResMut<Assets<Image>>,
     mut block_atlas_manager: ResMut<BlockAtlasManager>,
 ) {
-    let block_manager = Arc::make_mut(&mut block_atlas_manager.0);
+    if pending_textures
+        .0
+        .iter()
+        .any(|(_, handle)| matches!(asset_server.load_state(handle), LoadState::Loading))
+    {
+        return;
+    }
+    // Edited as a copy and swapped in as a new Arc, rather than through `Arc::make_mut` which would leave
+    // meshing tasks with a Weak to a manager that no longer exists
+    let mut block_manager = (*block_atlas_manager.0).clone();
 
     block_manager.set_error_texture(block_assets.error.clone());
-    block_manager.add_data(
-        Identifier(DEFAULT_NAMESPACE.to_owned(), "stone".to_owned()),
-        block_assets.stone.clone(),
+    // Blocks whose files were deleted since the last load
+    let removed = block_manager
+        .identifiers()
+        .filter(|identifier| {
+            !pending_textures
+                .0
+                .iter()
+                .any(|(file, _)| file.identifier == **identifier)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    for identifier in removed.iter() {
+        block_manager.remove_data(identifier);
+    }
+    // Blocks are registered before any face overrides so the overrides have a block to apply to
+    for (file, handle) in pending_textures
+        .0
+        .iter()
+        .filter(|(file, _)| file.faces.is_empty())
+    {
+        let Some(texture) = textures.get(handle) else {
+            warn!("Block texture {} failed to load", file.asset_path);
+            continue;
+        };
+        // Textures taller than they are wide are vertical strips of square animation frames
+        let (width, height) = (texture.width(), texture.height());
+        if height > width && height % width == 0 {
+            block_manager.add_animated_data(
+                file.identifier.clone(),
+                handle.clone(),
+                TextureAnimation {
+                    frames: height / width,
+                    frame_duration: BLOCK_ANIMATION_FRAME_DURATION,
+                },
+            );
+        } else {
+            block_manager.add_data(file.identifier.clone(), handle.clone());
+        }
+    }
+    for (file, handle) in pending_textures
+        .0
+        .iter()
+        .filter(|(file, _)| !file.faces.is_empty())
+    {
+        if textures.get(handle).is_none() {
+            warn!("Block texture {} failed to load", file.asset_path);
+            continue;
+        }
+        for &face in file.faces {
+            block_manager.add_face_data(&file.identifier, face, handle.clone());
+        }
+    }
+    // Texture files don't describe transparency, emission or block properties yet
+    for path in ["glass", "water"] {
+        block_manager.set_render_layer(
+            &Identifier::new(DEFAULT_NAMESPACE, path),
+            BlockRenderLayer::Transparent,
+        );
+    }
+    block_manager.set_emission(&Identifier::new(DEFAULT_NAMESPACE, "glowstone"), Some(1.));
+    for (path, sound_group) in [
+        ("stone", "stone"),
+        ("dirt", "dirt"),
+        ("grass", "dirt"),
+        ("glass", "glass"),
+        ("glowstone", "glass"),
+    ] {
+        block_manager.set_sound_group(
+            &Identifier::new(DEFAULT_NAMESPACE, path),
+            Some(Identifier::new(DEFAULT_NAMESPACE, sound_group)),
+        );
+    }
+    for (path, properties) in [
+        (
+            "dirt",
+            BlockProperties {
+                hardness: 0.5,
+                ..Default::default()
+            },
+        ),
+        (
+            "grass",
+            BlockProperties {
+                hardness: 0.6,
+                ..Default::default()
+            },
+        ),
+        (
+            "stone",
+            BlockProperties {
+                hardness: 1.5,
+                ..Default::default()
+            },
+        ),
+        (
+            "glowstone",
+            BlockProperties {
+                hardness: 0.3,
+                ..Default::default()
+            },
+        ),
+        (
+            "leaves",
+            BlockProperties {
+                hardness: 0.2,
+                ..Default::default()
+            },
+        ),
+        (
+            "glass",
+            BlockProperties {
+                opaque: false,
+                hardness: 0.3,
+                ..Default::default()
+            },
+        ),
+        (
+            "water",
+            BlockProperties {
+                solid: false,
+                opaque: false,
+                replaceable: true,
+                fluid: true,
+                hardness: 0.,
+            },
+        ),
+    ] {
+        block_manager.set_properties(&Identifier::new(DEFAULT_NAMESPACE, path), properties);
+    }
+
+    let changed = block_manager.rebuild_atlas(&mut textures);
+    if let Some(atlas) = block_manager
+        .atlas_texture()
+        .and_then(|atlas_texture| textures.get_mut(&atlas_texture))
+    {
+        atlas::set_mipmaps(atlas, settings.mipmaps);
+    }
+    block_atlas_manager.0 = Arc::new(block_manager);
+    // There is no level yet when the atlas is first created
+    // Inactive levels only rebuild their queued meshes once they are active again
+    if let Some(mut levels) = levels {
+        for level in levels.iter_mut() {
+            level.rebuild_meshes_containing(&changed);
+            // Tasks already running mesh with the old manager, or give up and queue themselves again
+            level.requeue_pending_meshes();
+        }
+    }
+
+    commands.remove_resource::<PendingBlockTextures>();
+    if *state.get() == GameState::CreateAtlases {
+        commands.set_state(crate::GameState::MainMenu);
+    }
+}
+
+/// Confine and hide the cursor for mouse look, or release it for menus
+fn grab_cursor(window: &mut Window, grab: bool) {
+    window.cursor_options.grab_mode = if grab {
+        bevy::window::CursorGrabMode::Confined
+    } else {
+        bevy::window::CursorGrabMode::None
+    };
+    window.cursor_options.visible = !grab;
+}
+
+fn toggle_pause(mut commands: Commands, input: ActionInput, state: Res<State<GameState>>) {
+    if !input.just_pressed(Action::Pause) {
+        return;
+    }
+    commands.set_state(match state.get() {
+        GameState::Paused => GameState::InGame,
+        _ => GameState::Paused,
+    });
+}
+
+fn open_pause_menu(mut commands: Commands, window_query: Single<&mut Window, With<PrimaryWindow>>) {
+    grab_cursor(&mut window_query.into_inner(), false);
+    commands.spawn((
+        PauseMenu,
+        Text::new("Paused"),
+        TextFont::from_font_size(48.),
+        Node {
+            position_type: PositionType::Absolute,
+            justify_self: bevy::ui::JustifySelf::Center,
+            align_self: bevy::ui::AlignSelf::Center,
+            ..Default::default()
+        },
+    ));
+}
+
+fn close_pause_menu(
+    mut commands: Commands,
+    menu_query: Query<Entity, With<PauseMenu>>,
+    window_query: Single<&mut Window, With<PrimaryWindow>>,
+) {
+    grab_cursor(&mut window_query.into_inner(), true);
+    for entity in menu_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn reload_block_textures(
+    commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    asset_server: Res<AssetServer>,
+) {
+    if bindings.just_pressed(&keyboard_input, Action::ReloadBlockTextures) {
+        load_block_textures(commands, asset_server);
+    }
+}
+
+fn toggle_fullscreen(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut settings: ResMut<GameSettings>,
+    window_query: Single<&mut Window, With<PrimaryWindow>>,
+) {
+    if !bindings.just_pressed(&keyboard_input, Action::ToggleFullscreen) {
+        return;
+    }
+    window_query.into_inner().mode = settings.window.toggle_fullscreen();
+}
+
+fn toggle_mipmaps(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut settings: ResMut<GameSettings>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    mut textures: ResMut<Assets<Image>>,
+) {
+    if !bindings.just_pressed(&keyboard_input, Action::ToggleMipmaps) {
+        return;
+    }
+    settings.mipmaps = !settings.mipmaps;
+    if let Some(atlas) = block_atlas_manager
+        .0
+        .atlas_texture()
+        .and_then(|atlas_texture| textures.get_mut(&atlas_texture))
+    {
+        atlas::set_mipmaps(atlas, settings.mipmaps);
+    }
+}
+
+/// Switch chunk meshes between one quad per face and greedy merged quads, remeshing every level
+fn toggle_mesh_strategy(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut settings: ResMut<GameSettings>,
+    mut levels: ResMut<Levels>,
+) {
+    if !bindings.just_pressed(&keyboard_input, Action::ToggleMeshStrategy) {
+        return;
+    }
+    settings.mesh_strategy = match settings.mesh_strategy {
+        MeshStrategy::PerFace => MeshStrategy::Greedy,
+        MeshStrategy::Greedy => MeshStrategy::PerFace,
+    };
+    for level in levels.iter_mut() {
+        level.set_mesh_strategy(settings.mesh_strategy);
+    }
+    info!("Meshing chunks with {:?}", settings.mesh_strategy);
+}
+
+/// Keep the fog of the camera ending at the horizontal render distance, or remove it while fog is disabled
+fn update_fog(
+    mut commands: Commands,
+    settings: Res<GameSettings>,
+    camera_query: Single<Entity, With<MovableCamera>>,
+) {
+    let mut camera = commands.entity(*camera_query);
+    if !settings.fog {
+        camera.remove::<DistanceFog>();
+        return;
+    }
+    let end = settings.horizontal_render_distance as f32 * chunk::SIZE_F32;
+    camera.insert(DistanceFog {
+        color: settings.fog_color,
+        falloff: FogFalloff::Linear {
+            start: end * (1. - settings.fog_density.clamp(0., 1.)),
+            end,
+        },
+        ..Default::default()
+    });
+}
+
+/// Widen or narrow the field of view, and zoom towards the zoom field of view while the zoom key is held<br>
+/// Only the camera's projection is changed so it keeps its position and orientation
+fn adjust_fov(
+    time: Res<Time>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut settings: ResMut<GameSettings>,
+    projection_query: Single<&mut Projection, With<MovableCamera>>,
+    // How far zoomed in the camera is, from 0 to 1
+    mut zoom: Local<f32>,
+) {
+    let mut fov_degrees = settings.fov_degrees;
+    if bindings.just_pressed(&keyboard_input, Action::IncreaseFov) {
+        fov_degrees += FOV_STEP_DEGREES;
+    }
+    if bindings.just_pressed(&keyboard_input, Action::DecreaseFov) {
+        fov_degrees -= FOV_STEP_DEGREES;
+    }
+    let fov_degrees = fov_degrees.clamp(MIN_FOV_DEGREES, MAX_FOV_DEGREES);
+    // Only written when it changes, writing every frame would mark the settings as changed every frame
+    if settings.fov_degrees != fov_degrees {
+        settings.fov_degrees = fov_degrees;
+    }
+
+    // Moving the zoom rather than the field of view means releasing the key mid zoom turns around from where it was
+    let zoom_step = time.delta_secs() / ZOOM_DURATION;
+    *zoom = if bindings.pressed(&keyboard_input, Action::Zoom) {
+        (*zoom + zoom_step).min(1.)
+    } else {
+        (*zoom - zoom_step).max(0.)
+    };
+    // Eased so the zoom starts and stops gently
+    let eased_zoom = *zoom * *zoom * (3. - 2. * *zoom);
+    let fov = fov_degrees
+        .lerp(settings.zoom_fov_degrees, eased_zoom)
+        .to_radians();
+
+    let mut projection = projection_query.into_inner();
+    // Checked before borrowing mutably so the projection is only marked as changed when it is
+    if !matches!(&*projection, Projection::Perspective(perspective) if perspective.fov != fov) {
+        return;
+    }
+    if let Projection::Perspective(perspective) = &mut *projection {
+        perspective.fov = fov;
+    }
+}
+
+/// Draw the edges of every chunk mesh, debug meshes opt out with [`NoWireframe`]
+fn toggle_wireframe(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut wireframe_config: ResMut<WireframeConfig>,
+) {
+    if bindings.just_pressed(&keyboard_input, Action::ToggleWireframe) {
+        wireframe_config.global = !wireframe_config.global;
+    }
+}
+
+const EXPORT_DIRECTORY: &str = "exports";
+
+/// Write the mesh of the chunk the camera is in to `exports/chunk_<x>_<y>_<z>.obj`
+fn export_camera_chunk(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    levels: Res<Levels>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    camera_query: Single<&Transform, With<MovableCamera>>,
+) {
+    let level = levels.active();
+    if !bindings.just_pressed(&keyboard_input, Action::ExportChunk) {
+        return;
+    }
+    let position = ChunkGrid::to_chunk_coordinates(camera_query.translation);
+    let path = format!(
+        "{EXPORT_DIRECTORY}/chunk_{}_{}_{}.obj",
+        position.x, position.y, position.z
     );
-    block_manager.add_data(
-        Identifier(DEFAULT_NAMESPACE.to_owned(), "dirt".to_owned()),
-        block_assets.dirt.clone(),
+    match level.export_chunk_obj(position, &block_atlas_manager.0, Path::new(&path)) {
+        Ok(()) => info!("Exported chunk {position} to {path}"),
+        Err(error) => error!("Failed to export chunk {position} to {path}: {error}"),
+    }
+}
+
+/// Regenerate the chunk the camera is in from the seed, discarding its edits
+fn reset_camera_chunk(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut levels: ResMut<Levels>,
+    camera_query: Single<&Transform, With<MovableCamera>>,
+) {
+    if !bindings.just_pressed(&keyboard_input, Action::ResetChunk) {
+        return;
+    }
+    let position = ChunkGrid::to_chunk_coordinates(camera_query.translation);
+    match levels.active_mut().reset_chunk(&mut commands, position) {
+        Ok(()) => info!("Reset chunk {position}"),
+        Err(error) => error!("Failed to reset chunk {position}: {error}"),
+    }
+}
+
+/// Rebuild every loaded chunk's mesh, picking up atlas or meshing changes without reloading the level
+fn remesh_all_chunks(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut levels: ResMut<Levels>,
+) {
+    if !bindings.just_pressed(&keyboard_input, Action::RemeshAll) {
+        return;
+    }
+    levels.active_mut().rebuild_all_meshes();
+    info!("Remeshing all loaded chunks");
+}
+
+const SCREENSHOT_DIRECTORY: &str = "screenshots";
+/// Held while taking a screenshot to leave the [`Hud`] out of it
+const CLEAN_SCREENSHOT_MODIFIERS: [KeyCode; 2] = [KeyCode::AltLeft, KeyCode::AltRight];
+
+/// Save the next frame to `screenshots/<milliseconds since epoch>.png`
+fn take_screenshot(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut hud_query: Query<&mut Visibility, With<Hud>>,
+) {
+    if !bindings.just_pressed(&keyboard_input, Action::Screenshot) {
+        return;
+    }
+    if let Err(error) = fs::create_dir_all(SCREENSHOT_DIRECTORY) {
+        error!("Failed to create the {SCREENSHOT_DIRECTORY} directory: {error}");
+        return;
+    }
+    // Milliseconds so screenshots taken in quick succession don't overwrite each other
+    let milliseconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis());
+    let path = format!("{SCREENSHOT_DIRECTORY}/{milliseconds}.png");
+    info!("Saving screenshot to {path}");
+
+    let mut screenshot = commands.spawn(Screenshot::primary_window());
+    screenshot.observe(save_to_disk(path));
+    // Hidden for the frame being captured and shown again once it has been
+    if keyboard_input.any_pressed(CLEAN_SCREENSHOT_MODIFIERS) {
+        for mut visibility in hud_query.iter_mut() {
+            *visibility = Visibility::Hidden;
+        }
+        screenshot.observe(show_hud);
+    }
+}
+
+fn show_hud(
+    _trigger: Trigger<ScreenshotCaptured>,
+    mut hud_query: Query<&mut Visibility, With<Hud>>,
+) {
+    for mut visibility in hud_query.iter_mut() {
+        *visibility = Visibility::Inherited;
+    }
+}
+
+/// Keep an outline around every loaded chunk while chunk borders are shown
+fn update_chunk_borders(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<InputBindings>,
+    levels: Res<Levels>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut debug_info: ResMut<PersistentDebugInformation>,
+) {
+    let level = levels.active();
+    if bindings.just_pressed(&keyboard_input, Action::ToggleChunkBorders) {
+        debug_info.show_chunk_borders = !debug_info.show_chunk_borders;
+    }
+    if !debug_info.show_chunk_borders {
+        for (_, (entity, _)) in debug_info.chunk_border_entities.drain() {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+
+    let assets = debug_info
+        .chunk_border_assets
+        .get_or_insert_with(|| ChunkBorderAssets::new(&mut meshes, &mut materials))
+        .clone();
+    let chunk_grid = level.get_chunk_grid();
+    debug_info
+        .chunk_border_entities
+        .retain(|position, (entity, _)| {
+            let loaded = chunk_grid.0.contains_key(position);
+            if !loaded {
+                commands.entity(*entity).despawn();
+            }
+            loaded
+        });
+    for position in chunk_grid.0.keys() {
+        let meshed = level.has_chunk_entities(*position);
+        match debug_info.chunk_border_entities.get(position) {
+            Some((_, outlined_meshed)) if *outlined_meshed == meshed => continue,
+            Some((entity, _)) => commands.entity(*entity).despawn(),
+            None => {}
+        }
+        let entity = commands.spawn(assets.outline(*position, meshed)).id();
+        debug_info
+            .chunk_border_entities
+            .insert(*position, (entity, meshed));
+    }
+}
+
+/// Break the targeted block once the remove key has been held for as long as its hardness asks for,
+/// growing the block outline to show the progress<br>
+/// Releasing the key or looking at another block starts over, blocks with a negative hardness never break
+fn break_targeted_block(
+    time: Res<Time>,
+    input: ActionInput,
+    mut levels: ResMut<Levels>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    current_target: Res<CurrentTarget>,
+    mut breaking: ResMut<BlockBreaking>,
+    mut block_outline_query: Single<&mut Transform, With<DebugBlockOutline>>,
+) {
+    let level = levels.active_mut();
+    let target = current_target.0.map(|hit| hit.block_coordinates);
+    // The brush clears blocks at once instead, see `handle_debug_input`
+    let held = input.pressed(Action::RemoveBlock) && !input.pressed(Action::Brush);
+    if held && target == breaking.target {
+        breaking.elapsed += time.delta_secs();
+    } else {
+        breaking.elapsed = 0.;
+    }
+    breaking.target = target;
+
+    let hardness = target
+        .and_then(|target| level.get_block(target))
+        .map(|block| block_atlas_manager.0.properties(&block.identifier).hardness);
+    // A hardness of 0 divides into infinity or NaN, both of which `min` turns into instantly breaking
+    let progress = match hardness {
+        Some(hardness) if held && hardness >= 0. => {
+            (breaking.elapsed / (hardness * BREAK_TIME_PER_HARDNESS)).min(1.)
+        }
+        _ => 0.,
+    };
+    block_outline_query.scale = Vec3::splat(1. + progress * BREAK_OUTLINE_GROWTH);
+
+    let Some(target) = target.filter(|_| progress >= 1.) else {
+        return;
+    };
+    level.set_block(target, None);
+    // The block behind it starts from nothing
+    breaking.target = None;
+    block_outline_query.scale = Vec3::ONE;
+}
+
+/// Cast the look ray from the camera into [`CurrentTarget`], for every system that needs to know what is being looked at
+fn update_current_target(
+    levels: Res<Levels>,
+    settings: Res<GameSettings>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    mut current_target: ResMut<CurrentTarget>,
+    camera_query: Single<&Transform, With<MovableCamera>>,
+) {
+    let hit = BlockRay::from_origin_in_direction(
+        camera_query.translation,
+        camera_query.forward().normalize(),
+    )
+    .cast(
+        levels.active().get_chunk_grid(),
+        &block_atlas_manager.0,
+        settings.reach,
     );
+    if current_target.0 != hit {
+        current_target.0 = hit;
+    }
+}
 
-    block_manager.rebuild_atlas(textures.into_inner());
+/// Tint the crosshair while a block is targeted, so it shows whether placing or breaking will do anything
+fn update_crosshair(
+    current_target: Res<CurrentTarget>,
+    crosshair_query: Single<&mut BackgroundColor, With<Crosshair>>,
+) {
+    let color = if current_target.0.is_some() {
+        CROSSHAIR_TARGET_COLOR
+    } else {
+        CROSSHAIR_IDLE_COLOR
+    };
+    crosshair_query
+        .into_inner()
+        .set_if_neq(BackgroundColor(color));
+}
 
-    commands.set_state(crate::GameState::InGame);
+/// Burst a removed block into small cubes textured with parts of its texture
+fn spawn_break_particles(
+    mut commands: Commands,
+    mut removed_events: EventReader<BlockRemoved>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut debug_info: ResMut<PersistentDebugInformation>,
+) {
+    let Some(atlas_texture) = block_atlas_manager.0.atlas_texture() else {
+        return;
+    };
+    let assets = debug_info
+        .break_particle_assets
+        .get_or_insert_with(|| BreakParticleAssets::new(atlas_texture, &mut materials));
+    for event in removed_events.read().take(MAX_BREAK_PARTICLE_BURSTS) {
+        let location = block_atlas_manager.0.atlas_location_or_error(&event.block);
+        let block_meshes = assets.meshes(location, &event.block, &mut meshes).clone();
+        let center = event.world.as_vec3() + 0.5;
+        for (index, corner) in BREAK_PARTICLE_CORNERS.iter().enumerate() {
+            commands.spawn((
+                BreakParticle {
+                    velocity: (*corner + Vec3::Y) * BREAK_PARTICLE_SPEED / 2.,
+                    lifetime: Timer::from_seconds(BREAK_PARTICLE_LIFETIME, TimerMode::Once),
+                },
+                Mesh3d(block_meshes[index % block_meshes.len()].clone()),
+                MeshMaterial3d(assets.material.clone()),
+                Transform::from_translation(center + *corner * 0.25),
+                NoWireframe,
+            ));
+        }
+    }
+    // Removals past the limit are dropped rather than left for the next frame
+    removed_events.clear();
+}
+
+fn update_break_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particle_query: Query<(Entity, &mut BreakParticle, &mut Transform)>,
+) {
+    for (entity, mut particle, mut transform) in particle_query.iter_mut() {
+        particle.lifetime.tick(time.delta());
+        if particle.lifetime.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        particle.velocity.y -= BREAK_PARTICLE_GRAVITY * time.delta_secs();
+        transform.translation += particle.velocity * time.delta_secs();
+        transform.scale = Vec3::splat(particle.lifetime.fraction_remaining());
+    }
+}
+
+/// Select a block with the number keys, or step through every block with the next and previous block actions
+fn select_block(
+    input: ActionInput,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    mut selected_block: ResMut<SelectedBlock>,
+) {
+    let step = match (
+        input.just_pressed(Action::NextBlock),
+        input.just_pressed(Action::PreviousBlock),
+    ) {
+        (true, false) => Some(1),
+        (false, true) => Some(-1),
+        _ => None,
+    };
+    let identifiers = block_atlas_manager.0.identifiers().collect::<Vec<_>>();
+    if let Some(step) = step.filter(|_| !identifiers.is_empty()) {
+        // Stepping from a selected block that isn't registered selects the first or last block
+        let index = identifiers
+            .iter()
+            .position(|identifier| **identifier == selected_block.0)
+            .map_or(if step > 0 { -1 } else { 0 }, |index| index as isize);
+        let index = (index + step).rem_euclid(identifiers.len() as isize);
+        selected_block.0 = identifiers[index as usize].clone();
+    }
+
+    for (index, key) in BLOCK_SELECTION_KEYS.iter().enumerate() {
+        if !input.keyboard.just_pressed(*key) {
+            continue;
+        }
+        // Keys without a registered block do nothing rather than selecting an error textured block
+        if let Some(identifier) = block_atlas_manager.0.identifiers().nth(index) {
+            selected_block.0 = identifier.clone();
+        }
+    }
 }
 
 fn update_debug_text(
     settings: Res<GameSettings>,
-    camera_query: Single<(&MovableCamera, &Transform)>,
+    selected_block: Res<SelectedBlock>,
+    time_of_day: Res<TimeOfDay>,
+    levels: Res<Levels>,
+    diagnostics: Res<DiagnosticsStore>,
+    mut debug_info: ResMut<PersistentDebugInformation>,
+    mut save_failed_events: EventReader<LevelSaveFailed>,
+    mut data_lost_events: EventReader<ChunkDataLost>,
+    camera_query: Single<(&MovableCamera, &Transform, &MovementMode)>,
     text_query: Single<&mut Text, With<DebugText>>,
 ) {
+    let level = levels.active();
+    for event in save_failed_events.read() {
+        debug_info.last_storage_error = Some(match event.position {
+            Some(position) => format!("chunk {position}: {}", event.error),
+            None => event.error.to_string(),
+        });
+    }
+    for event in data_lost_events.read() {
+        debug_info.last_storage_error = Some(format!(
+            "chunk {} was unreadable and regenerated{}",
+            event.position,
+            event
+                .backup
+                .as_ref()
+                .map_or(String::new(), |backup| format!(", backup at {backup}"))
+        ));
+    }
+
     let camera_position = camera_query.1.translation;
-    text_query.into_inner().0 = format!(
-        "Raw   x/y/z: {}\nBlock x/y/z: {} ({})\nChunk x/y/z: {}\n\nCamera Speed: {}\nRender Distance: [h:{}, v:{}]",
+    let mut text = format!(
+        "Raw   x/y/z: {}\nBlock x/y/z: {} ({})\nChunk x/y/z: {}\n\nCamera Speed: {}\nMovement Mode: {}\nRender Distance: [h:{}, v:{}]\nSelected Block: {}\nBrush Radius: {}",
         camera_position,
         camera_position.floor().as_ivec3(),
         Chunk::to_block_coordinates(camera_position.floor().as_ivec3()),
         ChunkGrid::to_chunk_coordinates(camera_position),
         camera_query.0.speed,
+        camera_query.2,
         settings.horizontal_render_distance,
-        settings.vertical_render_distance
+        settings.vertical_render_distance,
+        selected_block.0,
+        settings.brush_radius
     );
+    let (hours, minutes) = time_of_day.clock();
+    text.push_str(&format!(
+        "\nTime of Day: {hours:02}:{minutes:02}{}",
+        if time_of_day.paused { " (paused)" } else { "" }
+    ));
+    let fps = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed());
+    let frame_time = diagnostics.get(&FrameTimeDiagnosticsPlugin::FRAME_TIME);
+    if let (Some(fps), Some(frame_time)) = (fps, frame_time) {
+        // The slowest frame of the recorded history shows stutters that the smoothed values average away
+        let slowest = frame_time.values().copied().reduce(f64::max);
+        text.push_str(&format!(
+            "\nFPS: {fps:.0} ({:.1} ms, slowest {:.1} ms)",
+            frame_time.smoothed().unwrap_or_default(),
+            slowest.unwrap_or_default()
+        ));
+    }
+    let stats = level.mesh_stats();
+    // Counts are unknown for the frame when a background task holds the lock
+    let count = |count: Option<usize>| count.map_or("?".to_owned(), |count| count.to_string());
+    text.push_str(&format!(
+        "\nLoaded Chunks: {}\nPending Generation: {}\nPending Meshes: {}\nChunk Vertices: {}",
+        stats.loaded_chunks,
+        count(stats.pending_generation),
+        count(stats.pending_meshes),
+        stats.vertices
+    ));
+    let camera_chunk = ChunkGrid::to_chunk_coordinates(camera_position);
+    if let Some((generation, mesh)) = level.chunk_pipeline_stage(camera_chunk) {
+        text.push_str(&format!(
+            "\nCamera Chunk Stage: {generation:?}, mesh {mesh:?}"
+        ));
+    }
+    if let Some(error) = &debug_info.last_storage_error {
+        text.push_str(&format!("\nLast Storage Error: {error}"));
+    }
+    text_query.into_inner().0 = text;
 }
 
 #[allow(clippy::too_many_arguments)]
 #[allow(clippy::type_complexity)]
 fn handle_debug_input(
     mut commands: Commands,
-    mut level: ResMut<Level>,
+    mut levels: ResMut<Levels>,
     mut settings: ResMut<GameSettings>,
     mut debug_info: ResMut<PersistentDebugInformation>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    mut selected_block: ResMut<SelectedBlock>,
+    current_target: Res<CurrentTarget>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut mouse_wheel_input: EventReader<MouseWheel>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    input: ActionInput,
+    mouse_input: Res<ButtonInput<MouseButton>>,
     mut camera_query: Single<(&mut MovableCamera, &Transform)>,
     mut block_outline_query: Single<
         &mut Transform,
@@ -323,40 +1808,89 @@ fn handle_debug_input(
         ),
     >,
 ) {
+    let level = levels.active_mut();
     // Change chunk render distance
-    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
+    let previous_render_distances = (
+        settings.horizontal_render_distance,
+        settings.vertical_render_distance,
+    );
+    if input.just_pressed(Action::IncreaseVerticalRenderDistance) {
         settings.vertical_render_distance += 1;
     }
-    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+    if input.just_pressed(Action::DecreaseVerticalRenderDistance) {
         settings.vertical_render_distance -= 1;
     }
-    if keyboard_input.just_pressed(KeyCode::ArrowRight) {
+    if input.just_pressed(Action::IncreaseHorizontalRenderDistance) {
         settings.horizontal_render_distance += 1;
     }
-    if keyboard_input.just_pressed(KeyCode::ArrowLeft) {
+    if input.just_pressed(Action::DecreaseHorizontalRenderDistance) {
         settings.horizontal_render_distance -= 1;
     }
+    let render_distances = (
+        settings.horizontal_render_distance,
+        settings.vertical_render_distance,
+    );
+    if render_distances != previous_render_distances {
+        settings.clamp_render_distances();
+    }
+    let chunks = settings.chunks_in_render_distance();
+    if render_distances != previous_render_distances && chunks > settings.max_loaded_chunks {
+        // Chunk contents are stored uncompressed, by far the largest part of a loaded chunk
+        let megabytes = chunks * chunk::CONTENTS_SIZE * size_of::<Option<Block>>() / (1 << 20);
+        warn!(
+            "Render distance covers about {chunks} chunks, roughly {megabytes} MB, more than the {} that can be loaded at once",
+            settings.max_loaded_chunks
+        );
+    }
     // Toggle visibility of block interaction ray steps for current camera position+rotation
-    if keyboard_input.just_pressed(KeyCode::KeyR) {
+    if input.just_pressed(Action::ToggleRayOverlay) {
         debug_info.show_constant_entities = !debug_info.show_constant_entities;
     }
     // Place/Destroy block
     let mut block_interaction = None;
-    if keyboard_input.just_pressed(KeyCode::KeyE) {
-        block_interaction = Some(true);
+    if input.just_pressed(Action::PlaceBlock) {
+        block_interaction = Some(BlockInteraction::Place);
     }
-    if keyboard_input.just_pressed(KeyCode::KeyQ) {
-        block_interaction = Some(false);
+    if input.just_pressed(Action::RemoveBlock) {
+        block_interaction = Some(BlockInteraction::Remove);
     }
-    // Change camera move speed
-    for event in mouse_wheel_input.read() {
-        camera_query.0.speed += event.y;
-        camera_query.0.speed = camera_query.0.speed.clamp(0., 100.);
+    if input.just_pressed(Action::PlaceColumn) {
+        block_interaction = Some(BlockInteraction::PlaceColumn);
     }
-    // Cleanup entities created when rendering block interaction ray steps
-    for entity in debug_info.constant_ray_mesh_entities.drain(..) {
-        commands.entity(entity).despawn();
+    if input.just_pressed(Action::PickBlock) || mouse_input.just_pressed(MouseButton::Middle) {
+        block_interaction = Some(BlockInteraction::Pick);
+    }
+    let brush = input.pressed(Action::Brush);
+    if brush {
+        block_interaction = match block_interaction {
+            Some(BlockInteraction::Place) => Some(BlockInteraction::BrushPlace),
+            Some(BlockInteraction::Remove) => Some(BlockInteraction::BrushRemove),
+            other => other,
+        };
     }
+    // Change camera move speed, or the brush radius while the brush is held
+    for event in mouse_wheel_input.read() {
+        if brush {
+            let step = if event.y > 0. {
+                1
+            } else if event.y < 0. {
+                -1
+            } else {
+                0
+            };
+            settings.brush_radius = (settings.brush_radius + step).clamp(0, MAX_BRUSH_RADIUS);
+        } else {
+            camera_query.0.speed += event.y;
+            camera_query.0.speed = camera_query.0.speed.clamp(0., 100.);
+        }
+    }
+    let reach = settings.reach;
+    // Reuse entities created when rendering block interaction ray steps last frame
+    debug_info.ray_overlay.reset();
+    let overlay_assets = debug_info
+        .ray_overlay_assets
+        .get_or_insert_with(|| RayOverlayAssets::new(&mut meshes, &mut materials))
+        .clone();
     if block_interaction.is_some() {
         for entity in debug_info.ray_mesh_entities.drain(..) {
             commands.entity(entity).despawn();
@@ -370,11 +1904,12 @@ fn handle_debug_input(
                         materials.add(StandardMaterial::from_color(Color::srgba(0., 1., 1., 0.75))),
                     ),
                     Transform::from_translation(camera_query.1.translation),
+                    NoWireframe,
                 ))
                 .id(),
         );
-        // Draw line of cubes from camera position/rotation indicating where the ray is expected to end
-        for i in 0..200 {
+        // Draw line of cubes from camera position/rotation up to the reach distance
+        for i in 0..(reach * 2.) as usize {
             debug_info.ray_mesh_entities.push(
                 commands
                     .spawn((
@@ -386,46 +1921,35 @@ fn handle_debug_input(
                         Transform::from_translation(
                             camera_query.1.translation + camera_query.1.forward() * (i as f32 / 2.),
                         ),
+                        NoWireframe,
                     ))
                     .id(),
             )
         }
     }
-    // Create block interaction ray
-    let mut ray = BlockRay::from_origin_in_direction(
+    // Retrace the ray cast by `update_current_target` for the overlay
+    let ray = BlockRay::from_origin_in_direction(
         camera_query.1.translation,
         camera_query.1.forward().normalize(),
     );
-    // Initialize first chunk to check for solid blocks
-    let mut chunk_position = ChunkGrid::to_chunk_coordinates(ray.position);
-    let Some(mut chunk) = level.get_chunk_grid().0.get(&chunk_position) else {
-        // Inhabited chunk not loaded so we shouldn't modifiy it
-        return;
-    };
-    // Step over block interaction ray and return a chunk position if a block was hit
-    let rebuild = loop {
-        // Draw cubes at current ray position and normal
-        let position_entity = (
-            Mesh3d(meshes.add(Cuboid::from_length(0.1))),
-            MeshMaterial3d(
-                materials.add(StandardMaterial::from_color(Color::srgba(1., 0., 1., 0.75))),
-            ),
-            Transform::from_translation(ray.position),
-        );
-        let normal_entity = (
-            Mesh3d(meshes.add(Cuboid::from_length(0.05))),
-            MeshMaterial3d(
-                materials.add(StandardMaterial::from_color(Color::srgba(0., 1., 0., 0.75))),
-            ),
-            Transform::from_translation(ray.position + ray.normal * 0.1),
-        );
+    let hit = current_target.0;
+
+    // Draw cubes at each ray step and normal up to where the ray ended
+    let end_distance = hit.map_or(reach, |hit| hit.distance);
+    let mut overlay_ray = ray;
+    while overlay_ray.distance() <= end_distance {
+        let position_entity = overlay_assets.position_cube(overlay_ray.position);
+        let normal_entity =
+            overlay_assets.normal_cube(overlay_ray.position + overlay_ray.normal * 0.1);
         if debug_info.show_constant_entities {
-            debug_info
-                .constant_ray_mesh_entities
-                .push(commands.spawn(position_entity.clone()).id());
-            debug_info
-                .constant_ray_mesh_entities
-                .push(commands.spawn(normal_entity.clone()).id());
+            if let Some(slot) = debug_info.ray_overlay.next_slot() {
+                debug_info.ray_overlay.place(
+                    &mut commands,
+                    slot,
+                    position_entity.clone(),
+                    normal_entity.clone(),
+                );
+            }
         }
         if block_interaction.is_some() {
             debug_info
@@ -435,62 +1959,209 @@ fn handle_debug_input(
                 .ray_mesh_entities
                 .push(commands.spawn(normal_entity).id());
         }
+        overlay_ray.step();
+    }
+    debug_info.ray_overlay.hide_unused(&mut commands);
 
-        // Check if the position of the current ray step is still within the chunk being checked
-        // Seperate variable used to store the variable here to avoid locking chunk rwlock
-        let ray_chunk_position = ChunkGrid::to_chunk_coordinates(ray.position);
-        if ray_chunk_position != chunk_position {
-            chunk_position = ray_chunk_position;
-            let Some(ray_chunk) = level.get_chunk_grid().0.get(&chunk_position) else {
-                // Chunk at current ray step not loaded so we cant continue checking the ray any further
-                break None;
+    let Some(hit) = hit else {
+        return;
+    };
+
+    // Set overlay positions so we can see where ray ended up
+    block_outline_query.translation = hit.block_coordinates.as_vec3() + 0.5;
+    block_outline_normal_query.translation = (hit.block_coordinates + hit.normal).as_vec3() + 0.5;
+
+    // Get the block interaction we wish to do this frame or else end here if there is none
+    let Some(block_interaction) = block_interaction else {
+        return;
+    };
+
+    let is_replaceable = |block: &Block| {
+        block_atlas_manager
+            .0
+            .properties(&block.identifier)
+            .replaceable
+    };
+    match block_interaction {
+        // Place a block at the hit position offset by the hit normal, or in place of the hit block if it is replaceable
+        BlockInteraction::Place => {
+            let target = match level.get_block(hit.block_coordinates) {
+                Some(block) if is_replaceable(&block) => hit.block_coordinates,
+                _ => hit.block_coordinates + hit.normal,
             };
-            chunk = ray_chunk;
+            if level
+                .get_block(target)
+                .is_none_or(|block| is_replaceable(&block))
+            {
+                level.set_block(target, Some(Block::new(selected_block.0.clone())));
+            }
         }
-
-        // Get index of the block at the current ray step
-        let target_block_index =
-            Chunk::to_index(Chunk::to_block_coordinates(ray.position.floor().as_ivec3()));
-        // Check if block at previously defined index is solid
-        if chunk.read().expect("Chunk rw poisoned").contents[target_block_index].is_none() {
-            ray.step();
-            continue;
+        // Breaking takes time, see `break_targeted_block`
+        BlockInteraction::Remove => {}
+        // Fill downwards from the hit position offset by the hit normal until the ground is reached
+        BlockInteraction::PlaceColumn => {
+            let column = level.get_chunk_grid().column_to_surface(
+                hit.block_coordinates + hit.normal,
+                settings.column_fill_depth,
+                &block_atlas_manager.0,
+            );
+            level.set_blocks(
+                column
+                    .into_iter()
+                    .map(|position| (position, Some(Block::new(selected_block.0.clone())))),
+            );
+        }
+        // Copy the identifier of the hit block into the block selection
+        BlockInteraction::Pick => {
+            if let Some(block) = level.get_block(hit.block_coordinates) {
+                selected_block.0 = block.identifier;
+            }
+        }
+        // Set every block in the cube at once, so each affected chunk is only rebuilt once
+        BlockInteraction::BrushPlace | BlockInteraction::BrushRemove => {
+            let block = matches!(block_interaction, BlockInteraction::BrushPlace)
+                .then(|| Block::new(selected_block.0.clone()));
+            // Placing only fills empty and replaceable blocks unless overwriting, clearing removes everything
+            let overwrite = block.is_none() || input.pressed(Action::BrushOverwrite);
+            let radius = settings.brush_radius;
+            let edits = (-radius..=radius)
+                .flat_map(|x| {
+                    (-radius..=radius)
+                        .flat_map(move |y| (-radius..=radius).map(move |z| IVec3::new(x, y, z)))
+                })
+                .map(|offset| hit.block_coordinates + offset)
+                .filter(|&position| {
+                    overwrite
+                        || level
+                            .get_block(position)
+                            .is_none_or(|block| is_replaceable(&block))
+                })
+                .map(|position| (position, block.clone()))
+                .collect::<Vec<_>>();
+            level.set_blocks(edits);
         }
+    }
+}
 
-        // Set overlay positions so we can see where ray ended up
-        block_outline_query.translation = ray.position.floor() + 0.5;
-        block_outline_normal_query.translation = ray.position.floor() + ray.normal.floor() + 0.5;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // Get the block interaction we wish to do this frame or else end the ray here if there is none
-        let Some(block_interaction) = block_interaction else {
-            break None;
+    #[test]
+    fn window_mode_follows_settings() {
+        let mut settings = WindowSettings::default();
+        assert_eq!(settings.window_mode(), WindowMode::Windowed);
+
+        settings.fullscreen = true;
+        settings.borderless = true;
+        assert_eq!(
+            settings.window_mode(),
+            WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+        );
+
+        settings.borderless = false;
+        assert_eq!(
+            settings.window_mode(),
+            WindowMode::Fullscreen(MonitorSelection::Current, VideoModeSelection::Current)
+        );
+    }
+
+    #[test]
+    fn window_carries_title_and_resolution() {
+        let settings = WindowSettings {
+            title: "Test Window".to_owned(),
+            resolution: UVec2::new(800, 600),
+            fullscreen: false,
+            borderless: false,
         };
+        let window = settings.to_window();
+        assert_eq!(window.title, "Test Window");
+        assert_eq!(window.resolution.width(), 800.0);
+        assert_eq!(window.resolution.height(), 600.0);
+        assert_eq!(window.mode, WindowMode::Windowed);
+    }
 
-        // Place a block at the ray position offset by the ray normal
-        if block_interaction {
-            // If the position ends up in a different chunk when offset by the normal load that chunk
-            let ray_chunk_position = ChunkGrid::to_chunk_coordinates(ray.position + ray.normal);
-            if ray_chunk_position != chunk_position {
-                chunk_position = ray_chunk_position;
-                let Some(ray_chunk) = level.get_chunk_grid().0.get(&chunk_position) else {
-                    // Chunk not loaded so exit early
-                    break None;
-                };
-                chunk = ray_chunk;
+    #[test]
+    fn toggling_fullscreen_twice_returns_to_windowed() {
+        let mut settings = WindowSettings::default();
+        assert_eq!(
+            settings.toggle_fullscreen(),
+            WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+        );
+        assert_eq!(settings.toggle_fullscreen(), WindowMode::Windowed);
+        assert!(!settings.fullscreen);
+    }
+
+    #[test]
+    fn ray_overlay_pool_reuses_bounded_slots() {
+        let mut world = World::new();
+        let assets = RayOverlayAssets::new(&mut Assets::default(), &mut Assets::default());
+        let mut pool = RayOverlayPool::default();
+        let mut first_frame = None;
+
+        for _ in 0..4 {
+            pool.reset();
+            let mut commands = world.commands();
+            for step in 0..RAY_OVERLAY_MAX_SAMPLES * RAY_OVERLAY_SAMPLE_RATE * 10 {
+                if let Some(slot) = pool.next_slot() {
+                    let position = Vec3::splat(step as f32);
+                    pool.place(
+                        &mut commands,
+                        slot,
+                        assets.position_cube(position),
+                        assets.normal_cube(position),
+                    );
+                }
+                assert!(pool.entities.len() <= RAY_OVERLAY_MAX_SAMPLES);
+            }
+            pool.hide_unused(&mut commands);
+            world.flush();
+
+            assert_eq!(pool.entities.len(), RAY_OVERLAY_MAX_SAMPLES);
+            match &first_frame {
+                None => first_frame = Some(pool.entities.clone()),
+                Some(entities) => assert_eq!(&pool.entities, entities),
+            }
+        }
+
+        // A shorter ray hides the slots it didn't use
+        pool.reset();
+        let mut commands = world.commands();
+        for _ in 0..RAY_OVERLAY_SAMPLE_RATE * 3 {
+            if let Some(slot) = pool.next_slot() {
+                pool.place(
+                    &mut commands,
+                    slot,
+                    assets.position_cube(Vec3::ZERO),
+                    assets.normal_cube(Vec3::ZERO),
+                );
             }
-            chunk.write().expect("Chunk rw poisoned").contents[Chunk::to_index(
-                Chunk::to_block_coordinates((ray.position + ray.normal).floor().as_ivec3()),
-            )] = Some(Block::new(Identifier::new(DEFAULT_NAMESPACE, "dirt")));
         }
-        // Remove the block at the ray position
-        else {
-            chunk.write().expect("Chunk rw poisoned").contents[target_block_index] = None;
+        pool.hide_unused(&mut commands);
+        world.flush();
+
+        assert_eq!(pool.entities.len(), RAY_OVERLAY_MAX_SAMPLES);
+        for (index, &(position, normal)) in pool.entities.iter().enumerate() {
+            let expected = if index < 3 {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
+            assert_eq!(world.get::<Visibility>(position), Some(&expected));
+            assert_eq!(world.get::<Visibility>(normal), Some(&expected));
         }
-        break Some(chunk_position);
-    };
+    }
 
-    // Rebuild modified chunk mesh (if a chunk was modified)
-    if let Some(chunk_position) = rebuild {
-        level.rebuild_mesh(chunk_position);
+    #[test]
+    fn ray_overlay_pool_hides_nothing_when_over_used() {
+        let mut world = World::new();
+        let mut pool = RayOverlayPool {
+            used: 5,
+            ..default()
+        };
+        pool.hide_unused(&mut world.commands());
+        world.flush();
+        pool.reset();
+        assert_eq!(pool.next_slot(), Some(0));
     }
 }
@@ -0,0 +1,306 @@
+use std::{fs, io};
+
+use bevy::{
+    app::{App, Plugin, Update},
+    color::Color,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::EventReader,
+        query::{Changed, With},
+        resource::Resource,
+        schedule::IntoScheduleConfigs,
+        system::{Commands, Query, Res, ResMut, Single},
+    },
+    input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput},
+    },
+    log::error,
+    state::{
+        commands::CommandsStatesExt,
+        condition::in_state,
+        state::{OnEnter, OnExit},
+    },
+    ui::{
+        AlignItems, BackgroundColor, FlexDirection, Interaction, JustifyContent, Node, UiRect, Val,
+        widget::{Button, Text},
+    },
+};
+
+use crate::{
+    GameState,
+    block::BlockAtlasManager,
+    chunk::generation::{self, GeneratorKind},
+    level::{LevelSelection, NewWorld},
+};
+
+/// Directory holding a subdirectory for every world
+const SAVE_DIRECTORY: &str = "save";
+/// Name given to new worlds, followed by a number when a world already has it
+const NEW_WORLD_NAME: &str = "world";
+/// Longest seed that can be typed into the seed field
+const MAX_SEED_LENGTH: usize = 32;
+
+const BUTTON_COLOR: Color = Color::srgb(0.2, 0.2, 0.2);
+const BUTTON_HOVERED_COLOR: Color = Color::srgb(0.3, 0.3, 0.3);
+
+/// Marks the root of the main menu's UI
+#[derive(Component)]
+struct MainMenu;
+
+/// Button loading the world with this id
+#[derive(Component)]
+struct WorldButton(String);
+
+#[derive(Component)]
+struct NewWorldButton;
+
+/// Button cycling through the generators a new world can be created with
+#[derive(Component)]
+struct GeneratorButton;
+
+/// Marks the text showing the seed typed so far
+#[derive(Component)]
+struct SeedField;
+
+/// Seed typed for the next new world
+#[derive(Resource, Default)]
+struct SeedInput(String);
+
+/// Generator the next new world is created with
+#[derive(Resource, Clone, Copy, Default, PartialEq, Eq)]
+enum GeneratorChoice {
+    #[default]
+    Normal,
+    /// Stacked layers without any noise, see [`generation::default_flat_layers`]
+    Flat,
+    /// Every registered block laid out on a floor, see [`GeneratorKind::Showcase`]
+    Showcase,
+}
+
+impl GeneratorChoice {
+    fn next(self) -> Self {
+        match self {
+            Self::Normal => Self::Flat,
+            Self::Flat => Self::Showcase,
+            Self::Showcase => Self::Normal,
+        }
+    }
+
+    fn label(self) -> String {
+        let name = match self {
+            Self::Normal => "Normal",
+            Self::Flat => "Flat",
+            Self::Showcase => "Showcase",
+        };
+        format!("Generator: {name}")
+    }
+
+    fn generator(self, block_atlas_manager: &BlockAtlasManager) -> GeneratorKind {
+        match self {
+            Self::Normal => GeneratorKind::Normal,
+            Self::Flat => GeneratorKind::Flat {
+                layers: generation::default_flat_layers(),
+            },
+            Self::Showcase => GeneratorKind::Showcase {
+                blocks: block_atlas_manager.0.identifiers().cloned().collect(),
+            },
+        }
+    }
+}
+
+pub struct MainMenuPlugin;
+
+impl Plugin for MainMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SeedInput>()
+            .init_resource::<GeneratorChoice>()
+            .add_systems(OnEnter(GameState::MainMenu), spawn_main_menu)
+            .add_systems(OnExit(GameState::MainMenu), despawn_main_menu)
+            .add_systems(
+                Update,
+                (edit_seed, handle_menu_buttons).run_if(in_state(GameState::MainMenu)),
+            );
+    }
+}
+
+/// Names of the world directories under [`SAVE_DIRECTORY`], sorted
+fn existing_worlds() -> io::Result<Vec<String>> {
+    let mut worlds = Vec::new();
+    for entry in fs::read_dir(SAVE_DIRECTORY)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            worlds.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    worlds.sort();
+    Ok(worlds)
+}
+
+/// Create the directory of a new world and return its id<br>
+/// The name is numbered until it doesn't match an existing directory, which is never reused
+fn create_world_directory() -> io::Result<String> {
+    fs::create_dir_all(SAVE_DIRECTORY)?;
+    for number in 1.. {
+        let id = match number {
+            1 => NEW_WORLD_NAME.to_owned(),
+            _ => format!("{NEW_WORLD_NAME}_{number}"),
+        };
+        // Fails instead of opening a directory that appeared since the menu was shown
+        match fs::create_dir(format!("{SAVE_DIRECTORY}/{id}")) {
+            Ok(()) => return Ok(id),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    unreachable!()
+}
+
+fn button(text: impl Into<String>) -> (Button, Node, BackgroundColor, Text) {
+    (
+        Button,
+        Node {
+            width: Val::Px(300.),
+            padding: UiRect::all(Val::Px(8.)),
+            margin: UiRect::all(Val::Px(4.)),
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        },
+        BackgroundColor(BUTTON_COLOR),
+        Text::new(text),
+    )
+}
+
+fn spawn_main_menu(mut commands: Commands, generator: Res<GeneratorChoice>) {
+    // A missing save directory just means no worlds have been created yet
+    let worlds = existing_worlds().unwrap_or_else(|error| {
+        if error.kind() != io::ErrorKind::NotFound {
+            error!("Failed to list worlds in {SAVE_DIRECTORY}: {error}");
+        }
+        Vec::new()
+    });
+    commands
+        .spawn((
+            MainMenu,
+            Node {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(Text::new("Select a world"));
+            for world in worlds {
+                parent.spawn((WorldButton(world.clone()), button(world)));
+            }
+            parent.spawn((SeedField, Text::new("Seed: ")));
+            parent.spawn((GeneratorButton, button(generator.label())));
+            parent.spawn((NewWorldButton, button("New World")));
+        });
+}
+
+fn despawn_main_menu(mut commands: Commands, menu_query: Query<Entity, With<MainMenu>>) {
+    for entity in menu_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Type into the seed field, Backspace removes the last character
+fn edit_seed(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut seed: ResMut<SeedInput>,
+    seed_field: Single<&mut Text, With<SeedField>>,
+) {
+    let mut changed = false;
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Backspace => {
+                seed.0.pop();
+            }
+            Key::Character(characters) => {
+                for character in characters
+                    .chars()
+                    .filter(|character| !character.is_control())
+                {
+                    if seed.0.chars().count() < MAX_SEED_LENGTH {
+                        seed.0.push(character);
+                    }
+                }
+            }
+            Key::Space if seed.0.chars().count() < MAX_SEED_LENGTH => seed.0.push(' '),
+            _ => continue,
+        }
+        changed = true;
+    }
+    if changed {
+        seed_field.into_inner().0 = format!("Seed: {}", seed.0);
+    }
+}
+
+fn handle_menu_buttons(
+    mut commands: Commands,
+    seed: Res<SeedInput>,
+    mut generator: ResMut<GeneratorChoice>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    mut button_query: Query<
+        (
+            &Interaction,
+            &mut BackgroundColor,
+            &mut Text,
+            Option<&WorldButton>,
+            Option<&NewWorldButton>,
+            Option<&GeneratorButton>,
+        ),
+        Changed<Interaction>,
+    >,
+) {
+    for (interaction, mut color, mut text, world_button, new_world_button, generator_button) in
+        button_query.iter_mut()
+    {
+        color.0 = match interaction {
+            Interaction::Hovered | Interaction::Pressed => BUTTON_HOVERED_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        };
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if generator_button.is_some() {
+            *generator = generator.next();
+            text.0 = generator.label();
+            continue;
+        }
+        let selection = if let Some(WorldButton(id)) = world_button {
+            LevelSelection {
+                id: id.clone(),
+                new_world: None,
+            }
+        } else if new_world_button.is_some() {
+            let id = match create_world_directory() {
+                Ok(id) => id,
+                Err(error) => {
+                    error!("Failed to create a directory for the new world: {error}");
+                    continue;
+                }
+            };
+            LevelSelection {
+                id,
+                new_world: Some(NewWorld {
+                    seed: seed.0.clone(),
+                    generator: generator.generator(&block_atlas_manager),
+                }),
+            }
+        } else {
+            continue;
+        };
+        commands.insert_resource(selection);
+        commands.set_state(GameState::InGame);
+        return;
+    }
+}
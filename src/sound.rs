@@ -0,0 +1,136 @@
+use std::{fs, io, path::Path};
+
+use bevy::{
+    asset::{AssetServer, Handle, io::file::FileAssetReader},
+    audio::{AudioPlayer, AudioSource, PlaybackSettings},
+    log::error,
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+use bevy_asset_loader::asset_collection::AssetCollection;
+
+use crate::{
+    GameState, Identifier,
+    block::BlockAtlasManager,
+    level::{BlockPlaced, BlockRemoved},
+};
+
+/// Folder inside each namespace's asset folder holding its block sounds
+pub const SOUND_FOLDER: &str = "sounds";
+/// Block sounds playing at once, sounds beyond this are dropped until one finishes
+const MAX_BLOCK_SOUNDS: usize = 8;
+/// Removal sounds are played slower so they can be told apart from placing
+const REMOVE_SOUND_SPEED: f32 = 0.8;
+
+/// Sound groups are found by [`find_block_sounds`]
+#[derive(AssetCollection, Resource)]
+pub struct SoundAssets {
+    /// Played for blocks without a sound group, or whose group has no sound file
+    #[asset(path = "builtin/sounds/click.wav")]
+    pub click: Handle<AudioSource>,
+}
+
+/// Sound of each sound group, see [`AtlasManager::set_sound_group`](crate::atlas::AtlasManager::set_sound_group)
+#[derive(Resource, Default)]
+struct BlockSounds(HashMap<Identifier, Handle<AudioSource>>);
+
+/// Marks an entity playing a block sound
+#[derive(Component)]
+struct BlockSound;
+
+pub struct BlockSoundPlugin;
+
+impl Plugin for BlockSoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockSounds>()
+            .add_systems(OnEnter(GameState::AssetLoading), load_block_sounds)
+            .add_systems(
+                Update,
+                play_block_sounds.run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// Scan `assets/<namespace>/sounds/*.wav`, each file's stem becoming the path of a sound group in `<namespace>`<br>
+/// Returns each group with its path relative to the assets folder
+pub fn find_block_sounds(assets_directory: &Path) -> io::Result<Vec<(Identifier, String)>> {
+    let mut sounds = Vec::new();
+    for namespace_entry in fs::read_dir(assets_directory)? {
+        let namespace_entry = namespace_entry?;
+        let Some(namespace) = namespace_entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        let sounds_directory = namespace_entry.path().join(SOUND_FOLDER);
+        if !sounds_directory.is_dir() {
+            continue;
+        }
+        for sound_entry in fs::read_dir(sounds_directory)? {
+            let path = sound_entry?.path();
+            if path.extension().is_none_or(|extension| extension != "wav") {
+                continue;
+            }
+            if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                sounds.push((
+                    Identifier::new(&namespace, stem),
+                    format!("{namespace}/{SOUND_FOLDER}/{stem}.wav"),
+                ));
+            }
+        }
+    }
+    Ok(sounds)
+}
+
+fn load_block_sounds(asset_server: Res<AssetServer>, mut block_sounds: ResMut<BlockSounds>) {
+    let assets_directory = FileAssetReader::get_base_path().join("assets");
+    let sounds = find_block_sounds(&assets_directory).unwrap_or_else(|error| {
+        error!(
+            "Failed to read block sounds from {}: {error}",
+            assets_directory.display()
+        );
+        Vec::new()
+    });
+    block_sounds.0 = sounds
+        .into_iter()
+        .map(|(sound_group, asset_path)| (sound_group, asset_server.load(asset_path)))
+        .collect();
+}
+
+/// Each sound is played at most once a frame, so editing many blocks at once doesn't play a sound per block
+fn play_block_sounds(
+    mut commands: Commands,
+    mut placed_events: EventReader<BlockPlaced>,
+    mut removed_events: EventReader<BlockRemoved>,
+    sound_assets: Res<SoundAssets>,
+    block_sounds: Res<BlockSounds>,
+    block_atlas_manager: Res<BlockAtlasManager>,
+    playing_query: Query<(), With<BlockSound>>,
+) {
+    let sound = |block: &Identifier| {
+        block_atlas_manager
+            .0
+            .sound_group(block)
+            .and_then(|sound_group| block_sounds.0.get(sound_group))
+            .unwrap_or(&sound_assets.click)
+            .clone()
+    };
+    let mut played = HashSet::new();
+    let sounds = removed_events
+        .read()
+        .map(|event| (sound(&event.block), REMOVE_SOUND_SPEED))
+        .chain(placed_events.read().map(|event| (sound(&event.block), 1.)))
+        .filter(|(handle, speed)| played.insert((handle.id(), speed.to_bits())))
+        .collect::<Vec<_>>();
+
+    let mut playing = playing_query.iter().count();
+    for (handle, speed) in sounds {
+        if playing >= MAX_BLOCK_SOUNDS {
+            break;
+        }
+        commands.spawn((
+            BlockSound,
+            AudioPlayer(handle),
+            PlaybackSettings::DESPAWN.with_speed(speed),
+        ));
+        playing += 1;
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::{
+    input::{
+        ButtonState, InputSystem,
+        keyboard::{Key, KeyboardInput},
+        mouse::{AccumulatedMouseMotion, MouseButton, MouseWheel},
+    },
+    log::{info, warn},
+    prelude::*,
+    window::PrimaryWindow,
+};
+
+use crate::{
+    GameState, Hud,
+    camera_control::{MovableCamera, PlayerPhysics},
+    grab_cursor,
+    input::{Action, ActionInput},
+};
+
+/// Longest text that can be typed into the prompt
+const MAX_PROMPT_LENGTH: usize = 64;
+
+/// Coordinates typed so far, only present while the teleport prompt is open
+#[derive(Resource, Default)]
+struct TeleportPrompt(String);
+
+/// Marks the text showing the teleport prompt
+#[derive(Component)]
+struct TeleportPromptText;
+
+pub struct TeleportPlugin;
+
+impl Plugin for TeleportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            block_input
+                .after(InputSystem)
+                .run_if(resource_exists::<TeleportPrompt>),
+        )
+        .add_systems(
+            Update,
+            update_teleport_prompt.run_if(in_state(GameState::InGame)),
+        )
+        .add_systems(OnExit(GameState::InGame), close_teleport_prompt);
+    }
+}
+
+/// Hide keyboard and mouse input from every other system while typing, the prompt reads [`KeyboardInput`] events instead
+fn block_input(
+    mut keyboard_input: ResMut<ButtonInput<KeyCode>>,
+    mut mouse_input: ResMut<ButtonInput<MouseButton>>,
+    mut mouse_motion: ResMut<AccumulatedMouseMotion>,
+    mut mouse_wheel_events: ResMut<Events<MouseWheel>>,
+) {
+    keyboard_input.reset_all();
+    mouse_input.reset_all();
+    mouse_motion.delta = Vec2::ZERO;
+    mouse_wheel_events.clear();
+}
+
+fn open_teleport_prompt(commands: &mut Commands, window: &mut Window) {
+    commands.init_resource::<TeleportPrompt>();
+    grab_cursor(window, false);
+    commands.spawn((
+        TeleportPromptText,
+        Hud,
+        Text::new(prompt_text("")),
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.),
+            left: Val::Px(5.),
+            ..Default::default()
+        },
+    ));
+}
+
+fn prompt_text(typed: &str) -> String {
+    format!("Teleport to (x y z): {typed}_\n[Enter]: Teleport\n[Esc]: Cancel")
+}
+
+/// Open the prompt with [`Action::Teleport`], then Enter teleports to the typed coordinates and Escape cancels
+fn update_teleport_prompt(
+    mut commands: Commands,
+    input: ActionInput,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    prompt: Option<ResMut<TeleportPrompt>>,
+    mut prompt_text_query: Query<&mut Text, With<TeleportPromptText>>,
+    camera_query: Single<(
+        &mut Transform,
+        &mut MovableCamera,
+        Option<&mut PlayerPhysics>,
+    )>,
+    window_query: Single<&mut Window, With<PrimaryWindow>>,
+) {
+    let Some(mut prompt) = prompt else {
+        // Keys typed before the prompt opened, including the one opening it, aren't typed into it
+        keyboard_events.clear();
+        if input.just_pressed(Action::Teleport) {
+            open_teleport_prompt(&mut commands, &mut window_query.into_inner());
+        }
+        return;
+    };
+    let mut changed = false;
+    let mut close = false;
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Enter => {
+                let (mut transform, mut camera, physics) = camera_query.into_inner();
+                match parse_coordinates(&prompt.0) {
+                    Some(position) => {
+                        info!("Teleported to {position}");
+                        transform.translation = position;
+                        // Arrive standing still rather than carrying the speed from before
+                        camera.velocity = Vec3::ZERO;
+                        if let Some(mut physics) = physics {
+                            physics.velocity = Vec3::ZERO;
+                        }
+                    }
+                    None => warn!("Ignored teleport to \"{}\", expected x y z", prompt.0),
+                }
+                close = true;
+                break;
+            }
+            Key::Escape => {
+                close = true;
+                break;
+            }
+            Key::Backspace => {
+                prompt.0.pop();
+            }
+            Key::Character(characters) => {
+                for character in characters
+                    .chars()
+                    .filter(|character| !character.is_control())
+                {
+                    if prompt.0.chars().count() < MAX_PROMPT_LENGTH {
+                        prompt.0.push(character);
+                    }
+                }
+            }
+            Key::Space if prompt.0.chars().count() < MAX_PROMPT_LENGTH => prompt.0.push(' '),
+            _ => continue,
+        }
+        changed = true;
+    }
+    if close {
+        commands.remove_resource::<TeleportPrompt>();
+        commands.run_system_cached(despawn_prompt_text);
+        grab_cursor(&mut window_query.into_inner(), true);
+        return;
+    }
+    if !changed {
+        return;
+    }
+    for mut text in prompt_text_query.iter_mut() {
+        text.0 = prompt_text(&prompt.0);
+    }
+}
+
+/// Three finite numbers separated by whitespace and optionally commas, `None` for anything else
+fn parse_coordinates(text: &str) -> Option<Vec3> {
+    let mut coordinates = text
+        .split(|character: char| character.is_whitespace() || character == ',')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<f32>().ok().filter(|value| value.is_finite()));
+    let position = Vec3::new(
+        coordinates.next()??,
+        coordinates.next()??,
+        coordinates.next()??,
+    );
+    coordinates.next().is_none().then_some(position)
+}
+
+/// Leaving the game, such as to the pause menu, cancels the prompt
+fn close_teleport_prompt(mut commands: Commands) {
+    commands.remove_resource::<TeleportPrompt>();
+    commands.run_system_cached(despawn_prompt_text);
+}
+
+fn despawn_prompt_text(mut commands: Commands, query: Query<Entity, With<TeleportPromptText>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}